@@ -0,0 +1,18 @@
+//! Builds the gallery's manifest: geocodes the places in manifest.yaml, reads where and when every photo
+//! was taken from the gallery, and writes the map layers and `Manifest.elm` the site is compiled from.
+
+#[macro_use]
+extern crate serde;
+
+#[macro_use]
+extern crate lazy_static;
+
+pub use manifest::{
+    great_circle, simplify, split_antimeridian, watch, Config, Country, DeployTarget, ExportFormat,
+    GalleryImage, Location, Manifest, Position, Profile, Project, Taken, TrackPoint,
+};
+pub use serve::{serve, LiveReload};
+
+mod manifest;
+mod serve;
+mod utils;
@@ -1,26 +1,199 @@
-#![recursion_limit = "1024"]
-
-#[macro_use]
-extern crate serde;
+use anyhow::Result;
+use clap::{Parser, Subcommand};
+use manifester::{
+    serve, watch, Config, DeployTarget, ExportFormat, LiveReload, Manifest, Profile, Project,
+};
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
 
-#[macro_use]
-extern crate lazy_static;
+const MANIFEST_YAML: &str = include_str!("world/manifest.yaml");
+/// Where the built in manifest is read from, for watching it.
+const MANIFEST_PATH: &str = "src/world/manifest.yaml";
 
-use anyhow::Result;
-use manifest::{LocationInformation, construct_manifest};
+#[derive(Parser)]
+#[command(
+    version,
+    about = "Builds the gallery manifest, map data and Manifest.elm"
+)]
+struct Cli {
+    /// Build from this manifest.yaml instead of the one built in.
+    #[arg(long, global = true, env = "MANIFESTER_MANIFEST")]
+    manifest: Option<PathBuf>,
+    #[command(subcommand)]
+    command: Option<Commands>,
+}
 
-mod manifest;
-mod utils;
+#[derive(Subcommand)]
+enum Commands {
+    /// Geocode places and write cities.json, trips.json and Manifest.elm. This is the default.
+    Manifest,
+    /// Propose trips from photo capture times and locations, and check existing trips against them.
+    SuggestTrips {
+        /// Days without photos that end a trip.
+        #[arg(long, default_value_t = 3)]
+        gap: i64,
+        /// Locations that are home rather than travel, may be repeated.
+        #[arg(long)]
+        home: Vec<String>,
+    },
+    /// Geocode new places and places whose pin changed into cities.json.
+    Geocode {
+        /// Instead list the best matches for each place with their distance from cities.json, to pin the right one.
+        #[arg(long)]
+        review: bool,
+        /// Matches listed per place when reviewing.
+        #[arg(long, default_value_t = 5)]
+        candidates: usize,
+        /// Places to review, by name or id. Defaults to every place.
+        locations: Vec<String>,
+    },
+    /// Build the manifest, compile Elm and post-process the assets into dist.
+    Build {
+        #[arg(long, value_enum, default_value_t = Profile::Release)]
+        profile: Profile,
+    },
+    /// Upload what changed in dist to the S3 compatible bucket in the manifest, or release it into
+    /// the manifest's deploy directory.
+    Deploy {
+        /// Needed when the manifest deploys to both.
+        #[arg(long, value_enum)]
+        target: Option<DeployTarget>,
+        /// List the changes without making them.
+        #[arg(long)]
+        dry_run: bool,
+        /// Delete objects that are no longer in dist from the bucket.
+        #[arg(long)]
+        delete: bool,
+    },
+    /// Point the deploy directory's current symlink back at an earlier release.
+    Rollback {
+        /// Defaults to the release before the current one.
+        release: Option<String>,
+    },
+    /// Write the places and trips from cities.json and trips.json as KML or GPX.
+    Export {
+        #[arg(long, value_enum, default_value_t = ExportFormat::Kml)]
+        format: ExportFormat,
+        /// Defaults to travels.kml or travels.gpx.
+        #[arg(long)]
+        output: Option<PathBuf>,
+    },
+    /// Build, then rebuild whenever photos, their sidecars or the manifest change.
+    Watch {
+        /// Milliseconds a change has to settle before rebuilding.
+        #[arg(long, default_value_t = 500)]
+        debounce: u64,
+    },
+    /// Serve dist locally, with unknown paths routed to the Elm application.
+    Serve {
+        #[arg(long, default_value = "127.0.0.1")]
+        host: String,
+        #[arg(long, default_value_t = 8000)]
+        port: u16,
+        /// Also watch like the watch subcommand, and reload open pages after every rebuild.
+        #[arg(long)]
+        watch: bool,
+        /// Milliseconds a change has to settle before rebuilding when watching.
+        #[arg(long, default_value_t = 500)]
+        debounce: u64,
+        /// Make thumbnails and blurs that are missing when they are asked for.
+        #[arg(long)]
+        derivatives: bool,
+    },
+}
 
 fn main() -> Result<()> {
+    let cli = Cli::parse();
+
     rayon::ThreadPoolBuilder::new()
         .num_threads(num_cpus::get_physical())
         .build_global()?;
 
-    let locations_information = LocationInformation::new("cities.json", "trips.json")?;
+    let manifest_path = cli
+        .manifest
+        .clone()
+        .unwrap_or_else(|| PathBuf::from(MANIFEST_PATH));
+    match cli.command {
+        Some(Commands::Watch { debounce }) => {
+            return watch(
+                &manifest_path,
+                Config::default(),
+                Duration::from_millis(debounce),
+                || {},
+            );
+        }
+        Some(Commands::Serve {
+            host,
+            port,
+            watch: watching,
+            debounce,
+            derivatives,
+        }) => {
+            let config = Config::default();
+            let dist = config.dist.clone();
+            let live_reload = watching.then(|| Arc::new(LiveReload::default()));
+
+            if let Some(live_reload) = &live_reload {
+                let live_reload = Arc::clone(live_reload);
+                thread::spawn(move || {
+                    let watching = watch(
+                        &manifest_path,
+                        config,
+                        Duration::from_millis(debounce),
+                        || live_reload.reload(),
+                    );
+                    if let Err(error) = watching {
+                        eprintln!("Stopped watching: {:#}", error);
+                    }
+                });
+            }
+            return serve(
+                &dist,
+                &format!("{}:{}", host, port),
+                derivatives,
+                live_reload,
+            );
+        }
+        _ => {}
+    }
 
-    construct_manifest("../src/Manifest.elm", &locations_information)?;
+    let manifest = match &cli.manifest {
+        Some(path) => Manifest::read(path)?,
+        None => Manifest::load(MANIFEST_YAML)?,
+    };
+    let project = Project::new(manifest, Config::default())?;
 
-    println!("World and Manifest builds complete.");
-    Ok(())
+    match cli.command.unwrap_or(Commands::Manifest) {
+        Commands::Manifest => {
+            project.build()?;
+            println!("World and Manifest builds complete.");
+            Ok(())
+        }
+        Commands::Build { profile } => project.build_site(profile),
+        Commands::Deploy {
+            target,
+            dry_run,
+            delete,
+        } => project.deploy(target, dry_run, delete),
+        Commands::Rollback { release } => project.rollback(release.as_deref()),
+        Commands::SuggestTrips { gap, home } => project.suggest_trips(gap, &home),
+        Commands::Geocode {
+            review: true,
+            candidates,
+            locations,
+        } => project.review(candidates, &locations),
+        Commands::Geocode { .. } => project.geocode(),
+        Commands::Export { format, output } => {
+            let output = output.unwrap_or_else(|| match format {
+                ExportFormat::Kml => PathBuf::from("travels.kml"),
+                ExportFormat::Gpx => PathBuf::from("travels.gpx"),
+            });
+            project.export(format, &output)
+        }
+        Commands::Watch { .. } | Commands::Serve { .. } => {
+            unreachable!("watch and serve load the manifest themselves")
+        }
+    }
 }
@@ -0,0 +1,436 @@
+use anyhow::{anyhow, bail, Result};
+use serde_json::{json, Value};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs::{self, File};
+use std::path::{Path, PathBuf};
+
+use super::map::{Coordinates, Feature};
+use super::route::Position;
+use super::track::{simplify, TrackPoint};
+
+/// Where to find country outlines and how much detail to keep of them.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Boundaries {
+    /// Admin-0 polygons, either a shapefile with its .dbf next to it or GeoJSON, such as Natural Earth's
+    /// countries, relative to the repository.
+    pub source: PathBuf,
+    /// Attribute holding the cca3 code, otherwise ADM0_A3, ISO_A3, ISO_A3_EH and cca3 are tried in turn.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub code_field: Option<String>,
+    /// Metres a simplified border may stray from the source.
+    #[serde(default = "default_tolerance")]
+    pub tolerance: f64,
+    /// Also write a TopoJSON copy of the layer.
+    #[serde(default)]
+    pub topojson: bool,
+}
+
+fn default_tolerance() -> f64 {
+    1000.0
+}
+
+const CODE_FIELDS: [&str; 7] = [
+    "ADM0_A3",
+    "ISO_A3",
+    "ISO_A3_EH",
+    "adm0_a3",
+    "iso_a3",
+    "iso_a3_eh",
+    "cca3",
+];
+
+/// Closed rings, the first is the outline and the rest are holes.
+pub type Polygon = Vec<Vec<Position>>;
+
+/// Attributes and polygons of one record in the source.
+type Shape = (BTreeMap<String, String>, Vec<Polygon>);
+
+/// Simplified outlines of the countries in `codes`, by cca3 code. Outlines run clockwise and holes
+/// counterclockwise, the winding d3 expects.
+pub fn read_boundaries(
+    boundaries: &Boundaries,
+    codes: &BTreeSet<String>,
+) -> Result<BTreeMap<String, Vec<Polygon>>> {
+    let source = &boundaries.source;
+    let extension = source
+        .extension()
+        .and_then(|e| e.to_str())
+        .map(|e| e.to_lowercase());
+
+    let shapes = match extension.as_deref() {
+        Some("shp") => read_shapefile(source)?,
+        Some("geojson") | Some("json") => read_geojson(source)?,
+        _ => bail!("{} is not a shapefile or GeoJSON", source.display()),
+    };
+
+    let fields = match &boundaries.code_field {
+        Some(field) => vec![field.as_str()],
+        None => CODE_FIELDS.to_vec(),
+    };
+
+    let mut countries: BTreeMap<String, Vec<Polygon>> = BTreeMap::new();
+    for (attributes, polygons) in shapes {
+        // Natural Earth leaves ISO_A3 at -99 for a few countries, so take the first field that is wanted.
+        let code = fields
+            .iter()
+            .filter_map(|field| attributes.get(*field))
+            .find(|code| codes.contains(*code));
+        if let Some(code) = code {
+            countries.entry(code.clone()).or_default().extend(polygons);
+        }
+    }
+
+    for polygons in countries.values_mut() {
+        let simplified = polygons
+            .iter()
+            .filter_map(|polygon| simplify_polygon(polygon, boundaries.tolerance))
+            .collect::<Vec<Polygon>>();
+        // Keep small island nations visible even if the tolerance would erase them.
+        if !simplified.is_empty() {
+            *polygons = simplified;
+        }
+        for polygon in polygons.iter_mut() {
+            orient(polygon);
+        }
+    }
+
+    Ok(countries)
+}
+
+fn simplify_polygon(polygon: &Polygon, tolerance: f64) -> Option<Polygon> {
+    let mut rings = polygon.iter().map(|ring| {
+        let points = ring
+            .iter()
+            .map(|&position| TrackPoint {
+                position,
+                elevation: None,
+            })
+            .collect::<Vec<TrackPoint>>();
+        simplify(&points, tolerance)
+    });
+
+    let outline = rings.next().filter(|ring| ring.len() >= 4)?;
+    Some(
+        std::iter::once(outline)
+            .chain(rings.filter(|ring| ring.len() >= 4))
+            .collect(),
+    )
+}
+
+fn orient(polygon: &mut Polygon) {
+    for (idx, ring) in polygon.iter_mut().enumerate() {
+        let clockwise = signed_area(ring) < 0.0;
+        if clockwise != (idx == 0) {
+            ring.reverse();
+        }
+    }
+}
+
+/// Twice the area enclosed by a ring, negative when it runs clockwise.
+fn signed_area(ring: &[Position]) -> f64 {
+    ring.windows(2)
+        .map(|pair| pair[0].0 * pair[1].1 - pair[1].0 * pair[0].1)
+        .sum()
+}
+
+fn contains(ring: &[Position], (x, y): Position) -> bool {
+    let mut inside = false;
+    for pair in ring.windows(2) {
+        let ((x1, y1), (x2, y2)) = (pair[0], pair[1]);
+        if (y1 > y) != (y2 > y) && x < x1 + (y - y1) * (x2 - x1) / (y2 - y1) {
+            inside = !inside;
+        }
+    }
+    inside
+}
+
+fn read_geojson(path: &Path) -> Result<Vec<Shape>> {
+    let geojson: Value = serde_json::from_reader(File::open(path)?)?;
+
+    let features = geojson["features"].as_array().ok_or(anyhow!(
+        "{} is not a GeoJSON FeatureCollection",
+        path.display()
+    ))?;
+
+    Ok(features
+        .iter()
+        .map(|feature| {
+            let attributes = feature["properties"]
+                .as_object()
+                .map(|properties| {
+                    properties
+                        .iter()
+                        .map(|(key, value)| match value {
+                            Value::String(s) => (key.clone(), s.clone()),
+                            other => (key.clone(), other.to_string()),
+                        })
+                        .collect()
+                })
+                .unwrap_or_default();
+
+            let geometry = &feature["geometry"];
+            let polygons = match geometry["type"].as_str() {
+                Some("Polygon") => vec![geojson_polygon(&geometry["coordinates"])],
+                Some("MultiPolygon") => geometry["coordinates"]
+                    .as_array()
+                    .map(|polygons| polygons.iter().map(geojson_polygon).collect())
+                    .unwrap_or_default(),
+                _ => Vec::new(),
+            };
+
+            (attributes, polygons)
+        })
+        .collect())
+}
+
+fn geojson_polygon(rings: &Value) -> Polygon {
+    rings
+        .as_array()
+        .map(|rings| {
+            rings
+                .iter()
+                .filter_map(|ring| ring.as_array())
+                .map(|ring| {
+                    ring.iter()
+                        .filter_map(|position| {
+                            Some((position.get(0)?.as_f64()?, position.get(1)?.as_f64()?))
+                        })
+                        .collect::<Vec<Position>>()
+                })
+                .filter(|ring| ring.len() >= 4)
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// Polygons from a .shp file, with their attributes from the .dbf file of the same name.
+fn read_shapefile(path: &Path) -> Result<Vec<Shape>> {
+    let shp = fs::read(path)?;
+    let mut attributes = read_dbf(&path.with_extension("dbf"))?.into_iter();
+
+    if i32::from_be_bytes(bytes(&shp, 0)?) != 9994 {
+        bail!("{} is not a shapefile", path.display());
+    }
+
+    let mut shapes: Vec<Shape> = Vec::new();
+    let mut offset = 100;
+    while offset + 8 <= shp.len() {
+        // Record lengths are counted in 16 bit words.
+        let length = i32::from_be_bytes(bytes(&shp, offset + 4)?) as usize * 2;
+        let content = shp
+            .get(offset + 8..offset + 8 + length)
+            .ok_or(anyhow!("{} is truncated", path.display()))?;
+        offset += 8 + length;
+
+        // Records deleted from the .dbf are deleted from the layer.
+        match attributes.next() {
+            Some(None) => continue,
+            Some(Some(attributes)) => shapes.push((attributes, shp_polygons(content)?)),
+            None => shapes.push((BTreeMap::new(), shp_polygons(content)?)),
+        }
+    }
+
+    Ok(shapes)
+}
+
+fn shp_polygons(content: &[u8]) -> Result<Vec<Polygon>> {
+    match i32::from_le_bytes(bytes(content, 0)?) {
+        0 => return Ok(Vec::new()),
+        5 | 15 | 25 => {}
+        other => bail!(
+            "Boundaries must be polygons, the shapefile has shape type {}",
+            other
+        ),
+    }
+
+    let parts = i32::from_le_bytes(bytes(content, 36)?) as usize;
+    let points = i32::from_le_bytes(bytes(content, 40)?) as usize;
+    let starts = (0..parts)
+        .map(|part| Ok(i32::from_le_bytes(bytes(content, 44 + 4 * part)?) as usize))
+        .collect::<Result<Vec<usize>>>()?;
+    let base = 44 + 4 * parts;
+
+    let mut rings: Vec<Vec<Position>> = Vec::new();
+    for (idx, start) in starts.iter().enumerate() {
+        let end = starts.get(idx + 1).copied().unwrap_or(points);
+        rings.push(
+            (*start..end)
+                .map(|point| {
+                    let at = base + 16 * point;
+                    Ok((
+                        f64::from_le_bytes(bytes(content, at)?),
+                        f64::from_le_bytes(bytes(content, at + 8)?),
+                    ))
+                })
+                .collect::<Result<Vec<Position>>>()?,
+        );
+    }
+
+    Ok(assemble(rings))
+}
+
+/// Shapefiles list the rings of a record without grouping them: clockwise rings are outlines and
+/// counterclockwise ones are holes in the outline around them.
+fn assemble(rings: Vec<Vec<Position>>) -> Vec<Polygon> {
+    let (outlines, holes): (Vec<_>, Vec<_>) = rings
+        .into_iter()
+        .filter(|ring| ring.len() >= 4)
+        .partition(|ring| signed_area(ring) <= 0.0);
+
+    let mut polygons: Vec<Polygon> = outlines.into_iter().map(|ring| vec![ring]).collect();
+    for hole in holes {
+        match polygons
+            .iter_mut()
+            .find(|polygon| contains(&polygon[0], hole[0]))
+        {
+            Some(polygon) => polygon.push(hole),
+            // Outside every outline it is an outline wound the wrong way.
+            None => polygons.push(vec![hole]),
+        }
+    }
+
+    polygons
+}
+
+/// The attributes of every record in a .dbf file, `None` for the ones flagged as deleted.
+fn read_dbf(path: &Path) -> Result<Vec<Option<BTreeMap<String, String>>>> {
+    let dbf = fs::read(path)?;
+    let truncated = || anyhow!("{} is truncated", path.display());
+
+    let records = u32::from_le_bytes(bytes(&dbf, 4)?) as usize;
+    let header = u16::from_le_bytes(bytes(&dbf, 8)?) as usize;
+    let record = u16::from_le_bytes(bytes(&dbf, 10)?) as usize;
+
+    let mut fields: Vec<(String, usize)> = Vec::new();
+    let mut descriptor = 32;
+    while descriptor + 32 <= header {
+        let field = dbf.get(descriptor..descriptor + 32).ok_or_else(truncated)?;
+        if field[0] == 0x0D {
+            break;
+        }
+        let name = field[..11]
+            .iter()
+            .take_while(|b| **b != 0)
+            .map(|b| *b as char)
+            .collect::<String>();
+        fields.push((name, field[16] as usize));
+        descriptor += 32;
+    }
+
+    (0..records)
+        .map(|idx| {
+            let start = header + idx * record;
+            let data = dbf.get(start..start + record).ok_or_else(truncated)?;
+            // The first byte flags deleted records.
+            if data.first() == Some(&b'*') {
+                return Ok(None);
+            }
+            let mut at = 1;
+            let mut attributes = BTreeMap::new();
+            for (name, length) in &fields {
+                let value = data.get(at..at + length).ok_or_else(truncated)?;
+                attributes.insert(
+                    name.clone(),
+                    String::from_utf8_lossy(value).trim().to_string(),
+                );
+                at += length;
+            }
+            Ok(Some(attributes))
+        })
+        .collect()
+}
+
+fn bytes<const N: usize>(data: &[u8], at: usize) -> Result<[u8; N]> {
+    data.get(at..at + N)
+        .and_then(|b| b.try_into().ok())
+        .ok_or(anyhow!("Shapefile is truncated"))
+}
+
+/// Quantisation grid of the TopoJSON layer.
+const QUANTISATION: f64 = 1e5;
+
+/// TopoJSON with the same features as `features`, quantised and delta encoded. Every ring is its own arc,
+/// shared borders aren't merged. There is no layer without a single position to bound it.
+pub fn to_topojson(features: &[Feature]) -> Option<Value> {
+    let positions = features
+        .iter()
+        .filter_map(|feature| match &feature.geometry.coordinates {
+            Coordinates::MultiPolygon(polygons) => Some(polygons),
+            _ => None,
+        })
+        .flatten()
+        .flatten()
+        .flatten();
+
+    let (mut x0, mut y0, mut x1, mut y1) = (f64::MAX, f64::MAX, f64::MIN, f64::MIN);
+    let mut bounded = false;
+    for position in positions {
+        let (x, y) = (position[0] as f64, position[1] as f64);
+        (x0, y0, x1, y1) = (x0.min(x), y0.min(y), x1.max(x), y1.max(y));
+        bounded = true;
+    }
+    if !bounded {
+        return None;
+    }
+    let scale = (
+        ((x1 - x0) / (QUANTISATION - 1.0)).max(f64::EPSILON),
+        ((y1 - y0) / (QUANTISATION - 1.0)).max(f64::EPSILON),
+    );
+
+    let mut arcs: Vec<Vec<[i64; 2]>> = Vec::new();
+    let mut geometries: Vec<Value> = Vec::new();
+    for feature in features {
+        let Coordinates::MultiPolygon(polygons) = &feature.geometry.coordinates else {
+            continue;
+        };
+
+        let mut polygon_arcs: Vec<Vec<Vec<usize>>> = Vec::new();
+        for polygon in polygons {
+            let mut ring_arcs: Vec<Vec<usize>> = Vec::new();
+            for ring in polygon {
+                let mut arc: Vec<[i64; 2]> = Vec::new();
+                let mut previous: Option<[i64; 2]> = None;
+                for position in ring {
+                    let point = [
+                        ((position[0] as f64 - x0) / scale.0).round() as i64,
+                        ((position[1] as f64 - y0) / scale.1).round() as i64,
+                    ];
+                    match previous {
+                        Some(previous) if previous == point => continue,
+                        Some(previous) => {
+                            arc.push([point[0] - previous[0], point[1] - previous[1]])
+                        }
+                        None => arc.push(point),
+                    }
+                    previous = Some(point);
+                }
+                ring_arcs.push(vec![arcs.len()]);
+                arcs.push(arc);
+            }
+            polygon_arcs.push(ring_arcs);
+        }
+
+        geometries.push(json!({
+            "type": "MultiPolygon",
+            "id": feature.properties.id,
+            "properties": feature.properties,
+            "arcs": polygon_arcs,
+        }));
+    }
+
+    Some(json!({
+        "type": "Topology",
+        "bbox": [x0, y0, x1, y1],
+        "transform": {
+            "scale": [scale.0, scale.1],
+            "translate": [x0, y0],
+        },
+        "objects": {
+            "countries": {
+                "type": "GeometryCollection",
+                "geometries": geometries,
+            },
+        },
+        "arcs": arcs,
+    }))
+}
@@ -0,0 +1,223 @@
+use anyhow::Result;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use rayon::prelude::*;
+use std::collections::BTreeSet;
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+/// manifest.yaml `build: compress:`, which files in dist get `.gz` and `.br` siblings for a static
+/// host to pick up. Images are compressed already and left out.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Compress {
+    #[serde(default = "default_extensions")]
+    pub extensions: Vec<String>,
+    #[serde(default = "default_true")]
+    pub gzip: bool,
+    #[serde(default = "default_true")]
+    pub brotli: bool,
+}
+
+impl Default for Compress {
+    fn default() -> Self {
+        Compress {
+            extensions: default_extensions(),
+            gzip: true,
+            brotli: true,
+        }
+    }
+}
+
+fn default_extensions() -> Vec<String> {
+    [
+        "html",
+        "js",
+        "css",
+        "json",
+        "geojson",
+        "topojson",
+        "webmanifest",
+        "svg",
+        "xml",
+    ]
+    .iter()
+    .map(|extension| extension.to_string())
+    .collect()
+}
+
+fn default_true() -> bool {
+    true
+}
+
+#[derive(Clone, Copy)]
+enum Encoding {
+    Gzip,
+    Brotli,
+}
+
+impl Encoding {
+    fn extension(self) -> &'static str {
+        match self {
+            Encoding::Gzip => "gz",
+            Encoding::Brotli => "br",
+        }
+    }
+
+    fn encode(self, content: &[u8]) -> io::Result<Vec<u8>> {
+        match self {
+            Encoding::Gzip => {
+                let mut encoder = GzEncoder::new(Vec::new(), Compression::best());
+                encoder.write_all(content)?;
+                encoder.finish()
+            }
+            Encoding::Brotli => {
+                let mut encoded = Vec::new();
+                {
+                    let mut encoder = brotli::CompressorWriter::new(&mut encoded, 4096, 11, 22);
+                    encoder.write_all(content)?;
+                }
+                Ok(encoded)
+            }
+        }
+    }
+}
+
+/// `path` with `.gz` or `.br` added to its name.
+fn sibling(path: &Path, encoding: Encoding) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(".");
+    name.push(encoding.extension());
+    PathBuf::from(name)
+}
+
+/// Lists the siblings in dist that were not worth writing because they came out no smaller than their
+/// file, so an unchanged file isn't compressed again on every build.
+pub const SKIPPED: &str = ".compress-skipped";
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+impl Compress {
+    fn enabled(&self, encoding: Encoding) -> bool {
+        match encoding {
+            Encoding::Gzip => self.gzip,
+            Encoding::Brotli => self.brotli,
+        }
+    }
+
+    /// Brings the compressed siblings of the files in `dist` up to date. A sibling is only kept if it
+    /// is smaller than its file, is rewritten once the file is newer than it, and is removed along with
+    /// its file or its encoding being turned off. The ones that didn't shrink are listed in
+    /// [`SKIPPED`] and only tried again once their file changes. Returns how many siblings were written.
+    pub fn apply(&self, dist: &Path) -> Result<usize> {
+        let stamp = dist.join(SKIPPED);
+        if self.extensions.is_empty() {
+            if stamp.exists() {
+                fs::remove_file(&stamp)?;
+            }
+            return Ok(0);
+        }
+        let extensions = self.extensions.join(",");
+
+        let stamped = modified(&stamp);
+        let skipped = match fs::read_to_string(&stamp) {
+            Ok(skipped) => skipped
+                .lines()
+                .map(|relative| dist.join(relative))
+                .collect::<BTreeSet<_>>(),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => BTreeSet::new(),
+            Err(e) => return Err(e.into()),
+        };
+
+        let files =
+            globwalk::GlobWalkerBuilder::from_patterns(dist, &[format!("*.{{{}}}", extensions)])
+                .build()?
+                .filter_map(|entry| entry.ok())
+                .filter(|entry| entry.file_type().is_file())
+                .map(|entry| entry.into_path())
+                .collect::<Vec<_>>();
+
+        let results = files
+            .par_iter()
+            .map(|file| -> Result<(usize, Vec<PathBuf>)> {
+                let mut content = None;
+                let mut written = 0;
+                let mut still_skipped = Vec::new();
+
+                for encoding in [Encoding::Gzip, Encoding::Brotli] {
+                    let compressed = sibling(file, encoding);
+                    if !self.enabled(encoding) {
+                        if compressed.exists() {
+                            fs::remove_file(&compressed)?;
+                        }
+                        continue;
+                    }
+                    if modified(&compressed) >= modified(file) {
+                        continue;
+                    }
+                    if skipped.contains(&compressed) && stamped >= modified(file) {
+                        still_skipped.push(compressed);
+                        continue;
+                    }
+
+                    let content = match &content {
+                        Some(content) => content,
+                        None => content.insert(fs::read(file)?),
+                    };
+                    let encoded = encoding.encode(content)?;
+                    if encoded.len() < content.len() {
+                        fs::write(&compressed, encoded)?;
+                        written += 1;
+                    } else {
+                        if compressed.exists() {
+                            fs::remove_file(&compressed)?;
+                        }
+                        still_skipped.push(compressed);
+                    }
+                }
+                Ok((written, still_skipped))
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        let written = results.iter().map(|(written, _)| written).sum();
+        let mut still_skipped = results
+            .into_iter()
+            .flat_map(|(_, skipped)| skipped)
+            .map(|sibling| {
+                let relative = sibling.strip_prefix(dist)?;
+                Ok(relative
+                    .iter()
+                    .map(|part| part.to_string_lossy())
+                    .collect::<Vec<_>>()
+                    .join("/"))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        still_skipped.sort();
+        if still_skipped.is_empty() {
+            if stamp.exists() {
+                fs::remove_file(&stamp)?;
+            }
+        } else {
+            // Rewritten every time, so the files it lists are checked against when they were last tried.
+            fs::write(&stamp, still_skipped.join("\n") + "\n")?;
+        }
+
+        // Siblings of files that are gone, such as assets fingerprinted with an older hash.
+        let orphans = globwalk::GlobWalkerBuilder::from_patterns(
+            dist,
+            &[format!("*.{{{}}}.{{gz,br}}", extensions)],
+        )
+        .build()?
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| !entry.path().with_extension("").exists())
+        .map(|entry| entry.into_path());
+        for orphan in orphans {
+            fs::remove_file(orphan)?;
+        }
+
+        Ok(written)
+    }
+}
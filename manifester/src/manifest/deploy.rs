@@ -0,0 +1,353 @@
+use anyhow::{anyhow, bail, Result};
+use md5::{Digest, Md5};
+use rayon::prelude::*;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs::{self, File};
+use std::path::{Path, PathBuf};
+
+use super::compress;
+use super::directory::{release, rollback, Directory};
+use super::s3::{Bucket, S3};
+use super::Project;
+use crate::utils::mime_type;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum DeployTarget {
+    /// The S3 compatible bucket under `deploy: s3:`.
+    S3,
+    /// The web root under `deploy: directory:`.
+    Directory,
+}
+
+/// manifest.yaml `deploy:`, where the built site is published and how it is cached there.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Deploy {
+    pub s3: Option<S3>,
+    pub directory: Option<Directory>,
+    /// Globs of files in dist that are never published, relative to it.
+    #[serde(default = "default_exclude")]
+    pub exclude: Vec<String>,
+    #[serde(default)]
+    pub cache_control: CacheControl,
+}
+
+impl Default for Deploy {
+    fn default() -> Self {
+        Deploy {
+            s3: None,
+            directory: None,
+            exclude: default_exclude(),
+            cache_control: CacheControl::default(),
+        }
+    }
+}
+
+fn default_exclude() -> Vec<String> {
+    vec![
+        "**/*.desc".to_string(),
+        "**/*.trip".to_string(),
+        compress::SKIPPED.to_string(),
+    ]
+}
+
+/// The `Cache-Control` of each class of file.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CacheControl {
+    /// Fingerprinted assets, whose name changes with their content.
+    #[serde(default = "default_immutable")]
+    pub immutable: String,
+    /// Pages and data, which keep their URL from one build to the next.
+    #[serde(default = "default_revalidate")]
+    pub revalidate: String,
+    /// Photos, their derivatives and icons.
+    #[serde(default = "default_images")]
+    pub images: String,
+    #[serde(default = "default_other")]
+    pub other: String,
+}
+
+impl Default for CacheControl {
+    fn default() -> Self {
+        CacheControl {
+            immutable: default_immutable(),
+            revalidate: default_revalidate(),
+            images: default_images(),
+            other: default_other(),
+        }
+    }
+}
+
+fn default_immutable() -> String {
+    "public, max-age=31536000, immutable".to_string()
+}
+
+fn default_revalidate() -> String {
+    "no-cache".to_string()
+}
+
+fn default_images() -> String {
+    "public, max-age=604800".to_string()
+}
+
+fn default_other() -> String {
+    "public, max-age=86400".to_string()
+}
+
+/// Extensions of the files that are fetched at the same URL whatever the build.
+const REVALIDATED: [&str; 7] = [
+    "html",
+    "json",
+    "geojson",
+    "topojson",
+    "webmanifest",
+    "xml",
+    "txt",
+];
+
+/// The files in `dist` that are published, by their path relative to it with `/` separators.
+pub fn site_files(dist: &Path, exclude: &[String]) -> Result<BTreeMap<String, PathBuf>> {
+    let mut patterns = vec!["**".to_string()];
+    patterns.extend(exclude.iter().map(|glob| format!("!{}", glob)));
+
+    let mut files = BTreeMap::new();
+    for entry in globwalk::GlobWalkerBuilder::from_patterns(dist, &patterns)
+        .build()?
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| entry.file_type().is_file())
+    {
+        let name = entry
+            .path()
+            .strip_prefix(dist)?
+            .iter()
+            .map(|part| {
+                part.to_str()
+                    .ok_or(anyhow!("{} is not valid UTF-8", entry.path().display()))
+            })
+            .collect::<Result<Vec<_>>>()?
+            .join("/");
+        files.insert(name, entry.into_path());
+    }
+    Ok(files)
+}
+
+/// A file as it is uploaded.
+struct Object<'a> {
+    name: &'a str,
+    /// The file itself, or its gzip sibling.
+    body: &'a Path,
+    gzip: bool,
+    md5: String,
+    content_type: &'static str,
+    cache_control: &'a str,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Change {
+    Added,
+    Changed,
+    Removed,
+}
+
+impl Change {
+    fn symbol(self) -> char {
+        match self {
+            Change::Added => '+',
+            Change::Changed => '~',
+            Change::Removed => '-',
+        }
+    }
+}
+
+/// Publishes dist to `target`, which may be left out when the manifest only has the one.
+pub fn deploy(
+    project: &Project,
+    target: Option<DeployTarget>,
+    dry_run: bool,
+    delete: bool,
+) -> Result<()> {
+    let deploy = &project.manifest.deploy;
+    let target = match (target, &deploy.s3, &deploy.directory) {
+        (Some(target), _, _) => target,
+        (None, Some(_), None) => DeployTarget::S3,
+        (None, None, Some(_)) => DeployTarget::Directory,
+        (None, Some(_), Some(_)) => {
+            bail!("The manifest deploys to both s3 and a directory, pick one with --target")
+        }
+        (None, None, None) => {
+            bail!("The manifest has no deploy: s3: bucket or directory: to deploy to")
+        }
+    };
+
+    match target {
+        DeployTarget::S3 => {
+            let s3 = deploy.s3.as_ref().ok_or(anyhow!(
+                "The manifest has no deploy: s3: bucket to deploy to"
+            ))?;
+            sync(project, s3, dry_run, delete)
+        }
+        DeployTarget::Directory => {
+            let directory = deploy.directory.as_ref().ok_or(anyhow!(
+                "The manifest has no deploy: directory: to deploy to"
+            ))?;
+            let files = site_files(&project.config.dist, &deploy.exclude)?;
+            release(directory, &files, dry_run)
+        }
+    }
+}
+
+/// Switches the directory deploy back to an earlier release, see `rollback`.
+pub fn roll_back(project: &Project, to: Option<&str>) -> Result<()> {
+    let directory = project.manifest.deploy.directory.as_ref().ok_or(anyhow!(
+        "The manifest has no deploy: directory: to roll back"
+    ))?;
+    rollback(directory, to)
+}
+
+/// Publishes dist to the configured bucket. Files whose content matches the object already there
+/// are left alone, the rest are uploaded with the `Content-Type`, `Content-Encoding` and
+/// `Cache-Control` they are served with. Assets go up before the pages that refer to them.
+///
+/// With `delete`, objects under the prefix that are no longer in dist are removed. With `dry_run`,
+/// the changes are only listed.
+fn sync(project: &Project, s3: &S3, dry_run: bool, delete: bool) -> Result<()> {
+    let deploy = &project.manifest.deploy;
+    let dist = &project.config.dist;
+
+    let files = site_files(dist, &deploy.exclude)?;
+    let fingerprinted = fingerprinted(dist, &project.manifest.build.fingerprint.manifest)?;
+    let bucket = Bucket::connect(s3)?;
+    let remote = bucket.list()?;
+
+    let objects = files
+        .par_iter()
+        .filter(|(name, _)| !is_sibling(name, &files))
+        .map(|(name, path)| {
+            let gzip = files.get(&format!("{}.gz", name));
+            let body = gzip.unwrap_or(path);
+            let cache_control = if fingerprinted.contains(name.as_str()) {
+                &deploy.cache_control.immutable
+            } else if revalidated(path) {
+                &deploy.cache_control.revalidate
+            } else if mime_type(path).starts_with("image/") {
+                &deploy.cache_control.images
+            } else {
+                &deploy.cache_control.other
+            };
+
+            let mut md5 = Md5::new();
+            std::io::copy(&mut File::open(body)?, &mut md5)?;
+            Ok(Object {
+                name,
+                body,
+                gzip: gzip.is_some(),
+                md5: hex::encode(md5.finalize()),
+                content_type: mime_type(path),
+                cache_control,
+            })
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    let mut changes = Vec::new();
+    let mut unchanged = 0;
+    for object in &objects {
+        match remote.get(&bucket.key(object.name)) {
+            None => changes.push((Change::Added, object)),
+            Some(etag) if *etag != object.md5 => changes.push((Change::Changed, object)),
+            Some(_) => unchanged += 1,
+        }
+    }
+    let published = objects
+        .iter()
+        .map(|object| bucket.key(object.name))
+        .collect::<BTreeSet<_>>();
+    let removed = remote
+        .keys()
+        .filter(|key| !published.contains(*key))
+        .collect::<Vec<_>>();
+
+    changes.sort_by_key(|(change, object)| (object.name, *change));
+    for (change, object) in &changes {
+        println!("{} {}", change.symbol(), object.name);
+    }
+    if delete {
+        for key in &removed {
+            println!("{} {}", Change::Removed.symbol(), key);
+        }
+    }
+
+    if dry_run {
+        println!(
+            "Dry run: {} to upload, {} to delete, {} unchanged.",
+            changes.len(),
+            if delete { removed.len() } else { 0 },
+            unchanged
+        );
+        return Ok(());
+    }
+
+    let (pages, assets): (Vec<_>, Vec<_>) = changes
+        .iter()
+        .map(|(_, object)| *object)
+        .partition(|object| revalidated(Path::new(object.name)));
+    for batch in [assets, pages] {
+        batch
+            .par_iter()
+            .map(|object| upload(&bucket, object))
+            .collect::<Result<Vec<_>>>()?;
+    }
+    if delete {
+        removed
+            .par_iter()
+            .map(|key| bucket.delete(key))
+            .collect::<Result<Vec<_>>>()?;
+    }
+
+    println!(
+        "{} uploaded, {} deleted, {} unchanged.",
+        changes.len(),
+        if delete { removed.len() } else { 0 },
+        unchanged
+    );
+    if !delete && !removed.is_empty() {
+        println!(
+            "{} objects are no longer in dist, --delete removes them.",
+            removed.len()
+        );
+    }
+    Ok(())
+}
+
+fn upload(bucket: &Bucket, object: &Object) -> Result<()> {
+    let mut headers = vec![
+        ("Content-Type", object.content_type),
+        ("Cache-Control", object.cache_control),
+    ];
+    if object.gzip {
+        headers.push(("Content-Encoding", "gzip"));
+    }
+    bucket.put(&bucket.key(object.name), fs::read(object.body)?, &headers)
+}
+
+/// Whether `name` is the `.gz` or `.br` sibling of another file, which is uploaded in its place
+/// rather than beside it.
+fn is_sibling(name: &str, files: &BTreeMap<String, PathBuf>) -> bool {
+    [".gz", ".br"].iter().any(|extension| {
+        name.strip_suffix(extension)
+            .is_some_and(|original| files.contains_key(original))
+    })
+}
+
+fn revalidated(path: &Path) -> bool {
+    path.extension()
+        .and_then(|extension| extension.to_str())
+        .is_some_and(|extension| REVALIDATED.contains(&extension.to_lowercase().as_str()))
+}
+
+/// The hashed names in the asset manifest, if the build fingerprinted anything.
+fn fingerprinted(dist: &Path, manifest: &Path) -> Result<BTreeSet<String>> {
+    let assets: BTreeMap<String, String> = match File::open(dist.join(manifest)) {
+        Ok(file) => serde_json::from_reader(file)?,
+        Err(_) => BTreeMap::new(),
+    };
+    Ok(assets.into_values().collect())
+}
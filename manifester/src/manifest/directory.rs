@@ -0,0 +1,303 @@
+use anyhow::{anyhow, bail, Result};
+use chrono::Utc;
+use rayon::prelude::*;
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+const RELEASES: &str = "releases";
+const CURRENT: &str = "current";
+
+/// manifest.yaml `deploy: directory:`, a web root served through its `current` symlink. Every deploy
+/// is a new directory under `releases`, which `current` is switched to once it is complete.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Directory {
+    /// Relative to the repository, unless absolute.
+    pub root: PathBuf,
+    /// Releases kept for rolling back to, the newest ones. The current release is always kept.
+    #[serde(default = "default_keep")]
+    pub keep: usize,
+}
+
+fn default_keep() -> usize {
+    5
+}
+
+#[cfg(unix)]
+fn symlink(target: &Path, link: &Path) -> io::Result<()> {
+    std::os::unix::fs::symlink(target, link)
+}
+
+#[cfg(windows)]
+fn symlink(target: &Path, link: &Path) -> io::Result<()> {
+    std::os::windows::fs::symlink_dir(target, link)
+}
+
+fn sha256(path: &Path) -> Result<String> {
+    let mut hasher = Sha256::new();
+    io::copy(&mut File::open(path)?, &mut hasher)?;
+    Ok(hex::encode(hasher.finalize()))
+}
+
+impl Directory {
+    fn releases(&self) -> PathBuf {
+        self.root.join(RELEASES)
+    }
+
+    /// The checksums of every file of `release`, written next to it rather than in it so the site
+    /// doesn't serve them.
+    fn checksums_path(&self, release: &str) -> PathBuf {
+        self.releases().join(format!("{}.sha256", release))
+    }
+
+    /// The finished releases, oldest first. Staging directories of unfinished ones start with a dot.
+    fn list(&self) -> Result<Vec<String>> {
+        let entries = match fs::read_dir(self.releases()) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(e.into()),
+        };
+
+        let mut releases = Vec::new();
+        for entry in entries {
+            let entry = entry?;
+            if !entry.file_type()?.is_dir() {
+                continue;
+            }
+            match entry.file_name().to_str() {
+                Some(name) if !name.starts_with('.') => releases.push(name.to_string()),
+                _ => {}
+            }
+        }
+        releases.sort();
+        Ok(releases)
+    }
+
+    /// The release `current` points at, if any.
+    fn current(&self) -> Option<String> {
+        let target = fs::read_link(self.root.join(CURRENT)).ok()?;
+        Some(target.file_name()?.to_str()?.to_string())
+    }
+
+    /// Points `current` at `release` by renaming a new symlink over the old one, so the web root is
+    /// never without it.
+    fn switch(&self, release: &str) -> Result<()> {
+        let current = self.root.join(CURRENT);
+        if let Ok(metadata) = fs::symlink_metadata(&current) {
+            if !metadata.file_type().is_symlink() {
+                bail!(
+                    "{} is not a symlink, move it out of the way to deploy releases",
+                    current.display()
+                );
+            }
+        }
+
+        let link = self.root.join(format!(".{}", CURRENT));
+        let _ = fs::remove_file(&link);
+        symlink(&Path::new(RELEASES).join(release), &link)?;
+        fs::rename(&link, &current)?;
+        println!("{} -> {}/{}", CURRENT, RELEASES, release);
+        Ok(())
+    }
+
+    fn read_checksums(&self, release: &str) -> Result<BTreeMap<String, String>> {
+        let path = self.checksums_path(release);
+        let text = fs::read_to_string(&path)
+            .map_err(|e| anyhow!("Can't read the checksums {}: {}", path.display(), e))?;
+        text.lines()
+            .map(|line| match line.split_once("  ") {
+                Some((hash, name)) => Ok((name.to_string(), hash.to_string())),
+                None => bail!("{} has a malformed line: {}", path.display(), line),
+            })
+            .collect()
+    }
+
+    fn write_checksums(&self, release: &str, checksums: &BTreeMap<String, String>) -> Result<()> {
+        let mut file = File::create(self.checksums_path(release))?;
+        for (name, hash) in checksums {
+            writeln!(file, "{}  {}", hash, name)?;
+        }
+        Ok(())
+    }
+
+    /// The files of `dir` that don't match `checksums`, or are missing.
+    fn mismatches(dir: &Path, checksums: &BTreeMap<String, String>) -> Vec<String> {
+        let mut mismatches = checksums
+            .par_iter()
+            .filter(|(name, hash)| sha256(&dir.join(name)).ok().as_ref() != Some(*hash))
+            .map(|(name, _)| name.clone())
+            .collect::<Vec<_>>();
+        mismatches.sort();
+        mismatches
+    }
+
+    /// Removes all but the newest `keep` releases and the current one, along with what is left of
+    /// releases that never finished. Returns the names of the releases removed.
+    ///
+    /// `pending` counts releases not made yet, as in a dry run, which are newer than the listed ones
+    /// and take the place of the current one.
+    fn prune(&self, pending: usize, dry_run: bool) -> Result<Vec<String>> {
+        let releases = self.list()?;
+        let current = if pending == 0 { self.current() } else { None };
+        let old = releases
+            .iter()
+            .take((releases.len() + pending).saturating_sub(self.keep.max(1)))
+            .filter(|release| Some(*release) != current.as_ref())
+            .cloned()
+            .collect::<Vec<_>>();
+        if dry_run {
+            return Ok(old);
+        }
+
+        for release in &old {
+            fs::remove_dir_all(self.releases().join(release))?;
+            let _ = fs::remove_file(self.checksums_path(release));
+        }
+        for entry in fs::read_dir(self.releases())? {
+            let entry = entry?;
+            if entry.file_name().to_string_lossy().starts_with('.') {
+                fs::remove_dir_all(entry.path())?;
+            }
+        }
+        Ok(old)
+    }
+}
+
+/// Copies `files`, paths relative to dist, into a new release and switches `current` to it.
+///
+/// The copy is made in a staging directory and checked against the checksums of the originals
+/// before it becomes a release, so `current` only ever points at a complete one. Files the current
+/// release has unchanged are hard linked from it rather than copied again.
+pub fn release(
+    directory: &Directory,
+    files: &BTreeMap<String, PathBuf>,
+    dry_run: bool,
+) -> Result<()> {
+    let releases = directory.releases();
+    let current = directory.current();
+    let previous = match &current {
+        Some(current) => directory.read_checksums(current).unwrap_or_default(),
+        None => BTreeMap::new(),
+    };
+
+    let stamp = Utc::now().format("%Y%m%d-%H%M%S").to_string();
+    let mut name = stamp.clone();
+    for n in 2.. {
+        if !releases.join(&name).exists() {
+            break;
+        }
+        name = format!("{}-{}", stamp, n);
+    }
+
+    let checksums = files
+        .par_iter()
+        .map(|(file, path)| Ok((file.clone(), sha256(path)?)))
+        .collect::<Result<BTreeMap<_, _>>>()?;
+    let unchanged = checksums
+        .iter()
+        .filter(|(file, hash)| previous.get(*file) == Some(*hash))
+        .count();
+
+    if dry_run {
+        println!(
+            "Dry run: {} files would be released as {}, {} of them unchanged.",
+            checksums.len(),
+            name,
+            unchanged
+        );
+        for release in directory.prune(1, true)? {
+            println!("- {}", release);
+        }
+        return Ok(());
+    }
+
+    let staging = releases.join(format!(".{}", name));
+    fs::create_dir_all(&staging)?;
+    checksums
+        .par_iter()
+        .map(|(file, hash)| -> Result<()> {
+            let to = staging.join(file);
+            if let Some(parent) = to.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            let linked = match &current {
+                Some(current) if previous.get(file) == Some(hash) => {
+                    fs::hard_link(releases.join(current).join(file), &to).is_ok()
+                }
+                _ => false,
+            };
+            if !linked {
+                fs::copy(&files[file], &to)?;
+            }
+            Ok(())
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    let mismatches = Directory::mismatches(&staging, &checksums);
+    if !mismatches.is_empty() {
+        fs::remove_dir_all(&staging)?;
+        bail!(
+            "{} files don't match their checksums once copied: {}",
+            mismatches.len(),
+            mismatches.join(", ")
+        );
+    }
+    directory.write_checksums(&name, &checksums)?;
+    fs::rename(&staging, releases.join(&name))?;
+    println!(
+        "Released {} files as {}, {} of them unchanged and linked.",
+        checksums.len(),
+        name,
+        unchanged
+    );
+
+    directory.switch(&name)?;
+    for release in directory.prune(0, false)? {
+        println!("- {}", release);
+    }
+    Ok(())
+}
+
+/// Switches `current` back to `to`, or else to the release before it, once the release is checked
+/// against its checksums.
+pub fn rollback(directory: &Directory, to: Option<&str>) -> Result<()> {
+    let releases = directory.list()?;
+    let current = directory.current();
+
+    let release = match to {
+        Some(to) if releases.iter().any(|release| release == to) => to.to_string(),
+        Some(to) => bail!(
+            "There is no release {} in {}",
+            to,
+            directory.releases().display()
+        ),
+        None => {
+            let before = match &current {
+                Some(current) => releases
+                    .iter()
+                    .take_while(|release| *release != current)
+                    .last(),
+                None => releases.last(),
+            };
+            before
+                .ok_or(anyhow!("There is no release before the current one"))?
+                .clone()
+        }
+    };
+
+    let mismatches = Directory::mismatches(
+        &directory.releases().join(&release),
+        &directory.read_checksums(&release)?,
+    );
+    if !mismatches.is_empty() {
+        bail!(
+            "Release {} has {} files that don't match their checksums: {}",
+            release,
+            mismatches.len(),
+            mismatches.join(", ")
+        );
+    }
+    directory.switch(&release)
+}
@@ -0,0 +1,263 @@
+use anyhow::{anyhow, Result};
+use quick_xml::escape::escape;
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+use super::map::{Coordinates, Feature, FeatureCollection};
+
+#[derive(Debug, Clone, Copy, clap::ValueEnum)]
+pub enum ExportFormat {
+    /// Google Earth, with a folder per country and per trip.
+    Kml,
+    /// GPS devices, with a waypoint per city and a route per trip.
+    Gpx,
+}
+
+/// Writes the places in cities.json and the trips in trips.json as KML or GPX.
+pub fn export<P1, P2, P3>(
+    format: ExportFormat,
+    codes: &BTreeMap<String, String>,
+    cities_json: P1,
+    trips_json: P2,
+    out: P3,
+) -> Result<()>
+where
+    P1: AsRef<Path>,
+    P2: AsRef<Path>,
+    P3: AsRef<Path>,
+{
+    let cities: FeatureCollection = serde_json::from_reader(File::open(&cities_json)?)?;
+    let trips: FeatureCollection = serde_json::from_reader(File::open(&trips_json)?)?;
+
+    let mut writer = BufWriter::new(File::create(&out)?);
+    match format {
+        ExportFormat::Kml => write_kml(&mut writer, codes, &cities, &trips)?,
+        ExportFormat::Gpx => write_gpx(&mut writer, codes, &cities, &trips)?,
+    }
+    writer.flush()?;
+
+    println!(
+        "Exported {} places and {} trips to {}.",
+        cities.features.len(),
+        trips.features.len(),
+        out.as_ref().display()
+    );
+    Ok(())
+}
+
+fn write_kml<W: Write>(
+    out: &mut W,
+    codes: &BTreeMap<String, String>,
+    cities: &FeatureCollection,
+    trips: &FeatureCollection,
+) -> Result<()> {
+    writeln!(out, r#"<?xml version="1.0" encoding="UTF-8"?>"#)?;
+    writeln!(out, r#"<kml xmlns="http://www.opengis.net/kml/2.2">"#)?;
+    writeln!(out, "<Document>")?;
+    writeln!(out, "  <name>Places and trips</name>")?;
+
+    writeln!(out, "  <Folder>")?;
+    writeln!(out, "    <name>Places</name>")?;
+    let mut countries = cities
+        .features
+        .iter()
+        .filter_map(|f| f.properties.country.as_deref())
+        .collect::<Vec<&str>>();
+    countries.sort_unstable();
+    countries.dedup();
+    for code in countries {
+        writeln!(out, "    <Folder>")?;
+        writeln!(
+            out,
+            "      <name>{}</name>",
+            escape(country_name(codes, code))
+        )?;
+        for city in cities
+            .features
+            .iter()
+            .filter(|f| f.properties.country.as_deref() == Some(code))
+        {
+            writeln!(out, "      <Placemark>")?;
+            writeln!(
+                out,
+                "        <name>{}</name>",
+                escape(&city.properties.name)
+            )?;
+            if let Some(local) = &city.properties.localname {
+                writeln!(out, "        <description>{}</description>", escape(local))?;
+            }
+            write_kml_geometry(out, city, "        ")?;
+            writeln!(out, "      </Placemark>")?;
+        }
+        writeln!(out, "    </Folder>")?;
+    }
+    writeln!(out, "  </Folder>")?;
+
+    writeln!(out, "  <Folder>")?;
+    writeln!(out, "    <name>Trips</name>")?;
+    for trip in &trips.features {
+        writeln!(out, "    <Folder>")?;
+        writeln!(out, "      <name>{}</name>", escape(&trip.properties.name))?;
+        writeln!(out, "      <Placemark>")?;
+        writeln!(
+            out,
+            "        <name>{}</name>",
+            escape(&trip.properties.name)
+        )?;
+        if let Some(id) = &trip.properties.id {
+            writeln!(out, "        <description>{}</description>", escape(id))?;
+        }
+        write_kml_geometry(out, trip, "        ")?;
+        writeln!(out, "      </Placemark>")?;
+        writeln!(out, "    </Folder>")?;
+    }
+    writeln!(out, "  </Folder>")?;
+
+    writeln!(out, "</Document>")?;
+    writeln!(out, "</kml>")?;
+    Ok(())
+}
+
+fn write_kml_geometry<W: Write>(out: &mut W, feature: &Feature, indent: &str) -> Result<()> {
+    match &feature.geometry.coordinates {
+        Coordinates::Point(point) => {
+            writeln!(
+                out,
+                "{}<Point><coordinates>{}</coordinates></Point>",
+                indent,
+                kml_coordinates(std::slice::from_ref(point))
+            )?;
+        }
+        Coordinates::LineString(line) => {
+            writeln!(
+                out,
+                "{}<LineString><tessellate>1</tessellate><coordinates>{}</coordinates></LineString>",
+                indent,
+                kml_coordinates(line)
+            )?;
+        }
+        Coordinates::MultiLineString(lines) => {
+            writeln!(out, "{}<MultiGeometry>", indent)?;
+            for line in lines {
+                writeln!(
+                    out,
+                    "{}  <LineString><tessellate>1</tessellate><coordinates>{}</coordinates></LineString>",
+                    indent,
+                    kml_coordinates(line)
+                )?;
+            }
+            writeln!(out, "{}</MultiGeometry>", indent)?;
+        }
+        Coordinates::MultiPolygon(polygons) => {
+            writeln!(out, "{}<MultiGeometry>", indent)?;
+            for polygon in polygons {
+                writeln!(out, "{}  <Polygon>", indent)?;
+                for (idx, ring) in polygon.iter().enumerate() {
+                    let boundary = if idx == 0 { "outer" } else { "inner" };
+                    writeln!(
+                        out,
+                        "{}    <{}BoundaryIs><LinearRing><coordinates>{}</coordinates></LinearRing></{}BoundaryIs>",
+                        indent,
+                        boundary,
+                        kml_coordinates(ring),
+                        boundary
+                    )?;
+                }
+                writeln!(out, "{}  </Polygon>", indent)?;
+            }
+            writeln!(out, "{}</MultiGeometry>", indent)?;
+        }
+    }
+    Ok(())
+}
+
+fn kml_coordinates(positions: &[Vec<f32>]) -> String {
+    positions
+        .iter()
+        .filter(|p| p.len() >= 2)
+        .map(|p| format!("{},{}", p[0], p[1]))
+        .collect::<Vec<String>>()
+        .join(" ")
+}
+
+fn write_gpx<W: Write>(
+    out: &mut W,
+    codes: &BTreeMap<String, String>,
+    cities: &FeatureCollection,
+    trips: &FeatureCollection,
+) -> Result<()> {
+    writeln!(out, r#"<?xml version="1.0" encoding="UTF-8"?>"#)?;
+    writeln!(
+        out,
+        r#"<gpx version="1.1" creator="{}" xmlns="http://www.topografix.com/GPX/1/1">"#,
+        env!("CARGO_PKG_NAME")
+    )?;
+
+    for city in &cities.features {
+        if let Coordinates::Point(point) = &city.geometry.coordinates {
+            let (lon, lat) = lon_lat(point)?;
+            writeln!(out, r#"  <wpt lat="{}" lon="{}">"#, lat, lon)?;
+            writeln!(out, "    <name>{}</name>", escape(&city.properties.name))?;
+            if let Some(local) = &city.properties.localname {
+                writeln!(out, "    <desc>{}</desc>", escape(local))?;
+            }
+            if let Some(code) = &city.properties.country {
+                writeln!(
+                    out,
+                    "    <type>{}</type>",
+                    escape(country_name(codes, code))
+                )?;
+            }
+            writeln!(out, "  </wpt>")?;
+        }
+    }
+
+    for trip in &trips.features {
+        let lines = match &trip.geometry.coordinates {
+            Coordinates::Point(point) => vec![vec![point.clone()]],
+            Coordinates::LineString(line) => vec![line.clone()],
+            Coordinates::MultiLineString(lines) => lines.clone(),
+            Coordinates::MultiPolygon(polygons) => polygons.iter().flatten().cloned().collect(),
+        };
+
+        // GPX routes are a single run of points, so a trip split at the antimeridian becomes several.
+        for (idx, line) in lines.iter().enumerate() {
+            writeln!(out, "  <rte>")?;
+            if lines.len() > 1 {
+                writeln!(
+                    out,
+                    "    <name>{} ({})</name>",
+                    escape(&trip.properties.name),
+                    idx + 1
+                )?;
+            } else {
+                writeln!(out, "    <name>{}</name>", escape(&trip.properties.name))?;
+            }
+            for point in line {
+                let (lon, lat) = lon_lat(point)?;
+                writeln!(out, r#"    <rtept lat="{}" lon="{}"/>"#, lat, lon)?;
+            }
+            writeln!(out, "  </rte>")?;
+        }
+    }
+
+    writeln!(out, "</gpx>")?;
+    Ok(())
+}
+
+fn lon_lat(point: &[f32]) -> Result<(f32, f32)> {
+    match point {
+        [lon, lat, ..] => Ok((*lon, *lat)),
+        _ => Err(anyhow!("{:?} is not a longitude and latitude", point)),
+    }
+}
+
+fn country_name(codes: &BTreeMap<String, String>, code: &str) -> String {
+    codes
+        .iter()
+        .find(|(_, alpha3)| *alpha3 == code)
+        .map(|(name, _)| name.to_string())
+        .unwrap_or_else(|| code.to_string())
+}
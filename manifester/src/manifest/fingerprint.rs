@@ -0,0 +1,187 @@
+use anyhow::{anyhow, bail, Result};
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+/// manifest.yaml `build: fingerprint:`, which assets get content hashed names and which pages refer to
+/// them. Paths are relative to dist.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Fingerprint {
+    /// Globs of the assets to rename.
+    #[serde(default = "default_assets")]
+    pub assets: Vec<String>,
+    /// Pages rendered from `templates` into dist, with their references to the assets rewritten.
+    #[serde(default = "default_pages")]
+    pub pages: Vec<PathBuf>,
+    /// Where the pages are kept with the plain asset names, relative to the repository. The copies in
+    /// dist are written by every build.
+    #[serde(default = "default_templates")]
+    pub templates: PathBuf,
+    /// Where the original name of every asset is mapped to its hashed one.
+    #[serde(default = "default_manifest")]
+    pub manifest: PathBuf,
+}
+
+impl Default for Fingerprint {
+    fn default() -> Self {
+        Fingerprint {
+            assets: default_assets(),
+            pages: default_pages(),
+            templates: default_templates(),
+            manifest: default_manifest(),
+        }
+    }
+}
+
+fn default_assets() -> Vec<String> {
+    vec![
+        "assets/js/initprism.min.js".to_string(),
+        "assets/css/gallery.css".to_string(),
+    ]
+}
+
+fn default_pages() -> Vec<PathBuf> {
+    ["index.html", "404.html", "site.webmanifest"]
+        .iter()
+        .map(PathBuf::from)
+        .collect()
+}
+
+fn default_templates() -> PathBuf {
+    PathBuf::from("src/pages")
+}
+
+fn default_manifest() -> PathBuf {
+    PathBuf::from("asset-manifest.json")
+}
+
+/// Characters that continue a path, so a reference only matches where none of them surround it.
+fn continues_path(c: char) -> bool {
+    c.is_alphanumeric() || matches!(c, '_' | '-' | '.')
+}
+
+/// `name` with the first 10 hex digits of the SHA-256 of `content` before its extension:
+/// `assets/css/gallery.css` becomes `assets/css/gallery.0123456789.css`.
+fn hashed_name(name: &str, content: &[u8]) -> String {
+    let hash = hex::encode(Sha256::digest(content));
+    match name.rsplit_once('.') {
+        Some((stem, extension)) if !stem.ends_with('/') => {
+            format!("{}.{}.{}", stem, &hash[..10], extension)
+        }
+        _ => format!("{}.{}", name, &hash[..10]),
+    }
+}
+
+/// Replaces the references to `from` in `text` that aren't part of a longer path.
+fn replace_reference(text: &str, from: &str, to: &str) -> String {
+    let mut replaced = String::with_capacity(text.len());
+    let mut rest = text;
+
+    while let Some(at) = rest.find(from) {
+        let before = rest[..at]
+            .chars()
+            .next_back()
+            .or(replaced.chars().next_back());
+        let after = rest[at + from.len()..].chars().next();
+        replaced.push_str(&rest[..at]);
+        if before.is_some_and(continues_path) || after.is_some_and(continues_path) {
+            replaced.push_str(from);
+        } else {
+            replaced.push_str(to);
+        }
+        rest = &rest[at + from.len()..];
+    }
+    replaced.push_str(rest);
+    replaced
+}
+
+impl Fingerprint {
+    /// Renames the assets in `dist` after their content, renders the pages from their templates
+    /// pointing at the new names and records them in the asset manifest. Without `hash` the assets
+    /// keep their plain names, which the templates already refer to.
+    ///
+    /// The previous asset manifest says what the last build renamed: a rebuilt asset replaces its old
+    /// hashed copy, one that wasn't rebuilt keeps its hashed name, or gets its plain one back.
+    pub fn apply(&self, root: &Path, dist: &Path, hash: bool) -> Result<BTreeMap<String, String>> {
+        let manifest_path = dist.join(&self.manifest);
+        let previous: BTreeMap<String, String> = match File::open(&manifest_path) {
+            Ok(file) => serde_json::from_reader(file)?,
+            Err(_) => BTreeMap::new(),
+        };
+
+        let mut assets = BTreeMap::new();
+        for (name, hashed) in &previous {
+            if dist.join(name).is_file() {
+                let _ = fs::remove_file(dist.join(hashed));
+            } else if !dist.join(hashed).is_file() {
+                continue;
+            } else if hash {
+                assets.insert(name.clone(), hashed.clone());
+            } else {
+                fs::rename(dist.join(hashed), dist.join(name))?;
+            }
+        }
+
+        if hash {
+            self.rename(dist, &mut assets)?;
+        }
+
+        for page in &self.pages {
+            let template = root.join(&self.templates).join(page);
+            let mut text = match fs::read_to_string(&template) {
+                Ok(text) => text,
+                Err(e) if e.kind() == io::ErrorKind::NotFound => continue,
+                Err(e) => bail!("Can't read the page {}: {}", template.display(), e),
+            };
+            for (name, hashed) in &assets {
+                text = replace_reference(&text, name, hashed);
+            }
+
+            // Left alone when it hasn't changed, so its compressed siblings aren't made again.
+            let path = dist.join(page);
+            if fs::read_to_string(&path).ok().as_ref() != Some(&text) {
+                if let Some(parent) = path.parent() {
+                    fs::create_dir_all(parent)?;
+                }
+                fs::write(&path, text)?;
+            }
+        }
+
+        let mut file = File::create(&manifest_path)?;
+        serde_json::to_writer_pretty(&mut file, &assets)?;
+        writeln!(file)?;
+        Ok(assets)
+    }
+
+    /// Gives the assets in `dist` that aren't already in `assets` their hashed names, and adds them.
+    fn rename(&self, dist: &Path, assets: &mut BTreeMap<String, String>) -> Result<()> {
+        let patterns = self.assets.iter().map(String::as_str).collect::<Vec<_>>();
+        for entry in globwalk::GlobWalkerBuilder::from_patterns(dist, &patterns)
+            .build()?
+            .filter_map(|entry| entry.ok())
+            .filter(|entry| entry.file_type().is_file())
+        {
+            let name = entry
+                .path()
+                .strip_prefix(dist)?
+                .iter()
+                .map(|part| {
+                    part.to_str()
+                        .ok_or(anyhow!("{} is not valid UTF-8", entry.path().display()))
+                })
+                .collect::<Result<Vec<_>>>()?
+                .join("/");
+            if assets.values().any(|hashed| *hashed == name) {
+                continue;
+            }
+
+            let hashed = hashed_name(&name, &fs::read(entry.path())?);
+            fs::rename(entry.path(), dist.join(&hashed))?;
+            println!("{} -> {}", name, hashed);
+            assets.insert(name, hashed);
+        }
+        Ok(())
+    }
+}
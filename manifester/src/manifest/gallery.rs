@@ -0,0 +1,214 @@
+use anyhow::{anyhow, bail, Result};
+use chrono::{Datelike, NaiveDate};
+use globwalk::DirEntry;
+use std::collections::BTreeMap;
+use std::fs::{self, File};
+use std::io::{self, BufReader};
+use std::path::{Path, PathBuf};
+
+use super::map::{Location, Taken, Trip};
+use super::Manifest;
+
+/// Whether `path` is an original image rather than a thumbnail or blur generated from one.
+pub fn is_original(path: &Path) -> bool {
+    let name = match path.file_name().and_then(|name| name.to_str()) {
+        Some(name) => name,
+        None => return false,
+    };
+    let extension = path
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .unwrap_or_default()
+        .to_lowercase();
+
+    ["png", "jpg", "jpeg"].contains(&extension.as_str())
+        && !name.contains("_small")
+        && !name.contains("_blur")
+}
+
+/// The thumbnail and the blur generated next to the image at `path`.
+pub fn derivative_paths(path: &Path) -> Result<(PathBuf, PathBuf)> {
+    let stem = path
+        .file_stem()
+        .and_then(|p| p.to_str())
+        .ok_or(anyhow!("{} has no file stem", path.display()))?;
+    let ext = path
+        .extension()
+        .and_then(|p| p.to_str())
+        .ok_or(anyhow!("{} has no extension", path.display()))?;
+
+    Ok((
+        path.with_file_name(format!("{}_small.{}", stem, ext)),
+        path.with_file_name(format!("{}_blur.{}", stem, ext)),
+    ))
+}
+
+/// The original a thumbnail or blur at `path` is generated from, if it is one.
+pub fn original_of(path: &Path) -> Option<PathBuf> {
+    let stem = path.file_stem()?.to_str()?;
+    let ext = path.extension()?.to_str()?;
+    let original = stem
+        .strip_suffix("_small")
+        .or_else(|| stem.strip_suffix("_blur"))?;
+    let original = path.with_file_name(format!("{}.{}", original, ext));
+    is_original(&original).then_some(original)
+}
+
+/// Every original image in the gallery, ignoring the thumbnails and blurs generated from them.
+fn gallery_images(gallery: &Path) -> Result<Vec<DirEntry>> {
+    Ok(globwalk::GlobWalkerBuilder::from_patterns(
+        gallery,
+        &["*.{png,jpg,jpeg,PNG,JPG,JPEG}", "!*_small*", "!*_blur*"],
+    )
+    .follow_links(true)
+    .build()?
+    .filter_map(Result::ok)
+    .collect::<Vec<DirEntry>>())
+}
+
+/// Every original image in the gallery, sorted by path: the order a directory is walked in differs
+/// between file systems and shouldn't change the outputs.
+pub fn read_gallery(gallery: &Path, manifest: &Manifest) -> Result<Vec<GalleryImage>> {
+    let mut images = gallery_images(gallery)?
+        .iter()
+        .map(|file| GalleryImage::read(file.path(), gallery, manifest))
+        .collect::<Result<Vec<GalleryImage>>>()?;
+    images.sort_by(|a, b| a.path.cmp(&b.path));
+    Ok(images)
+}
+
+/// Where and when an image was taken, read from its `year/month/country/location/name` gallery path.
+#[derive(Debug, Clone)]
+pub struct GalleryImage {
+    pub path: PathBuf,
+    gallery_path: String,
+    pub name: String,
+    pub location: Location,
+    pub year: i32,
+    pub month: u32,
+    pub taken: Taken,
+}
+
+impl GalleryImage {
+    pub fn read(path: &Path, gallery: &Path, manifest: &Manifest) -> Result<Self> {
+        let parts = path
+            .strip_prefix(gallery)?
+            .iter()
+            .map(|part| {
+                part.to_str()
+                    .ok_or(anyhow!("{} is not valid UTF-8", path.display()))
+            })
+            .collect::<Result<Vec<&str>>>()?;
+
+        // Countries with regions file their locations one level deeper, under the region.
+        let (year, month, country, region, location, name) = match parts.as_slice() {
+            [year, month, country, location, name] => (year, month, country, None, location, name),
+            [year, month, country, region, location, name] => {
+                (year, month, country, Some(region), location, name)
+            }
+            _ => bail!(
+                "{} is not filed as year/month/country/[region/]location/name",
+                path.display()
+            ),
+        };
+        let year = year.parse::<i32>()?;
+        let month = month.parse::<u32>()?;
+        let location = manifest.folder_location(country, location)?.clone();
+
+        let expected = manifest.region(&location).map(|r| r.name());
+        let region = region.map(|r| r.replace('_', " "));
+        if expected != region {
+            bail!(
+                "{} is filed under {} but places puts {} in {}",
+                path.display(),
+                region.unwrap_or("no region".to_string()),
+                location.name(),
+                expected.unwrap_or("no region".to_string())
+            );
+        }
+
+        // Trust the capture day only if it agrees with the folder the image was filed under.
+        let taken = match capture_date(path) {
+            Some(day) if (day.year(), day.month()) == (year, month) => Taken::Day(day),
+            _ => Taken::Month(year, month),
+        };
+
+        Ok(GalleryImage {
+            path: path.to_path_buf(),
+            gallery_path: parts.join("/"),
+            name: name.to_string(),
+            location,
+            year,
+            month,
+            taken,
+        })
+    }
+}
+
+impl GalleryImage {
+    /// Path of the image relative to the gallery root, as served next to the map.
+    pub fn gallery_path(&self) -> &str {
+        &self.gallery_path
+    }
+
+    /// The thumbnail and the blur generated next to the image.
+    pub fn derivative_paths(&self) -> Result<(PathBuf, PathBuf)> {
+        derivative_paths(&self.path)
+    }
+
+    /// The trips this image belongs to. A trip sidecar overrides the automatic assignment,
+    /// an empty one keeps the image out of every trip.
+    pub fn trips<'a>(&self, trips: &[&'a Trip]) -> Result<Vec<&'a Trip>> {
+        let sidecar = self.path.with_extension("trip");
+        match fs::read_to_string(&sidecar) {
+            Ok(ids) => ids
+                .split_whitespace()
+                .map(|id| {
+                    trips
+                        .iter()
+                        .find(|trip| trip.id() == id)
+                        .copied()
+                        .ok_or(anyhow!("{} names unknown trip {}", self.path.display(), id))
+                })
+                .collect(),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(trips
+                .iter()
+                .filter(|trip| trip.includes(&self.location, &self.taken))
+                .copied()
+                .collect()),
+            Err(e) => bail!("Can't read {}: {}", sidecar.display(), e),
+        }
+    }
+}
+
+/// File names of the images in each trip, by trip id.
+pub fn trip_photos<'a>(
+    images: &'a [GalleryImage],
+    trips: &[&Trip],
+) -> Result<BTreeMap<String, Vec<&'a GalleryImage>>> {
+    let mut photos: BTreeMap<String, Vec<&GalleryImage>> = BTreeMap::new();
+
+    for image in images {
+        for trip in image.trips(trips)? {
+            photos.entry(trip.id()).or_default().push(image);
+        }
+    }
+
+    Ok(photos)
+}
+
+fn capture_date(path: &Path) -> Option<NaiveDate> {
+    let file = File::open(path).ok()?;
+    let exif = exif::Reader::new()
+        .read_from_container(&mut BufReader::new(file))
+        .ok()?;
+    let field = exif.get_field(exif::Tag::DateTimeOriginal, exif::In::PRIMARY)?;
+
+    match &field.value {
+        exif::Value::Ascii(values) => {
+            let taken = exif::DateTime::from_ascii(values.first()?).ok()?;
+            NaiveDate::from_ymd_opt(taken.year.into(), taken.month.into(), taken.day.into())
+        }
+        _ => None,
+    }
+}
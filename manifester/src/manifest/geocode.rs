@@ -0,0 +1,285 @@
+use anyhow::{bail, Result};
+use std::fs::File;
+use std::io::{self, Write};
+use std::path::Path;
+
+use super::map::{
+    find_location, Coordinates, Country, Feature, FeatureCollection, Geometry, Location, Pin,
+    Places, Properties,
+};
+use super::nominatim::Nominatim;
+use super::route::{central_angle, EARTH_RADIUS_KM};
+use super::Manifest;
+
+/// Reads cities.json, geocodes the places it is missing or whose pin in manifest.yaml changed since they
+/// were written, and brings the rest in line with manifest.yaml.
+pub fn geocode_cities<P>(manifest: &Manifest, cities_json: P) -> Result<FeatureCollection>
+where
+    P: AsRef<Path>,
+{
+    let mut cities = read_cities(&cities_json)?;
+
+    identify_cities(manifest, &mut cities.features);
+
+    for (country, places) in &manifest.places {
+        for (location, local_name) in &places.locations {
+            let existing = cities
+                .features
+                .iter()
+                .position(|f| f.properties.id.as_deref() == Some(location.id()));
+            let pin = places.pins.get(location);
+
+            let stale = match (existing, pin) {
+                (None, _) => true,
+                (Some(idx), Some(Pin::Coordinates(lon, lat))) => {
+                    cities.features[idx].geometry.coordinates
+                        != Coordinates::Point(vec![*lon as f32, *lat as f32])
+                }
+                (Some(idx), Some(Pin::Osm(osm))) => {
+                    cities.features[idx].properties.osm.as_ref() != Some(osm)
+                }
+                // A place that lost its OpenStreetMap pin is searched for again.
+                (Some(idx), None) => cities.features[idx].properties.osm.is_some(),
+            };
+            if !stale {
+                continue;
+            }
+
+            let (coordinates, osm) = match pin {
+                Some(Pin::Coordinates(lon, lat)) => (vec![*lon as f32, *lat as f32], None),
+                Some(Pin::Osm(osm)) => {
+                    let coords = Nominatim::lookup(&manifest.geocoding, osm)?;
+                    (
+                        vec![coords.lon().parse::<f32>()?, coords.lat().parse::<f32>()?],
+                        Some(osm.clone()),
+                    )
+                }
+                None => {
+                    let coords =
+                        Nominatim::search(&manifest.geocoding, &query(country, places, location))?;
+                    (
+                        vec![coords.lon().parse::<f32>()?, coords.lat().parse::<f32>()?],
+                        None,
+                    )
+                }
+            };
+
+            let properties = Properties {
+                name: location.name(),
+                localname: local_name.to_owned(),
+                country: Some(country.code(&manifest.codes)?),
+                region: places.region(location).map(|region| region.name()),
+                id: Some(location.id().to_string()),
+                osm,
+                ..Default::default()
+            };
+
+            let geometry = Geometry {
+                type_: "Point".to_string(),
+                coordinates: Coordinates::Point(coordinates),
+            };
+
+            println!("{} {:?}", location.id(), geometry.coordinates);
+            let feature = Feature {
+                type_: "Feature".to_string(),
+                properties,
+                geometry,
+            };
+            match existing {
+                Some(idx) => cities.features[idx] = feature,
+                None => cities.features.push(feature),
+            }
+        }
+    }
+
+    reconcile(manifest, &mut cities)?;
+
+    Ok(cities)
+}
+
+/// Reads cities.json, which starts out empty when there is none yet so every place is new and gets
+/// geocoded.
+fn read_cities<P>(cities_json: P) -> Result<FeatureCollection>
+where
+    P: AsRef<Path>,
+{
+    match File::open(&cities_json) {
+        Ok(buffer) => Ok(serde_json::from_reader(&buffer)?),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(FeatureCollection {
+            type_: "FeatureCollection".to_string(),
+            features: Vec::new(),
+        }),
+        Err(e) => bail!("Reading {} failed: {}", cities_json.as_ref().display(), e),
+    }
+}
+
+/// Updates the names, local names, countries and regions of cities from manifest.yaml, drops the ones
+/// it no longer lists and sorts the rest by id.
+fn reconcile(manifest: &Manifest, cities: &mut FeatureCollection) -> Result<()> {
+    let mut features: Vec<Feature> = Vec::new();
+
+    for mut feature in cities.features.drain(..) {
+        let place = manifest.places.iter().find_map(|(country, places)| {
+            places
+                .locations
+                .iter()
+                .find(|(location, _)| feature.properties.id.as_deref() == Some(location.id()))
+                .map(|(location, local_name)| (country, places, location, local_name))
+        });
+
+        let (country, places, location, local_name) = match place {
+            Some(place) => place,
+            None => {
+                println!(
+                    "Removed {} ({}) from cities.json, it is no longer in places",
+                    feature.properties.name,
+                    feature.properties.id.as_deref().unwrap_or("no id")
+                );
+                continue;
+            }
+        };
+
+        let properties = &mut feature.properties;
+        properties.name = location.name();
+        properties.localname = local_name.clone();
+        properties.country = Some(country.code(&manifest.codes)?);
+        properties.region = places.region(location).map(|region| region.name());
+        features.push(feature);
+    }
+
+    features.sort_by(|a, b| a.properties.id.cmp(&b.properties.id));
+    cities.features = features;
+
+    Ok(())
+}
+
+/// Writes cities.json pretty printed, so changes to it read well in a diff.
+pub fn write_cities<P>(cities_json: P, cities: &FeatureCollection) -> Result<()>
+where
+    P: AsRef<Path>,
+{
+    let mut cities_create = File::create(&cities_json)?;
+    serde_json::to_writer_pretty(&cities_create, cities)?;
+    writeln!(cities_create)?;
+
+    Ok(())
+}
+
+/// Geocodes new and re-pinned places into cities.json without building the rest of the manifest.
+pub fn geocode<P>(manifest: &Manifest, cities_json: P) -> Result<()>
+where
+    P: AsRef<Path>,
+{
+    let cities = geocode_cities(manifest, &cities_json)?;
+    write_cities(&cities_json, &cities)
+}
+
+/// Prints the best `candidates` Nominatim matches for each of `locations`, or every place, with how far
+/// each one is from the coordinates in cities.json, and the pin that would select it.
+pub fn review<P>(
+    manifest: &Manifest,
+    cities_json: P,
+    candidates: usize,
+    locations: &[String],
+) -> Result<()>
+where
+    P: AsRef<Path>,
+{
+    let cities = read_cities(&cities_json)?;
+
+    let selected = locations
+        .iter()
+        .map(|reference| find_location(&manifest.places, reference))
+        .collect::<Result<Vec<&Location>>>()?;
+
+    for (country, places) in &manifest.places {
+        for location in places.locations.keys() {
+            if !selected.is_empty() && !selected.contains(&location) {
+                continue;
+            }
+
+            let current = location
+                .feature_coordinates(&cities.features)
+                .ok()
+                .map(|c| (c[0] as f64, c[1] as f64));
+
+            match (current, places.pins.get(location)) {
+                (Some((lon, lat)), Some(pin)) => println!(
+                    "{} ({}) at {:.4}, {:.4}, pinned to {}",
+                    location.name(),
+                    location.id(),
+                    lon,
+                    lat,
+                    match pin {
+                        Pin::Coordinates(..) => "coordinates".to_string(),
+                        Pin::Osm(osm) => osm.clone(),
+                    }
+                ),
+                (Some((lon, lat)), None) => println!(
+                    "{} ({}) at {:.4}, {:.4}",
+                    location.name(),
+                    location.id(),
+                    lon,
+                    lat
+                ),
+                (None, _) => println!("{} ({}) not geocoded yet", location.name(), location.id()),
+            }
+
+            let query = query(country, places, location);
+            let results = Nominatim::candidates(&manifest.geocoding, &query, candidates)?;
+
+            if results.is_empty() {
+                println!("  nothing found for \"{}\"", query);
+            }
+
+            for (idx, result) in results.iter().enumerate() {
+                let position = (result.lon().parse::<f64>()?, result.lat().parse::<f64>()?);
+                let distance = current
+                    .map(|current| {
+                        format!(
+                            " {:.1} km away",
+                            central_angle(current, position) * EARTH_RADIUS_KM
+                        )
+                    })
+                    .unwrap_or_default();
+
+                println!(
+                    "  {}. {} ({:.4}, {:.4}){}",
+                    idx + 1,
+                    result.display_name(),
+                    position.0,
+                    position.1,
+                    distance
+                );
+                match result.osm() {
+                    Some(osm) => println!("     osm: {}", osm),
+                    None => println!("     coordinates: [{:.4}, {:.4}]", position.0, position.1),
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn query(country: &Country, places: &Places, location: &Location) -> String {
+    match places.region(location) {
+        Some(region) => format!("{}, {}, {}", location.name(), region.name(), country.name()),
+        None => format!("{}, {}", location.name(), country.name()),
+    }
+}
+
+/// Gives the features of a cities.json written before locations had ids the id of the location with
+/// their name in their country.
+fn identify_cities(manifest: &Manifest, features: &mut [Feature]) {
+    for feature in features.iter_mut().filter(|f| f.properties.id.is_none()) {
+        let properties = &mut feature.properties;
+        properties.id = manifest
+            .places
+            .iter()
+            .filter(|(country, _)| country.code(&manifest.codes).ok() == properties.country)
+            .flat_map(|(_, places)| places.locations.keys())
+            .find(|location| location.name() == properties.name)
+            .map(|location| location.id().to_string());
+    }
+}
@@ -1,9 +1,12 @@
-use anyhow::{bail, Result};
+use anyhow::{anyhow, bail, Result};
+use chrono::{Datelike, Months, NaiveDate};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
 use std::collections::BTreeMap;
 use std::fmt;
-use strum_macros::EnumString;
+use std::path::Path;
 
-use crate::utils::to_location_identfier_string;
+use super::track::is_track;
+use crate::utils::{to_constructor, to_slug};
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Feature {
@@ -13,13 +16,60 @@ pub struct Feature {
     pub geometry: Geometry,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Default, Serialize, Deserialize)]
 pub struct Properties {
     pub name: String,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub localname: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub country: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub region: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+    /// The OpenStreetMap object a city's coordinates were pinned to.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub osm: Option<String>,
+    /// Kilometres along great circles between a trip's cities.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub distance: Option<f64>,
+    /// Countries and distinct cities a trip visits, the days it lasts and the photos taken on it.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub countries: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cities: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub days: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub photos: Option<usize>,
+    /// Kilometres covered by a trip's recorded tracks.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub track_distance: Option<f64>,
+    /// Metres climbed and descended, and the highest and lowest elevation, along the tracks.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ascent: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub descent: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub highest: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub lowest: Option<f64>,
+    /// First and last day of a trip, and the colour it is drawn in.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub start: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub end: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub colour: Option<String>,
+    /// When a city was first and last photographed, the trips through it and its latest photo as a cover.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub first_visit: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub last_visit: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub trips: Option<Vec<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cover: Option<String>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -34,6 +84,8 @@ pub struct Geometry {
 pub enum Coordinates {
     Point(Vec<f32>),
     LineString(Vec<Vec<f32>>),
+    MultiLineString(Vec<Vec<Vec<f32>>>),
+    MultiPolygon(Vec<Vec<Vec<Vec<f32>>>>),
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -43,22 +95,25 @@ pub struct FeatureCollection {
     pub features: Vec<Feature>,
 }
 
+/// A country as keyed in places, either by its name or run together as in `SouthKorea`.
 #[derive(Debug, Clone, Ord, PartialOrd, Eq, PartialEq, Serialize, Deserialize)]
-pub enum Country {
-    Korea
-}
+pub struct Country(String);
 
 impl fmt::Display for Country {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        fmt::Debug::fmt(self, f)
+        write!(f, "{}", to_constructor(&to_slug(&self.name()), "Country"))
     }
 }
 
 impl Country {
     pub fn name(&self) -> String {
+        if self.0.contains(' ') {
+            return self.0.clone();
+        }
+
         let mut name: Vec<char> = Vec::new();
 
-        for (idx, c) in self.to_string().char_indices() {
+        for (idx, c) in self.0.char_indices() {
             if idx > 0 && c.is_uppercase() {
                 name.push(' ');
             }
@@ -75,67 +130,661 @@ impl Country {
     }
 }
 
-#[derive(Debug, Clone, Ord, PartialOrd, Eq, PartialEq, EnumString, Serialize, Deserialize)]
-pub enum Location {
-    Local,
-    Seoul,
-    Andong,
-    Jeju,
-    Cheongju,
-    Danyang,
-    Gangneung,
-    Yeongdeok,
-    Jinju,
+/// An administrative region of a country such as a province, state or prefecture, named as in its folder.
+#[derive(Debug, Clone, Ord, PartialOrd, Eq, PartialEq, Serialize, Deserialize)]
+pub struct Region(String);
+
+impl fmt::Display for Region {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", to_constructor(&self.id(), "Region"))
+    }
+}
+
+impl Region {
+    pub fn name(&self) -> String {
+        self.0.replace('_', " ")
+    }
+
+    pub fn id(&self) -> String {
+        to_slug(&self.0)
+    }
+}
+
+/// The places of a country as written in manifest.yaml: cities with their local names, optionally grouped
+/// into regions. `Local` names the country or region itself.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum Place {
+    City(City),
+    Region(BTreeMap<String, City>),
+}
+
+/// A city is just its local name, unless geocoding needs help finding it.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum City {
+    Name(Option<String>),
+    Pinned(PinnedCity),
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PinnedCity {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub local: Option<String>,
+    /// Longitude and latitude used instead of geocoding.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub coordinates: Option<[f64; 2]>,
+    /// OpenStreetMap object to take the coordinates from, `N`, `W` or `R` followed by its id.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub osm: Option<String>,
+}
+
+/// Where a location is, when that isn't left to a search for its name.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Pin {
+    Coordinates(f64, f64),
+    Osm(String),
+}
+
+impl City {
+    fn split(self) -> Result<(Option<String>, Option<Pin>)> {
+        match self {
+            City::Name(local) => Ok((local, None)),
+            City::Pinned(PinnedCity {
+                local,
+                coordinates,
+                osm,
+            }) => match (coordinates, osm) {
+                (Some([lon, lat]), None) => {
+                    if !(-180.0..=180.0).contains(&lon) || !(-90.0..=90.0).contains(&lat) {
+                        bail!(
+                            "coordinates {}, {} are not a longitude and latitude",
+                            lon,
+                            lat
+                        );
+                    }
+                    Ok((local, Some(Pin::Coordinates(lon, lat))))
+                }
+                (None, Some(osm)) => {
+                    let mut chars = osm.chars();
+                    if !matches!(chars.next(), Some('N' | 'W' | 'R'))
+                        || chars.as_str().is_empty()
+                        || !chars.all(|c| c.is_ascii_digit())
+                    {
+                        bail!("osm {} should be N, W or R followed by the object id", osm);
+                    }
+                    Ok((local, Some(Pin::Osm(osm))))
+                }
+                (None, None) => Ok((local, None)),
+                (Some(_), Some(_)) => bail!("give either coordinates or osm, not both"),
+            },
+        }
+    }
+
+    fn join(local: Option<String>, pin: Option<&Pin>) -> Self {
+        match pin {
+            None => City::Name(local),
+            Some(Pin::Coordinates(lon, lat)) => City::Pinned(PinnedCity {
+                local,
+                coordinates: Some([*lon, *lat]),
+                osm: None,
+            }),
+            Some(Pin::Osm(osm)) => City::Pinned(PinnedCity {
+                local,
+                coordinates: None,
+                osm: Some(osm.clone()),
+            }),
+        }
+    }
+}
+
+/// Every location of a country with its local name, and the region each one belongs to if any.
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(into = "BTreeMap<String, Place>")]
+pub struct Places {
+    pub local_name: Option<String>,
+    pub locations: BTreeMap<Location, Option<String>>,
+    pub regions: BTreeMap<Region, Option<String>>,
+    pub location_regions: BTreeMap<Location, Region>,
+    pub pins: BTreeMap<Location, Pin>,
+}
+
+const LOCAL: &str = "Local";
+
+impl Places {
+    fn new(country_code: &str, entries: BTreeMap<String, Place>) -> Result<Self> {
+        let mut places = Places::default();
+        let mut ids: BTreeMap<String, String> = BTreeMap::new();
+
+        let mut add = |places: &mut Places, name: String, city: City| {
+            let (local_name, pin) = city
+                .split()
+                .map_err(|error| anyhow!("{} has {}", name, error))?;
+            let location = Location::new(country_code, &name);
+            if to_slug(&name).is_empty() {
+                bail!("{} needs a name with letters or digits", name);
+            }
+            if let Some(other) = ids.insert(location.id.clone(), name.clone()) {
+                bail!(
+                    "{} and {} both have the id {}, rename one of them",
+                    other,
+                    name,
+                    location.id
+                );
+            }
+            if let Some(pin) = pin {
+                places.pins.insert(location.clone(), pin);
+            }
+            places.locations.insert(location.clone(), local_name);
+            Ok(location)
+        };
+
+        for (key, place) in entries {
+            match place {
+                Place::City(City::Name(local_name)) if key == LOCAL => {
+                    places.local_name = local_name
+                }
+                Place::City(City::Pinned(_)) if key == LOCAL => {
+                    bail!("{} names the country itself and can't be pinned", LOCAL)
+                }
+                Place::City(city) => {
+                    add(&mut places, key, city)?;
+                }
+                Place::Region(mut locations) => {
+                    let region = Region(key);
+                    let local_name = match locations.remove(LOCAL) {
+                        Some(City::Name(local_name)) => local_name,
+                        Some(City::Pinned(_)) => bail!(
+                            "{} of region {} names the region itself and can't be pinned",
+                            LOCAL,
+                            region.name()
+                        ),
+                        None => None,
+                    };
+                    for (name, local) in locations {
+                        let location = add(&mut places, name, local)?;
+                        places.location_regions.insert(location, region.clone());
+                    }
+                    places.regions.insert(region, local_name);
+                }
+            }
+        }
+
+        Ok(places)
+    }
+
+    pub fn region(&self, location: &Location) -> Option<&Region> {
+        self.location_regions.get(location)
+    }
+}
+
+impl From<Places> for BTreeMap<String, Place> {
+    fn from(places: Places) -> Self {
+        let mut entries: BTreeMap<String, Place> = BTreeMap::new();
+
+        if places.local_name.is_some() {
+            entries.insert(
+                LOCAL.to_string(),
+                Place::City(City::Name(places.local_name.clone())),
+            );
+        }
+
+        for (region, local_name) in &places.regions {
+            let mut locations = BTreeMap::new();
+            if local_name.is_some() {
+                locations.insert(LOCAL.to_string(), City::Name(local_name.clone()));
+            }
+            entries.insert(region.0.clone(), Place::Region(locations));
+        }
+
+        for (location, local_name) in places.locations {
+            let city = City::join(local_name, places.pins.get(&location));
+            match places.location_regions.get(&location) {
+                Some(region) => {
+                    if let Some(Place::Region(locations)) = entries.get_mut(&region.0) {
+                        locations.insert(location.name, city);
+                    }
+                }
+                None => {
+                    entries.insert(location.name, Place::City(city));
+                }
+            }
+        }
+
+        entries
+    }
+}
+
+/// Reads the places of every country, keying their locations by the country's cca3 code.
+pub fn read_places(
+    places: serde_yaml::Value,
+    codes: &BTreeMap<String, String>,
+) -> Result<BTreeMap<Country, Places>> {
+    serde_yaml::from_value::<BTreeMap<Country, BTreeMap<String, Place>>>(places)?
+        .into_iter()
+        .map(|(country, entries)| {
+            let places = Places::new(&country.code(codes)?, entries)?;
+            Ok((country, places))
+        })
+        .collect()
+}
+
+/// The location in `places` that `reference` names, either by its name or by its id.
+pub fn find_location<'a>(
+    places: &'a BTreeMap<Country, Places>,
+    reference: &str,
+) -> Result<&'a Location> {
+    let matches = places
+        .values()
+        .flat_map(|places| places.locations.keys())
+        .filter(|location| location.id == reference || location.name == reference)
+        .collect::<Vec<&Location>>();
+
+    match matches.as_slice() {
+        [location] => Ok(location),
+        [] => bail!("{} is not in places", reference),
+        _ => bail!(
+            "{} is in more than one country, use one of {}",
+            reference,
+            matches
+                .iter()
+                .map(|location| location.id.as_str())
+                .collect::<Vec<&str>>()
+                .join(", ")
+        ),
+    }
+}
+
+/// A city, identified by its country's cca3 code and a slug of its name, `kor-jeju`, so that cities
+/// sharing a name in different countries stay apart.
+#[derive(Debug, Clone, Ord, PartialOrd, Eq, PartialEq)]
+pub struct Location {
+    id: String,
+    name: String,
 }
 
 impl fmt::Display for Location {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        fmt::Debug::fmt(self, f)
+        write!(f, "{}", to_constructor(&self.id, "Location"))
     }
 }
 
 impl Location {
+    pub fn new(country_code: &str, name: &str) -> Self {
+        Location {
+            id: format!("{}-{}", country_code.to_lowercase(), to_slug(name)),
+            name: name.to_string(),
+        }
+    }
+
+    /// A city named in a trip, resolved against places once the whole manifest is read.
+    pub fn reference(reference: &str) -> Self {
+        Location {
+            id: String::new(),
+            name: reference.to_string(),
+        }
+    }
+
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
     pub fn name(&self) -> String {
-        let mut name: Vec<char> = Vec::new();
+        self.name.clone()
+    }
 
-        for (idx, c) in self.to_string().char_indices() {
-            if idx > 0 && c.is_uppercase() {
-                name.push(' ');
-            }
-            name.push(c);
+    pub fn is_resolved(&self) -> bool {
+        !self.id.is_empty()
+    }
+
+    /// This location as a trip should name it, by its id when another city in `places` has its name.
+    pub fn unambiguous(&self, places: &BTreeMap<Country, Places>) -> Location {
+        match find_location(places, &self.name) {
+            Ok(_) => self.clone(),
+            Err(_) => Location {
+                id: self.id.clone(),
+                name: self.id.clone(),
+            },
         }
+    }
 
-        String::from_iter(name)
+    /// Name of the gallery folder holding this location's photos.
+    pub fn folder(&self) -> String {
+        self.name.replace(' ', "_")
     }
 
     pub fn feature_coordinates(&self, features: &[Feature]) -> Result<Vec<f32>> {
         for feature in features {
-            if to_location_identfier_string(&feature.properties.name) == self.to_string() {
+            if feature.properties.id.as_deref() == Some(self.id.as_str()) {
                 match &feature.geometry.coordinates {
                     Coordinates::Point(coords) => return Ok(coords.clone()),
                     _ => {
-                        bail!("{} does not have Point coordinates.", self);
+                        bail!("{} does not have Point coordinates.", self.name);
                     }
                 };
             }
         }
-        bail!("Could not find coordinates for {}.", self)
+        bail!("Could not find coordinates for {}.", self.name)
+    }
+}
+
+impl Serialize for Location {
+    /// Trips name their cities, `unambiguous` swaps in the id where the name alone isn't enough.
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.serialize_str(&self.name)
+    }
+}
+
+impl<'de> Deserialize<'de> for Location {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        Ok(Location::reference(&String::deserialize(deserializer)?))
     }
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Trip {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
     pub name: String,
     pub description: String,
+    pub legs: Vec<Leg>,
+    /// GPX, KML or GeoJSON recordings, relative to the repository, drawn alongside the legs.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tracks: Vec<String>,
+    /// Simplification tolerance for this trip's tracks in metres, overriding `track_tolerance`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tolerance: Option<f64>,
+    /// `#rrggbb` colour for the map, otherwise one is picked from a palette.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub colour: Option<String>,
+}
+
+/// A stretch of a trip spent around `cities`. Dates are either `YYYY-MM-DD` or `YYYY-MM`,
+/// in which case the leg covers the month from its first to its last day.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Leg {
     pub cities: Vec<Location>,
-    pub dates: Vec<String>,
+    #[serde(deserialize_with = "day::start")]
+    pub start: NaiveDate,
+    #[serde(deserialize_with = "day::end")]
+    pub end: NaiveDate,
+    #[serde(default, skip_serializing_if = "Mode::is_drive")]
+    pub mode: Mode,
+    /// GPX, KML or GeoJSON file, relative to the repository, to draw instead of the computed path.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub track: Option<String>,
+}
+
+/// How a leg was travelled: from the previous leg's last city and between its own cities.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Mode {
+    Flight,
+    #[default]
+    Drive,
+    Train,
+    Ferry,
+}
+
+impl Mode {
+    pub fn is_drive(&self) -> bool {
+        *self == Mode::Drive
+    }
+}
+
+/// When an image was taken: the EXIF capture day if there is one, otherwise the year and month of its gallery folder.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Taken {
+    Day(NaiveDate),
+    Month(i32, u32),
+}
+
+impl Taken {
+    /// The earliest day the image could have been taken, for ordering.
+    pub fn first_day(&self) -> Option<NaiveDate> {
+        match self {
+            Taken::Day(day) => Some(*day),
+            Taken::Month(year, month) => NaiveDate::from_ymd_opt(*year, *month, 1),
+        }
+    }
+}
+
+impl fmt::Display for Taken {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Taken::Day(day) => write!(f, "{}", day),
+            Taken::Month(year, month) => write!(f, "{:04}-{:02}", year, month),
+        }
+    }
+}
+
+impl Leg {
+    pub fn covers(&self, taken: &Taken) -> bool {
+        match taken {
+            Taken::Day(day) => self.start <= *day && *day <= self.end,
+            Taken::Month(year, month) => {
+                (self.start.year(), self.start.month()) <= (*year, *month)
+                    && (*year, *month) <= (self.end.year(), self.end.month())
+            }
+        }
+    }
 }
 
 impl Trip {
+    /// Stable identifier used in trips.json and URLs: the explicit `id` from the manifest, or a slug of the description.
+    pub fn id(&self) -> String {
+        match &self.id {
+            Some(id) => id.to_string(),
+            None => to_slug(&self.description),
+        }
+    }
+
+    /// Elm constructor for this trip, derived from its id.
     pub fn id_string(&self) -> String {
-        let mut id = self.description.to_string();
+        to_constructor(&self.id(), "Trip")
+    }
+
+    /// Cities in visiting order, without repeating a city where one leg ends and the next begins.
+    pub fn cities(&self) -> Vec<&Location> {
+        let mut cities: Vec<&Location> = Vec::new();
+        for city in self.legs.iter().flat_map(|leg| &leg.cities) {
+            if cities.last() != Some(&city) {
+                cities.push(city);
+            }
+        }
+        cities
+    }
 
-        id.retain(|c| c != ' ' && c != '/');
-        id
+    pub fn start(&self) -> Option<NaiveDate> {
+        self.legs.first().map(|leg| leg.start)
+    }
+
+    pub fn end(&self) -> Option<NaiveDate> {
+        self.legs.last().map(|leg| leg.end)
+    }
+
+    /// Days travelled. A leg starting on the day the previous one ended doesn't count that day twice.
+    pub fn days(&self) -> i64 {
+        let mut days = 0;
+        let mut previous_end: Option<NaiveDate> = None;
+        for leg in &self.legs {
+            days += (leg.end - leg.start).num_days() + 1;
+            if previous_end == Some(leg.start) {
+                days -= 1;
+            }
+            previous_end = Some(leg.end);
+        }
+        days
+    }
+
+    /// Whether an image taken in `location` at `taken` belongs to this trip.
+    pub fn includes(&self, location: &Location, taken: &Taken) -> bool {
+        self.legs
+            .iter()
+            .any(|leg| leg.cities.contains(location) && leg.covers(taken))
+    }
+
+    /// Every (year, month) touched by a leg, in order.
+    pub fn months(&self) -> Vec<(i32, u32)> {
+        let mut months: Vec<(i32, u32)> = Vec::new();
+        for leg in &self.legs {
+            let mut date = leg.start.with_day(1).unwrap_or(leg.start);
+            while date <= leg.end {
+                if !months.contains(&(date.year(), date.month())) {
+                    months.push((date.year(), date.month()));
+                }
+                date = match date.checked_add_months(Months::new(1)) {
+                    Some(next) => next,
+                    None => break,
+                };
+            }
+        }
+        months
+    }
+
+    pub fn validate(&self) -> Result<()> {
+        if self.legs.is_empty() {
+            bail!("Trip \"{}\" has no legs", self.description);
+        }
+
+        let mut previous_end: Option<NaiveDate> = None;
+        for leg in &self.legs {
+            if leg.cities.is_empty() {
+                bail!(
+                    "Trip \"{}\" has a leg starting {} with no cities",
+                    self.description,
+                    leg.start
+                );
+            }
+            if leg.start > leg.end {
+                bail!(
+                    "Trip \"{}\" has a leg ending {} before it starts {}",
+                    self.description,
+                    leg.end,
+                    leg.start
+                );
+            }
+            if let Some(previous) = previous_end {
+                if leg.start < previous {
+                    bail!(
+                        "Trip \"{}\" has a leg starting {} before the previous one ends {}, legs must be in order and not overlap",
+                        self.description,
+                        leg.start,
+                        previous
+                    );
+                }
+            }
+            if let Some(track) = &leg.track {
+                self.validate_track(track)?;
+            }
+            previous_end = Some(leg.end);
+        }
+
+        for track in &self.tracks {
+            self.validate_track(track)?;
+        }
+
+        if let Some(colour) = &self.colour {
+            let hex = colour.strip_prefix('#').unwrap_or_default();
+            if hex.len() != 6 || !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+                bail!(
+                    "Trip \"{}\" has colour {}, expected #rrggbb",
+                    self.description,
+                    colour
+                );
+            }
+        }
+
+        Ok(())
+    }
+
+    fn validate_track(&self, track: &str) -> Result<()> {
+        if !Path::new(track).is_file() {
+            bail!(
+                "Trip \"{}\" has a missing track {}",
+                self.description,
+                track
+            );
+        }
+        if !is_track(Path::new(track)) {
+            bail!(
+                "Trip \"{}\" has a track {} that isn't GPX, KML or GeoJSON",
+                self.description,
+                track
+            );
+        }
+        Ok(())
+    }
+
+    /// Resolves the tracks of the trip and its legs against `root`.
+    pub fn resolve_tracks(&mut self, root: &Path) {
+        let tracks = self
+            .legs
+            .iter_mut()
+            .filter_map(|leg| leg.track.as_mut())
+            .chain(self.tracks.iter_mut());
+        for track in tracks {
+            *track = root.join(&*track).to_string_lossy().into_owned();
+        }
+    }
+
+    /// Every recording attached to the trip, leg tracks first.
+    pub fn track_files(&self) -> Vec<&str> {
+        self.legs
+            .iter()
+            .filter_map(|leg| leg.track.as_deref())
+            .chain(self.tracks.iter().map(|t| t.as_str()))
+            .collect()
+    }
+}
+
+mod day {
+    use chrono::{Datelike, Months, NaiveDate};
+    use serde::de::{Deserialize, Deserializer, Error};
+
+    fn parse<'de, D>(deserializer: D) -> Result<(NaiveDate, bool), D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let date = String::deserialize(deserializer)?;
+
+        if let Ok(day) = NaiveDate::parse_from_str(&date, "%Y-%m-%d") {
+            return Ok((day, false));
+        }
+
+        NaiveDate::parse_from_str(&format!("{}-01", date), "%Y-%m-%d")
+            .map(|first| (first, true))
+            .map_err(|_| D::Error::custom(format!("{} is not a YYYY-MM-DD or YYYY-MM date", date)))
+    }
+
+    /// A day, or the first day of a month.
+    pub fn start<'de, D>(deserializer: D) -> Result<NaiveDate, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        Ok(parse(deserializer)?.0)
+    }
+
+    /// A day, or the last day of a month.
+    pub fn end<'de, D>(deserializer: D) -> Result<NaiveDate, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let (date, whole_month) = parse(deserializer)?;
+        if whole_month {
+            date.checked_add_months(Months::new(1))
+                .and_then(|next| next.pred_opt())
+                .filter(|last| last.month() == date.month())
+                .ok_or_else(|| D::Error::custom(format!("{} has no last day", date)))
+        } else {
+            Ok(date)
+        }
     }
 }
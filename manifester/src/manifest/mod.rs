@@ -1,41 +1,346 @@
-use anyhow::{anyhow, Error, Result};
+use anyhow::{anyhow, bail, Error, Result};
+use chrono::{Datelike, NaiveDate};
 
-use globwalk::DirEntry;
 use image::imageops::FilterType::Lanczos3;
-use image::GenericImageView;
 use indicatif::{ProgressBar, ProgressStyle};
+use rayon::prelude::*;
 use std::collections::BTreeMap;
 use std::fmt;
-use std::fs::{File, OpenOptions};
+use std::fs::{self, File};
 use std::io::{Read, Write};
+use std::path::Path;
 use std::process::Command;
 use std::str::FromStr;
-use std::thread;
-use std::time::Duration;
 use url::Url;
 
 use super::utils::*;
 
+use boundaries::{read_boundaries, to_topojson, Boundaries};
+mod boundaries;
+
+mod compress;
+
+use deploy::Deploy;
+pub use deploy::DeployTarget;
+mod deploy;
+
+mod directory;
+
+pub use export::ExportFormat;
+mod export;
+
+mod fingerprint;
+
+use geocode::{geocode_cities, write_cities};
+mod geocode;
+
+pub(crate) use gallery::original_of;
+pub use gallery::GalleryImage;
+use gallery::{derivative_paths, trip_photos};
+mod gallery;
+
 use map::*;
+pub use map::{Country, Location, Taken};
 mod map;
 
-use nominatim::Nominatim;
+use nominatim::Geocoding;
 mod nominatim;
 
+use pipeline::Pipeline;
+pub use pipeline::Profile;
+mod pipeline;
+
+mod s3;
+
+pub use route::{great_circle, split_antimeridian, Position};
+use route::{trip_path, Routing};
+mod route;
+
+use stats::{lifetime_statistics, trip_statistics, Statistics};
+mod stats;
+
+pub use project::{Config, Project};
+mod project;
+
+mod suggest;
+
+pub use watch::watch;
+mod watch;
+
+use track::{read_track, TrackStats};
+pub use track::{simplify, TrackPoint};
+mod track;
+
 const CCA3_JSON: &str = include_str!("../world/cca3.json");
-const MANIFEST_YAML: &str = include_str!("../world/manifest.yaml");
+/// Names Manifest.elm already gives its own types, record constructors and months, and the
+/// constructors every Elm module imports, which a region or trip constructor would clash with.
+const RESERVED_CONSTRUCTORS: &[&str] = &[
+    "Country",
+    "Region",
+    "Location",
+    "LocationInformation",
+    "Trip",
+    "TripInformation",
+    "Leg",
+    "Year",
+    "Month",
+    "Date",
+    "Day",
+    "Image",
+    "Statistics",
+    "Jan",
+    "Feb",
+    "Mar",
+    "Apr",
+    "May",
+    "Jun",
+    "Jul",
+    "Aug",
+    "Sep",
+    "Oct",
+    "Nov",
+    "Dec",
+    "Just",
+    "Nothing",
+    "Ok",
+    "Err",
+    "True",
+    "False",
+    "LT",
+    "EQ",
+    "GT",
+];
+#[allow(dead_code)]
 const ATTRIBUTION_YAML: &str = include_str!("../world/attribution.yaml");
 
-lazy_static! {
-    static ref CCA3: CountryCode = serde_json::from_str(&CCA3_JSON).unwrap();
-    static ref MANIFEST: Manifest = serde_yaml::from_str(&MANIFEST_YAML).unwrap();
-    static ref ATTRIBUTION: Attribution = serde_yaml::from_str(&ATTRIBUTION_YAML).unwrap();
-}
-
+/// manifest.yaml: the places photos are filed under and the trips taken to them.
 #[derive(Debug, Serialize, Deserialize)]
-struct Manifest {
-    places: BTreeMap<Country, BTreeMap<Location, Option<String>>>,
+pub struct Manifest {
+    /// Country names to cca3 codes.
+    #[serde(skip)]
+    codes: BTreeMap<String, String>,
+    /// Read by `load` once the codes of the countries are known.
+    #[serde(skip_deserializing)]
+    places: BTreeMap<Country, Places>,
     trips: Vec<Trip>,
+    #[serde(default)]
+    routing: Routing,
+    /// How places without coordinates are looked up.
+    #[serde(default)]
+    geocoding: Geocoding,
+    /// Metres a simplified track may stray from the recording.
+    #[serde(default = "default_track_tolerance")]
+    track_tolerance: f64,
+    /// Country outlines for countries.json, the layer is skipped without them.
+    #[serde(default)]
+    boundaries: Option<Boundaries>,
+    /// How the site is built once the manifest is.
+    #[serde(default)]
+    build: Pipeline,
+    /// Where the built site is published.
+    #[serde(default)]
+    deploy: Deploy,
+}
+
+/// Colours for trips without their own, picked by `trip_colour`.
+const TRIP_COLOURS: [&str; 10] = [
+    "#e6194b", "#3cb44b", "#4363d8", "#f58231", "#911eb4", "#42d4f4", "#f032e6", "#9a6324",
+    "#469990", "#808000",
+];
+
+/// The palette colour of a trip, from an FNV-1a hash of its id so that adding or removing other trips
+/// doesn't repaint it.
+fn trip_colour(id: &str) -> &'static str {
+    let hash = id.bytes().fold(0xcbf29ce484222325u64, |hash, byte| {
+        (hash ^ byte as u64).wrapping_mul(0x100000001b3)
+    });
+    TRIP_COLOURS[(hash % TRIP_COLOURS.len() as u64) as usize]
+}
+
+fn default_track_tolerance() -> f64 {
+    10.0
+}
+
+fn round(value: f64, places: i32) -> f64 {
+    let scale = 10f64.powi(places);
+    (value * scale).round() / scale
+}
+
+impl Manifest {
+    /// Reads the manifest and points the cities of every trip at their places. Cities that can't be
+    /// found are left for `validate` to report.
+    pub fn load(yaml: &str) -> Result<Self> {
+        let codes = serde_json::from_str::<CountryCode>(CCA3_JSON)?.codes;
+
+        let mut yaml: serde_yaml::Mapping = serde_yaml::from_str(yaml)?;
+        let places = yaml
+            .remove("places")
+            .ok_or(anyhow!("The manifest has no places"))?;
+        let mut manifest: Manifest = serde_yaml::from_value(serde_yaml::Value::Mapping(yaml))?;
+        manifest.places = read_places(places, &codes)?;
+        manifest.codes = codes;
+
+        let places = &manifest.places;
+        for city in manifest
+            .trips
+            .iter_mut()
+            .flat_map(|trip| trip.legs.iter_mut())
+            .flat_map(|leg| leg.cities.iter_mut())
+        {
+            if let Ok(location) = find_location(places, &city.name()) {
+                *city = location.clone();
+            }
+        }
+
+        Ok(manifest)
+    }
+
+    pub fn read<P>(path: P) -> Result<Self>
+    where
+        P: AsRef<Path>,
+    {
+        let yaml = fs::read_to_string(&path)
+            .map_err(|e| anyhow!("Could not read {}: {}", path.as_ref().display(), e))?;
+        Self::load(&yaml)
+    }
+
+    /// Makes the paths in the manifest, which are relative to the repository, relative to where the
+    /// manifester runs.
+    fn resolve_paths(&mut self, root: &Path) {
+        for trip in &mut self.trips {
+            trip.resolve_tracks(root);
+        }
+        if let Some(boundaries) = &mut self.boundaries {
+            boundaries.source = root.join(&boundaries.source);
+        }
+        if let Some(directory) = &mut self.deploy.directory {
+            directory.root = root.join(&directory.root);
+        }
+    }
+
+    /// Trip ids end up in URLs and trip and region constructors share the Manifest.elm namespace with
+    /// countries and locations, so all of them must be well formed and unique.
+    pub fn validate(&self) -> Result<()> {
+        self.geocoding.validate()?;
+        if let Some(s3) = &self.deploy.s3 {
+            s3.validate()?;
+        }
+
+        let mut constructors: BTreeMap<String, String> = RESERVED_CONSTRUCTORS
+            .iter()
+            .map(|name| (name.to_string(), "Manifest.elm itself".to_string()))
+            .collect();
+
+        for (country, places) in &self.places {
+            if to_slug(&country.name()).is_empty() {
+                bail!(
+                    "Country \"{}\" needs a name with letters or digits",
+                    country.name()
+                );
+            }
+            constructors.insert(country.to_string(), format!("country {}", country.name()));
+            for location in places.locations.keys() {
+                constructors.insert(
+                    location.to_string(),
+                    format!("location {}", location.name()),
+                );
+            }
+        }
+
+        for region in self
+            .places
+            .values()
+            .flat_map(|places| places.regions.keys())
+        {
+            if region.id().is_empty() {
+                bail!(
+                    "Region \"{}\" needs a name with letters or digits",
+                    region.name()
+                );
+            }
+            if let Some(other) =
+                constructors.insert(region.to_string(), format!("region {}", region.name()))
+            {
+                bail!(
+                    "Region \"{}\" becomes the Elm constructor {} which is already used by {}",
+                    region.name(),
+                    region,
+                    other
+                );
+            }
+        }
+
+        let mut ids: BTreeMap<String, &str> = BTreeMap::new();
+
+        for trip in &self.trips {
+            trip.validate()?;
+
+            for city in trip.cities() {
+                if !city.is_resolved() {
+                    if let Err(error) = find_location(&self.places, &city.name()) {
+                        bail!("Trip \"{}\" visits {}", trip.description, error);
+                    }
+                }
+            }
+
+            let id = trip.id();
+
+            if let Some(explicit) = &trip.id {
+                if !is_slug(explicit) {
+                    bail!(
+                        "Trip \"{}\" has id \"{}\", ids may only contain lowercase letters, digits and single hyphens",
+                        trip.description,
+                        explicit
+                    );
+                }
+            } else if id.is_empty() {
+                bail!(
+                    "Trip \"{}\" needs an explicit id, no slug can be made from its description",
+                    trip.description
+                );
+            }
+
+            if let Some(other) = ids.insert(id.clone(), &trip.description) {
+                bail!(
+                    "Trips \"{}\" and \"{}\" share the id \"{}\", give one of them an explicit id",
+                    other,
+                    trip.description,
+                    id
+                );
+            }
+
+            let constructor = trip.id_string();
+            if let Some(other) = constructors.insert(constructor.clone(), format!("trip {}", id)) {
+                bail!(
+                    "Trip \"{}\" becomes the Elm constructor {} which is already used by {}, give it an explicit id",
+                    trip.description,
+                    constructor,
+                    other
+                );
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl Manifest {
+    /// The location whose photos are filed in the `country` and `location` gallery folders.
+    fn folder_location(&self, country: &str, location: &str) -> Result<&Location> {
+        self.places
+            .iter()
+            .filter(|(c, _)| c.name().replace(' ', "_") == country)
+            .flat_map(|(_, places)| places.locations.keys())
+            .find(|l| l.folder() == location)
+            .ok_or(anyhow!("{}/{} is not in places", country, location))
+    }
+
+    /// The region `location` is filed under, if its country has regions.
+    fn region(&self, location: &Location) -> Option<&Region> {
+        self.places
+            .values()
+            .find_map(|places| places.region(location))
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -78,6 +383,7 @@ mod codes {
 
 #[derive(Debug, Serialize, Deserialize)]
 #[serde(rename_all = "PascalCase")]
+#[allow(dead_code)]
 struct Attribution {
     marked: bool,
     usage_terms: String,
@@ -88,10 +394,8 @@ struct Attribution {
     attribution_name: String,
 }
 
-
-
 #[derive(Debug, Serialize, Deserialize)]
-pub struct LocationInformation {
+struct LocationInformation {
     id: Location,
     name: String,
     country: Country,
@@ -99,223 +403,280 @@ pub struct LocationInformation {
 }
 
 impl LocationInformation {
-    pub fn new<P1, P2>(out_cities: P1, out_trips: P2) -> Result<Vec<Self>>
-    where
-        P1: AsRef<std::path::Path>,
-        P2: AsRef<std::path::Path>,
-    {
-        let pause = Duration::from_secs(1);
+    fn new(manifest: &Manifest, features: &[Feature]) -> Result<Vec<Self>> {
+        let mut locations_details: Vec<LocationInformation> = Vec::new();
 
-        let cities_buffer = OpenOptions::new()
-            .read(true)
-            .write(false)
-            .create(false)
-            .open(&out_cities);
+        for (country, places) in &manifest.places {
+            for location in places.locations.keys() {
+                locations_details.push(LocationInformation {
+                    id: location.clone(),
+                    name: location.name(),
+                    country: country.clone(),
+                    coordinates: location.feature_coordinates(features)?,
+                });
+            }
+        }
 
-        let mut locations_details: Vec<LocationInformation> = Vec::new();
+        Ok(locations_details)
+    }
+}
 
-        match cities_buffer {
-            Ok(buffer) => {
-                // Add new info to cities.json
-                let mut cities: FeatureCollection = serde_json::from_reader(&buffer)?;
-
-                let (new_countries, new_locations) = get_new_places(&cities);
-
-                for (country, locations) in &MANIFEST.places {
-                    for (location, local_name) in
-                        locations.iter().filter(|(l, _)| **l != Location::Local)
-                    {
-                        let coordinates = if new_locations.contains(location) {
-                            let coords = Nominatim::search(&format!(
-                                "{}, {}",
-                                location.name(),
-                                country.name()
-                            ))?;
-
-                            thread::sleep(pause);
-
-                            let properties = Properties {
-                                name: location.name(),
-                                localname: local_name.to_owned(),
-                                country: Some(country.code(&CCA3.codes)?),
-                            };
-
-                            let coordinates =
-                                vec![coords.lon().parse::<f32>()?, coords.lat().parse::<f32>()?];
-
-                            let geometry = Geometry {
-                                type_: "Point".to_string(),
-                                coordinates: Coordinates::Point(coordinates.clone()),
-                            };
-
-                            cities.features.push(Feature {
-                                type_: "Feature".to_string(),
-                                properties,
-                                geometry,
-                            });
-
-                            coordinates
-                        } else {
-                            location.feature_coordinates(&cities.features)?
-                        };
-
-                        locations_details.push(LocationInformation {
-                            id: location.clone(),
-                            name: location.name(),
-                            country: country.clone(),
-                            coordinates,
-                        });
-                    }
-                }
+fn write_outputs(project: &Project, images: &[GalleryImage]) -> Result<()> {
+    let config = &project.config;
+    let mut cities = geocode_cities(&project.manifest, &config.cities)?;
+    let locations_information = LocationInformation::new(&project.manifest, &cities.features)?;
 
-                if new_countries.len() + new_locations.len() > 0 {
-                    let cities_writer = OpenOptions::new()
-                        .write(true)
-                        .truncate(true) //We must truncate the file before writing the new data.
-                        .open(&out_cities)?;
-                    serde_json::to_writer(&cities_writer, &cities)?;
-                }
+    // Photo counts and visits change with every gallery update, so cities.json is rewritten each build.
+    update_city_properties(project, &mut cities.features, images);
 
-                write_trip(&cities.features, out_trips)?;
-            }
-            Err(_) => {
-                let mut features: Vec<Feature> = Vec::new();
-
-                for (country, locations) in &MANIFEST.places {
-                    for (location, local_name) in
-                        locations.iter().filter(|(l, _)| **l != Location::Local)
-                    {
-                        let coords =
-                            Nominatim::search(&format!("{}, {}", location.name(), country.name()))?;
-                        thread::sleep(pause);
-
-                        let properties = Properties {
-                            name: location.name(),
-                            localname: local_name.to_owned(),
-                            country: Some(country.code(&CCA3.codes)?),
-                        };
-                        let coordinates =
-                            vec![coords.lon().parse::<f32>()?, coords.lat().parse::<f32>()?];
-                        let geometry = Geometry {
-                            type_: "Point".to_string(),
-                            coordinates: Coordinates::Point(coordinates.clone()),
-                        };
-
-                        println!("{} {:?}", location.to_string(), geometry.coordinates);
-                        features.push(Feature {
-                            type_: "Feature".to_string(),
-                            properties,
-                            geometry,
-                        });
-                        locations_details.push(LocationInformation {
-                            id: location.clone(),
-                            name: location.name(),
-                            country: country.clone(),
-                            coordinates,
-                        });
-                    }
-                }
+    write_cities(&config.cities, &cities)?;
 
-                write_trip(&features, out_trips)?;
+    write_trip(project, &cities.features, images, &config.trips)?;
+    write_boundaries(project, images, &config.countries)?;
 
-                let cities = FeatureCollection {
-                    type_: "FeatureCollection".to_string(),
-                    features,
-                };
+    construct_manifest(project, images, &config.elm, &locations_information)
+}
 
-                let cities_create = File::create(&out_cities)?;
-                serde_json::to_writer(&cities_create, &cities)?;
-            }
+/// Refreshes the gallery derived properties of every city feature: how many photos were taken there,
+/// the first and last visits, the trips that pass through and the most recent photo as a cover.
+fn update_city_properties(project: &Project, features: &mut [Feature], images: &[GalleryImage]) {
+    for feature in features.iter_mut() {
+        let location = match project
+            .manifest
+            .places
+            .values()
+            .flat_map(|places| places.locations.keys())
+            .find(|location| feature.properties.id.as_deref() == Some(location.id()))
+        {
+            Some(location) => location,
+            None => continue,
         };
 
-        Ok(locations_details)
+        let photos = images
+            .iter()
+            .filter(|image| image.location == *location)
+            .collect::<Vec<&GalleryImage>>();
+
+        let trips = project
+            .manifest
+            .trips
+            .iter()
+            .filter(|trip| trip.cities().contains(&location))
+            .map(|trip| trip.id())
+            .collect::<Vec<String>>();
+
+        feature.properties.region = project
+            .manifest
+            .region(location)
+            .map(|region| region.name());
+        set_visits(&mut feature.properties, photos, trips);
     }
 }
 
-fn get_new_places(cities: &FeatureCollection) -> (Vec<Country>, Vec<Location>) {
-    let cities_country_codes = cities
-        .features
-        .iter()
-        .map(|f| f.properties.country.clone().unwrap())
-        .collect::<Vec<String>>();
-
-    let mut cities_countries: Vec<String> = Vec::new();
-
-    for c in cities_country_codes {
-        for (name, code) in &CCA3.codes {
-            if c == *code {
-                let mut identifier = name.clone().to_string();
-                identifier.retain(|c| c != ' ');
-                cities_countries.push(identifier);
-                break;
-            }
-        }
-    }
+/// Photo count, first and last visit, trips and cover of a city or country, from the photos taken there.
+fn set_visits(properties: &mut Properties, mut photos: Vec<&GalleryImage>, trips: Vec<String>) {
+    photos.sort_by_key(|image| image.taken.first_day());
 
-    let config_countries = MANIFEST
-        .places
-        .iter()
-        .map(|(name, _)| name)
-        .cloned()
-        .collect::<Vec<Country>>();
+    properties.photos = Some(photos.len());
+    properties.first_visit = photos.first().map(|image| image.taken.to_string());
+    properties.last_visit = photos.last().map(|image| image.taken.to_string());
+    properties.cover = photos.last().map(|image| image.gallery_path().to_string());
+    properties.trips = Some(trips);
+}
 
-    let mut new_countries: Vec<Country> = Vec::new();
-    for ctry in config_countries {
-        if !cities_countries.contains(&ctry.to_string()) {
-            new_countries.push(ctry);
-        }
+/// Writes the outlines of the countries in places with their visits, and a TopoJSON copy if asked for.
+fn write_boundaries<P>(project: &Project, images: &[GalleryImage], out_countries: P) -> Result<()>
+where
+    P: AsRef<std::path::Path>,
+{
+    let boundaries = match &project.manifest.boundaries {
+        Some(boundaries) => boundaries,
+        None => return Ok(()),
+    };
+
+    let mut countries: BTreeMap<String, &Country> = BTreeMap::new();
+    for country in project.manifest.places.keys() {
+        countries.insert(country.code(&project.manifest.codes)?, country);
     }
 
-    let cities_locations = cities
-        .features
-        .iter()
-        .map(|f| to_location_identfier_string(&f.properties.name))
-        .collect::<Vec<String>>();
+    let outlines = read_boundaries(boundaries, &countries.keys().cloned().collect())?;
+
+    let mut features: Vec<Feature> = Vec::new();
+    for (code, country) in countries {
+        let polygons = match outlines.get(&code) {
+            Some(polygons) => polygons,
+            None => {
+                println!(
+                    "{} has no boundary in {}",
+                    country.name(),
+                    boundaries.source.display()
+                );
+                continue;
+            }
+        };
 
-    let config_locations = MANIFEST
-        .places
-        .iter()
-        .map(|(_, locations)| {
-            locations
-                .iter()
-                .map(|(name, _)| name)
-                .cloned()
-                .collect::<Vec<Location>>()
-        })
-        .flatten()
-        .collect::<Vec<Location>>();
+        let places = &project.manifest.places[country];
+        let photos = images
+            .iter()
+            .filter(|image| places.locations.contains_key(&image.location))
+            .collect::<Vec<&GalleryImage>>();
+        let trips = project
+            .manifest
+            .trips
+            .iter()
+            .filter(|trip| {
+                trip.cities()
+                    .iter()
+                    .any(|c| places.locations.contains_key(c))
+            })
+            .map(|trip| trip.id())
+            .collect::<Vec<String>>();
+
+        let mut properties = Properties {
+            name: country.name(),
+            localname: places.local_name.clone(),
+            country: Some(code.clone()),
+            id: Some(code),
+            cities: Some(places.locations.len()),
+            ..Default::default()
+        };
+        set_visits(&mut properties, photos, trips);
 
-    let mut new_locations: Vec<Location> = Vec::new();
+        features.push(Feature {
+            type_: "Feature".to_string(),
+            properties,
+            geometry: Geometry {
+                type_: "MultiPolygon".to_string(),
+                coordinates: Coordinates::MultiPolygon(
+                    polygons
+                        .iter()
+                        .map(|polygon| {
+                            polygon
+                                .iter()
+                                .map(|ring| {
+                                    ring.iter()
+                                        .map(|&(lon, lat)| vec![lon as f32, lat as f32])
+                                        .collect()
+                                })
+                                .collect()
+                        })
+                        .collect(),
+                ),
+            },
+        });
+    }
 
-    for loc in config_locations {
-        if !cities_locations.contains(&loc.to_string()) && loc != Location::Local {
-            new_locations.push(loc);
+    if boundaries.topojson {
+        let out_topojson = out_countries.as_ref().with_extension("topojson");
+        match to_topojson(&features) {
+            Some(topojson) => serde_json::to_writer(&File::create(out_topojson)?, &topojson)?,
+            None => println!(
+                "No country has a boundary, {} is not written",
+                out_topojson.display()
+            ),
         }
     }
-    (new_countries, new_locations)
+
+    let countries_json = FeatureCollection {
+        type_: "FeatureCollection".to_string(),
+        features,
+    };
+    let countries_create = File::create(&out_countries)?;
+    serde_json::to_writer(&countries_create, &countries_json)?;
+
+    Ok(())
 }
 
-fn write_trip<P>(features: &[Feature], trips_json: P) -> Result<()>
+fn write_trip<P>(
+    project: &Project,
+    features: &[Feature],
+    images: &[GalleryImage],
+    trips_json: P,
+) -> Result<()>
 where
     P: AsRef<std::path::Path>,
 {
     let mut trip_features: Vec<Feature> = Vec::new();
+    let trips = chronological_trips(&project.manifest);
+    let photos = trip_photos(images, &trips)?;
+
+    for trip in &trips {
+        let tolerance = trip.tolerance.unwrap_or(project.manifest.track_tolerance);
+
+        let mut parts = trip_path(trip, features, &project.manifest.routing, tolerance)?;
+
+        let mut track_stats = TrackStats::default();
+        for file in trip.track_files() {
+            let segments = read_track(Path::new(file))?;
+            for segment in &segments {
+                track_stats.add(segment);
+            }
+            if !trip
+                .legs
+                .iter()
+                .any(|leg| leg.track.as_deref() == Some(file))
+            {
+                parts.extend(
+                    segments
+                        .iter()
+                        .flat_map(|segment| split_antimeridian(&simplify(segment, tolerance))),
+                );
+            }
+        }
+
+        let stats = trip_statistics(
+            &project.manifest.places,
+            trip,
+            |location| {
+                let coordinates = location.feature_coordinates(features)?;
+                Ok((coordinates[0] as f64, coordinates[1] as f64))
+            },
+            photos.get(&trip.id()).map_or(0, |images| images.len()),
+        )?;
 
-    for trip in &MANIFEST.trips {
+        let has_tracks = !trip.track_files().is_empty();
         let properties = Properties {
             name: trip.name.clone(),
-            localname: None,
-            country: None,
+            id: Some(trip.id()),
+            distance: Some(round(stats.distance, 1)),
+            countries: Some(stats.countries),
+            cities: Some(stats.cities),
+            days: Some(stats.days),
+            photos: Some(stats.photos),
+            start: trip.start().map(|start| start.to_string()),
+            end: trip.end().map(|end| end.to_string()),
+            colour: Some(
+                trip.colour
+                    .clone()
+                    .unwrap_or_else(|| trip_colour(&trip.id()).to_string()),
+            ),
+            track_distance: has_tracks.then_some(round(track_stats.distance, 1)),
+            ascent: has_tracks.then_some(round(track_stats.ascent, 0)),
+            descent: has_tracks.then_some(round(track_stats.descent, 0)),
+            highest: track_stats.highest.map(|h| round(h, 0)),
+            lowest: track_stats.lowest.map(|l| round(l, 0)),
+            ..Default::default()
         };
 
-        let mut coords: Vec<Vec<f32>> = Vec::new();
-        for city in &trip.cities {
-            coords.push(city.feature_coordinates(&features)?);
-        }
-
-        let geometry = Geometry {
-            type_: "LineString".to_string(),
-            coordinates: Coordinates::LineString(coords),
+        let mut parts = parts
+            .into_iter()
+            .map(|part| {
+                part.into_iter()
+                    .map(|(lon, lat)| vec![lon as f32, lat as f32])
+                    .collect::<Vec<Vec<f32>>>()
+            })
+            .collect::<Vec<Vec<Vec<f32>>>>();
+
+        let geometry = if parts.len() == 1 {
+            Geometry {
+                type_: "LineString".to_string(),
+                coordinates: Coordinates::LineString(parts.remove(0)),
+            }
+        } else {
+            Geometry {
+                type_: "MultiLineString".to_string(),
+                coordinates: Coordinates::MultiLineString(parts),
+            }
         };
 
         trip_features.push(Feature {
@@ -335,7 +696,9 @@ where
     Ok(())
 }
 
-pub fn construct_manifest<P>(
+fn construct_manifest<P>(
+    project: &Project,
+    images: &[GalleryImage],
     out_elm: P,
     locations_information: &[LocationInformation],
 ) -> Result<()>
@@ -346,34 +709,75 @@ where
 
     let mut manifest = File::create(&out_elm)?;
 
-    writeln!(manifest, "module Manifest exposing (Country(..), Date, Image, Location(..), Month(..), Trip(..), Year, countryId, countryList, countryLocalName, countryName, locationInformation, locationList, locationLocalName, manifest, stringToCountry, stringToLocation, stringToTrip, tripInformation, tripList)")?;
+    writeln!(manifest, "module Manifest exposing (Country(..), Date, Day, Image, Leg, Location(..), Month(..), Region(..), Statistics, Trip(..), Year, countryId, countryList, countryLocalName, countryLocation, countryName, locationId, locationInformation, locationList, locationLocalName, locationRegion, manifest, regionCountry, regionId, regionList, regionLocalName, regionLocations, regionName, stringToCountry, lifetimeStatistics, stringToLocation, stringToRegion, stringToTrip, tripId, tripImages, tripInformation, tripList, tripStatistics)")?;
 
     writeln!(manifest, "-- COUNTRIES")?;
-    write_countries(&mut manifest)?;
+    write_countries(&mut manifest, project)?;
+
+    writeln!(manifest, "-- REGIONS")?;
+    write_regions(&mut manifest, project)?;
 
     writeln!(manifest, "-- LOCATIONS")?;
-    write_locations(&mut manifest, locations_information)?;
+    write_locations(&mut manifest, project, locations_information)?;
 
     writeln!(manifest, "-- TRIPS")?;
-    write_trips(&mut manifest)?;
+    write_trips(&mut manifest, project)?;
 
     writeln!(manifest, "-- MANIFEST")?;
-    write_manifest(&mut manifest)?;
+    let trip_images = write_manifest(&mut manifest, project, images)?;
+
+    writeln!(manifest, "-- STATISTICS")?;
+    write_statistics(
+        &mut manifest,
+        project,
+        locations_information,
+        &trip_images,
+        images.len(),
+    )?;
+
+    if project.config.format_elm {
+        elm_format(out_elm.as_ref())?;
+    }
+    Ok(())
+}
+
+/// A command for `program` installed through npm, which only puts a .cmd shim on the Windows path.
+fn npm_command(program: &str) -> Command {
+    if cfg!(windows) {
+        let mut command = Command::new("cmd");
+        command.args(["/c", program]);
+        command
+    } else {
+        Command::new(program)
+    }
+}
 
-    Command::new("cmd")
-        .args(&["/c", "elm-format"])
+/// Formats the generated module in place. A missing elm-format leaves it unformatted, it still compiles.
+fn elm_format(path: &Path) -> Result<()> {
+    match npm_command("elm-format")
         .arg("--elm-version=0.19")
         .arg("--yes")
-        .arg("../src/Manifest.elm")
-        .status()?;
-    Ok(())
+        .arg(path)
+        .status()
+    {
+        Ok(status) if status.success() => Ok(()),
+        Ok(status) => bail!("elm-format {} failed with {}", path.display(), status),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+            eprintln!(
+                "elm-format not found, {} is left unformatted",
+                path.display()
+            );
+            Ok(())
+        }
+        Err(e) => Err(e.into()),
+    }
 }
 
-fn write_countries(manifest: &mut File) -> Result<()> {
+fn write_countries(manifest: &mut File, project: &Project) -> Result<()> {
     writeln!(manifest, "type Country")?;
 
     let mut idx = 0;
-    for (cntry, _) in &MANIFEST.places {
+    for cntry in project.manifest.places.keys() {
         if idx != 0 {
             writeln!(manifest, "    | {}", cntry)?;
         } else {
@@ -385,7 +789,7 @@ fn write_countries(manifest: &mut File) -> Result<()> {
     writeln!(manifest, "countryList : List Country")?;
     writeln!(manifest, "countryList =")?;
     idx = 0;
-    for (cntry, _) in &MANIFEST.places {
+    for cntry in project.manifest.places.keys() {
         if idx != 0 {
             writeln!(manifest, "    , {}", cntry)?;
         } else {
@@ -398,15 +802,19 @@ fn write_countries(manifest: &mut File) -> Result<()> {
     writeln!(manifest, "countryId : Country -> String")?;
     writeln!(manifest, "countryId country =")?;
     writeln!(manifest, "    case country of")?;
-    for (cntry, _) in &MANIFEST.places {
+    for cntry in project.manifest.places.keys() {
         writeln!(manifest, "        {} ->", cntry)?;
-        writeln!(manifest, "            \"{}\"", cntry.code(&CCA3.codes)?)?;
+        writeln!(
+            manifest,
+            "            \"{}\"",
+            cntry.code(&project.manifest.codes)?
+        )?;
     }
 
     writeln!(manifest, "countryName : Country -> String")?;
     writeln!(manifest, "countryName country =")?;
     writeln!(manifest, "    case country of")?;
-    for (cntry, _) in &MANIFEST.places {
+    for cntry in project.manifest.places.keys() {
         writeln!(manifest, "        {} ->", cntry)?;
         writeln!(manifest, "            \"{}\"", cntry.name())?;
     }
@@ -414,7 +822,7 @@ fn write_countries(manifest: &mut File) -> Result<()> {
     writeln!(manifest, "stringToCountry : String -> Maybe Country")?;
     writeln!(manifest, "stringToCountry country =")?;
     writeln!(manifest, "    case country of")?;
-    for (cntry, _) in &MANIFEST.places {
+    for cntry in project.manifest.places.keys() {
         writeln!(manifest, "        \"{}\" ->", cntry.name())?;
         writeln!(manifest, "            Just {}", cntry)?;
     }
@@ -424,8 +832,8 @@ fn write_countries(manifest: &mut File) -> Result<()> {
     writeln!(manifest, "countryLocalName : Country -> Maybe String")?;
     writeln!(manifest, "countryLocalName country =")?;
     writeln!(manifest, "    case country of")?;
-    for (cntry, locations) in &MANIFEST.places {
-        if let Some(Some(local)) = locations.get(&Location::Local) {
+    for (cntry, places) in &project.manifest.places {
+        if let Some(local) = &places.local_name {
             writeln!(manifest, "        {} ->", cntry)?;
             writeln!(manifest, "            Just \"{}\"", local)?;
         };
@@ -436,21 +844,173 @@ fn write_countries(manifest: &mut File) -> Result<()> {
     Ok(())
 }
 
+/// Regions are optional, a manifest without any still gets the functions over an uninhabited `Region`.
+fn write_regions(manifest: &mut File, project: &Project) -> Result<()> {
+    let regions = project
+        .manifest
+        .places
+        .iter()
+        .flat_map(|(country, places)| {
+            places
+                .regions
+                .iter()
+                .map(move |(region, local_name)| (country, places, region, local_name))
+        })
+        .collect::<Vec<(&Country, &Places, &Region, &Option<String>)>>();
+
+    if regions.is_empty() {
+        writeln!(manifest, "type Region")?;
+        writeln!(manifest, "    = Region Never")?;
+        writeln!(manifest, "regionList : List Region")?;
+        writeln!(manifest, "regionList =")?;
+        writeln!(manifest, "    []")?;
+        for (function, result) in [
+            ("regionId", "String"),
+            ("regionName", "String"),
+            ("regionLocalName", "Maybe String"),
+            ("regionCountry", "Country"),
+            ("regionLocations", "List Location"),
+        ] {
+            writeln!(manifest, "{} : Region -> {}", function, result)?;
+            writeln!(manifest, "{} (Region region) =", function)?;
+            writeln!(manifest, "    never region")?;
+        }
+        writeln!(manifest, "stringToRegion : String -> Maybe Region")?;
+        writeln!(manifest, "stringToRegion _ =")?;
+        writeln!(manifest, "    Nothing")?;
+        writeln!(manifest, "locationRegion : Location -> Maybe Region")?;
+        writeln!(manifest, "locationRegion _ =")?;
+        writeln!(manifest, "    Nothing")?;
+        return Ok(());
+    }
+
+    writeln!(manifest, "type Region")?;
+    for (idx, (_, _, region, _)) in regions.iter().enumerate() {
+        if idx != 0 {
+            writeln!(manifest, "    | {}", region)?;
+        } else {
+            writeln!(manifest, "    = {}", region)?;
+        }
+    }
+
+    writeln!(manifest, "regionList : List Region")?;
+    writeln!(manifest, "regionList =")?;
+    write!(manifest, "    [ ")?;
+    write_list(manifest, regions.iter().map(|(_, _, region, _)| region))?;
+    writeln!(manifest, " ]")?;
+
+    writeln!(manifest, "regionId : Region -> String")?;
+    writeln!(manifest, "regionId region =")?;
+    writeln!(manifest, "    case region of")?;
+    for (_, _, region, _) in &regions {
+        writeln!(manifest, "        {} ->", region)?;
+        writeln!(manifest, "            \"{}\"", region.id())?;
+    }
+
+    writeln!(manifest, "regionName : Region -> String")?;
+    writeln!(manifest, "regionName region =")?;
+    writeln!(manifest, "    case region of")?;
+    for (_, _, region, _) in &regions {
+        writeln!(manifest, "        {} ->", region)?;
+        writeln!(manifest, "            \"{}\"", region.name())?;
+    }
+
+    writeln!(manifest, "regionLocalName : Region -> Maybe String")?;
+    writeln!(manifest, "regionLocalName region =")?;
+    writeln!(manifest, "    case region of")?;
+    for (_, _, region, local_name) in &regions {
+        if let Some(local) = local_name {
+            writeln!(manifest, "        {} ->", region)?;
+            writeln!(manifest, "            Just \"{}\"", local)?;
+        }
+    }
+    if regions
+        .iter()
+        .any(|(_, _, _, local_name)| local_name.is_none())
+    {
+        writeln!(manifest, "        _ ->")?;
+        writeln!(manifest, "            Nothing")?;
+    }
+
+    writeln!(manifest, "regionCountry : Region -> Country")?;
+    writeln!(manifest, "regionCountry region =")?;
+    writeln!(manifest, "    case region of")?;
+    for (country, _, region, _) in &regions {
+        writeln!(manifest, "        {} ->", region)?;
+        writeln!(manifest, "            {}", country)?;
+    }
+
+    writeln!(manifest, "regionLocations : Region -> List Location")?;
+    writeln!(manifest, "regionLocations region =")?;
+    writeln!(manifest, "    case region of")?;
+    for (_, places, region, _) in &regions {
+        writeln!(manifest, "        {} ->", region)?;
+        write!(manifest, "            [ ")?;
+        write_list(
+            manifest,
+            places
+                .location_regions
+                .iter()
+                .filter(|(_, r)| r == region)
+                .map(|(location, _)| location),
+        )?;
+        writeln!(manifest, " ]")?;
+    }
+
+    writeln!(manifest, "stringToRegion : String -> Maybe Region")?;
+    writeln!(manifest, "stringToRegion region =")?;
+    writeln!(manifest, "    case region of")?;
+    for (_, _, region, _) in &regions {
+        writeln!(manifest, "        \"{}\" ->", region.name())?;
+        writeln!(manifest, "            Just {}", region)?;
+    }
+    writeln!(manifest, "        _ ->")?;
+    writeln!(manifest, "            Nothing")?;
+
+    let location_regions = project
+        .manifest
+        .places
+        .values()
+        .flat_map(|places| places.location_regions.iter())
+        .collect::<Vec<(&Location, &Region)>>();
+    let unfiled = project
+        .manifest
+        .places
+        .values()
+        .flat_map(|places| places.locations.keys())
+        .any(|location| project.manifest.region(location).is_none());
+
+    writeln!(manifest, "locationRegion : Location -> Maybe Region")?;
+    writeln!(manifest, "locationRegion location =")?;
+    writeln!(manifest, "    case location of")?;
+    for (location, region) in location_regions {
+        writeln!(manifest, "        {} ->", location)?;
+        writeln!(manifest, "            Just {}", region)?;
+    }
+    if unfiled {
+        writeln!(manifest, "        _ ->")?;
+        writeln!(manifest, "            Nothing")?;
+    }
+
+    Ok(())
+}
+
 fn write_locations(
     manifest: &mut File,
+    project: &Project,
     locations_information: &[LocationInformation],
 ) -> Result<()> {
-    let mut config_locations = MANIFEST
+    let mut config_locations = project
+        .manifest
         .places
-        .iter()
-        .map(|(_, locations)| {
-            locations
+        .values()
+        .flat_map(|places| {
+            places
+                .locations
                 .iter()
                 .map(|(name, loc)| (name.clone(), loc.clone()))
                 .collect::<Vec<(Location, Option<String>)>>()
         })
-        .flatten()
-        .filter(|(l, _)| *l != Location::Local)
         .collect::<Vec<(Location, Option<String>)>>();
 
     config_locations.sort();
@@ -478,16 +1038,45 @@ fn write_locations(
     }
     writeln!(manifest, "    ]")?;
 
+    writeln!(manifest, "locationId : Location -> String")?;
+    writeln!(manifest, "locationId location =")?;
+    writeln!(manifest, "    case location of")?;
+    for (loc, _) in &config_locations {
+        writeln!(manifest, "        {} ->", loc)?;
+        writeln!(manifest, "            \"{}\"", loc.id())?;
+    }
+
+    // Names shared by cities in different countries go to the first of them, countryLocation tells them apart.
+    let mut names: Vec<String> = Vec::new();
     writeln!(manifest, "stringToLocation : String -> Maybe Location")?;
     writeln!(manifest, "stringToLocation location =")?;
     writeln!(manifest, "    case location of")?;
     for (loc, _) in &config_locations {
+        if names.contains(&loc.name()) {
+            continue;
+        }
+        names.push(loc.name());
         writeln!(manifest, "        \"{}\" ->", loc.name())?;
         writeln!(manifest, "            Just {}", loc)?;
     }
     writeln!(manifest, "        _ ->")?;
     writeln!(manifest, "            Nothing")?;
 
+    writeln!(
+        manifest,
+        "countryLocation : Country -> String -> Maybe Location"
+    )?;
+    writeln!(manifest, "countryLocation country location =")?;
+    writeln!(manifest, "    case ( country, location ) of")?;
+    for (cntry, places) in &project.manifest.places {
+        for loc in places.locations.keys() {
+            writeln!(manifest, "        ( {}, \"{}\" ) ->", cntry, loc.name())?;
+            writeln!(manifest, "            Just {}", loc)?;
+        }
+    }
+    writeln!(manifest, "        _ ->")?;
+    writeln!(manifest, "            Nothing")?;
+
     writeln!(manifest, "locationLocalName : Location -> Maybe String")?;
     writeln!(manifest, "locationLocalName location =")?;
     writeln!(manifest, "    case location of")?;
@@ -515,7 +1104,7 @@ fn write_locations(
     for info in locations_information {
         let lon = info
             .coordinates
-            .get(0)
+            .first()
             .ok_or(anyhow!("No longitude value in coordinates"))?;
 
         let lat = info
@@ -526,17 +1115,23 @@ fn write_locations(
         writeln!(manifest, "    {} ->", info.id)?;
         writeln!(manifest, "        {{ name = \"{}\"", info.name)?;
         writeln!(manifest, "        , country = {}", info.country)?;
-        writeln!(manifest, "        , coordinates = ( {:.3}, {:.3} )", lon, lat)?;
+        writeln!(
+            manifest,
+            "        , coordinates = ( {:.3}, {:.3} )",
+            lon, lat
+        )?;
         writeln!(manifest, "        }}")?;
     }
     Ok(())
 }
 
-fn write_trips(manifest: &mut File) -> Result<()> {
+fn write_trips(manifest: &mut File, project: &Project) -> Result<()> {
+    let trips = chronological_trips(&project.manifest);
+
     writeln!(manifest, "type Trip")?;
     let mut idx = 0;
 
-    for trip in &MANIFEST.trips {
+    for trip in &trips {
         if idx != 0 {
             writeln!(manifest, "    | {}", trip.id_string())?;
         } else {
@@ -548,7 +1143,7 @@ fn write_trips(manifest: &mut File) -> Result<()> {
     writeln!(manifest, "tripList : List Trip")?;
     writeln!(manifest, "tripList =")?;
     idx = 0;
-    for trip in &MANIFEST.trips {
+    for trip in &trips {
         if idx != 0 {
             writeln!(manifest, "    , {}", trip.id_string())?;
         } else {
@@ -558,11 +1153,19 @@ fn write_trips(manifest: &mut File) -> Result<()> {
     }
     writeln!(manifest, "    ]")?;
 
+    writeln!(manifest, "tripId : Trip -> String")?;
+    writeln!(manifest, "tripId trip =")?;
+    writeln!(manifest, "    case trip of")?;
+    for trip in &trips {
+        writeln!(manifest, "        {} ->", trip.id_string())?;
+        writeln!(manifest, "            \"{}\"", trip.id())?;
+    }
+
     writeln!(manifest, "stringToTrip : String -> Maybe Trip")?;
     writeln!(manifest, "stringToTrip trip =")?;
     writeln!(manifest, "    case trip of")?;
-    for trip in &MANIFEST.trips {
-        writeln!(manifest, "        \"{}\" ->", trip.description)?;
+    for trip in &trips {
+        writeln!(manifest, "        \"{}\" ->", trip.id())?;
         writeln!(manifest, "            Just {}", trip.id_string())?;
     }
     writeln!(manifest, "        _ ->")?;
@@ -572,13 +1175,30 @@ fn write_trips(manifest: &mut File) -> Result<()> {
     writeln!(manifest, "    {{ name : String")?;
     writeln!(manifest, "    , description : String")?;
     writeln!(manifest, "    , locations : List Location")?;
+    writeln!(manifest, "    , legs : List Leg")?;
+    writeln!(manifest, "    , start : Day")?;
+    writeln!(manifest, "    , end : Day")?;
+    writeln!(manifest, "    , days : Int")?;
     writeln!(manifest, "    , dates : List Date")?;
     writeln!(manifest, "    }}")?;
 
+    writeln!(manifest, "type alias Leg =")?;
+    writeln!(manifest, "    {{ locations : List Location")?;
+    writeln!(manifest, "    , start : Day")?;
+    writeln!(manifest, "    , end : Day")?;
+    writeln!(manifest, "    }}")?;
+
     writeln!(manifest, "tripInformation : Trip -> TripInformation")?;
     writeln!(manifest, "tripInformation trip =")?;
     writeln!(manifest, "    case trip of")?;
-    for trip in &MANIFEST.trips {
+    for trip in &trips {
+        let start = trip
+            .start()
+            .ok_or(anyhow!("{} has no legs", trip.id_string()))?;
+        let end = trip
+            .end()
+            .ok_or(anyhow!("{} has no legs", trip.id_string()))?;
+
         writeln!(manifest, "        {} ->", trip.id_string())?;
         writeln!(manifest, "            {{ name = \"{}\"", trip.name)?;
         writeln!(
@@ -587,39 +1207,30 @@ fn write_trips(manifest: &mut File) -> Result<()> {
             trip.description
         )?;
         write!(manifest, "            , locations = [ ")?;
+        write_list(manifest, trip.cities())?;
+        writeln!(manifest, " ]")?;
+        write!(manifest, "            , legs = [ ")?;
         idx = 0;
-        for place in &trip.cities {
+        for leg in &trip.legs {
             if idx != 0 {
-                write!(manifest, ", {}", place)?;
-            } else {
-                write!(manifest, "{}", place)?;
+                write!(manifest, ", ")?;
             }
+            write!(manifest, "Leg [ ")?;
+            write_list(manifest, &leg.cities)?;
+            write!(manifest, " ] ({}) ({})", day(&leg.start)?, day(&leg.end)?)?;
             idx += 1;
         }
         writeln!(manifest, " ]")?;
+        writeln!(manifest, "            , start = {}", day(&start)?)?;
+        writeln!(manifest, "            , end = {}", day(&end)?)?;
+        writeln!(manifest, "            , days = {}", trip.days())?;
         write!(manifest, "            , dates = [ ")?;
         idx = 0;
-        for date in &trip.dates {
-            let splitidx = date
-                .find('/')
-                .ok_or(anyhow!("{} has a malformed date string", trip.id_string()))?;
-            let (year, month_str) = date.split_at(splitidx);
-            let mut month_string = month_str.to_string();
-            month_string.retain(|c| c != '/');
+        for (year, month) in trip.months() {
             if idx != 0 {
-                write!(
-                    manifest,
-                    ", Date {} {}",
-                    year,
-                    Month::from_str(&month_string)?
-                )?;
+                write!(manifest, ", Date {} {}", year, Month::try_from(month)?)?;
             } else {
-                write!(
-                    manifest,
-                    "Date {} {}",
-                    year,
-                    Month::from_str(&month_string)?
-                )?;
+                write!(manifest, "Date {} {}", year, Month::try_from(month)?)?;
             }
             idx += 1;
         }
@@ -649,155 +1260,241 @@ fn write_trips(manifest: &mut File) -> Result<()> {
     writeln!(manifest, "    {{ year : Year")?;
     writeln!(manifest, "    , month : Month")?;
     writeln!(manifest, "    }}")?;
+    writeln!(manifest, "type alias Day =")?;
+    writeln!(manifest, "    {{ year : Year")?;
+    writeln!(manifest, "    , month : Month")?;
+    writeln!(manifest, "    , day : Int")?;
+    writeln!(manifest, "    }}")?;
 
     Ok(())
 }
 
-fn write_manifest(manifest: &mut File) -> Result<()> {
-    // Ignore the thumbnails and blurs at this point. We will check for them later.
-    let walker = globwalk::GlobWalkerBuilder::from_patterns(
-        "../dist/gallery/",
-        &["*.{png,jpg,jpeg,PNG,JPG,JPEG}", "!*_small*", "!*_blur*"],
-    )
+/// Trips are emitted in chronological order, validation guarantees every trip has a start.
+fn chronological_trips(manifest: &Manifest) -> Vec<&Trip> {
+    let mut trips = manifest.trips.iter().collect::<Vec<&Trip>>();
+    trips.sort_by_key(|trip| trip.start());
+    trips
+}
 
-    .follow_links(true)
-    .build()?
-    .into_iter()
-    .filter_map(Result::ok)
-    .collect::<Vec<DirEntry>>();
+fn write_list<T, I>(manifest: &mut File, items: I) -> Result<()>
+where
+    T: fmt::Display,
+    I: IntoIterator<Item = T>,
+{
+    for (idx, item) in items.into_iter().enumerate() {
+        if idx != 0 {
+            write!(manifest, ", {}", item)?;
+        } else {
+            write!(manifest, "{}", item)?;
+        }
+    }
+    Ok(())
+}
+
+fn day(date: &NaiveDate) -> Result<String> {
+    Ok(format!(
+        "Day {} {} {}",
+        date.year(),
+        Month::try_from(date.month())?,
+        date.day()
+    ))
+}
+
+/// Generates a thumbnail and a blur of every image that doesn't have them yet.
+fn derivatives(images: &[GalleryImage]) -> Result<()> {
+    let bar = ProgressBar::new(images.len() as u64);
+
+    bar.set_style(
+        ProgressStyle::default_bar()
+            .template("[{elapsed_precise}] {bar:25.cyan/blue} {pos:>5}/{len:5} {msg}")?,
+    );
+
+    images.par_iter().try_for_each(|image| -> Result<()> {
+        let bar_msg = image.gallery_path();
+        if bar_msg.len() > 50 {
+            bar.set_message(format!(".../.../{}", image.name));
+        } else {
+            bar.set_message(bar_msg.to_string());
+        }
+
+        make_derivatives(&image.path)?;
+
+        bar.inc(1);
+        Ok(())
+    })?;
+    bar.finish();
 
-    let progcount = walker.len() as u64;
-    let bar = ProgressBar::new(progcount);
+    Ok(())
+}
 
-    bar.set_style(ProgressStyle::default_bar().template("[{elapsed_precise}] {bar:25.cyan/blue} {pos:>5}/{len:5} {msg}")?);
+/// Makes the thumbnail and blur of the image at `path` if either is missing.
+pub(crate) fn make_derivatives(path: &Path) -> Result<()> {
+    let (thumbnail, blur) = derivative_paths(path)?;
 
+    if !thumbnail.exists() || !blur.exists() {
+        let img = image::open(path)?;
+        let ratio = img.width() as f64 / img.height() as f64;
+        let thumb_width = if ratio < 3.0 { 500 } else { 900 };
+        let thumb = img.resize(thumb_width, 500, Lanczos3);
+
+        if !thumbnail.exists() {
+            thumb.save(&thumbnail)?;
+        }
+        if !blur.exists() {
+            thumb.blur(30.0).save(&blur)?;
+        }
+    }
+    Ok(())
+}
+
+/// Returns the file names of the images in each trip, by trip id.
+fn write_manifest<'a>(
+    manifest: &mut File,
+    project: &Project,
+    images: &'a [GalleryImage],
+) -> Result<BTreeMap<String, Vec<&'a GalleryImage>>> {
     writeln!(manifest, "type alias Image =")?;
     writeln!(manifest, "    {{ file : String")?;
     writeln!(manifest, "    , date : Date")?;
     writeln!(manifest, "    , location : Location")?;
     writeln!(manifest, "    , aspectRatio : Float")?;
     writeln!(manifest, "    , description : String")?;
+    writeln!(manifest, "    , trips : List Trip")?;
     writeln!(manifest, "    }}")?;
 
+    let trips = chronological_trips(&project.manifest);
+    let mut trip_images: BTreeMap<String, Vec<&GalleryImage>> = BTreeMap::new();
+
     writeln!(manifest, "manifest : List Image")?;
     writeln!(manifest, "manifest =")?;
 
-    for (idx, file) in bar.wrap_iter(walker.iter().enumerate()) {
-        let bar_msg = file
-            .path()
-            .strip_prefix("../dist/gallery/")?
-            .to_str()
-            .unwrap_or_default();
-
-        if bar_msg.len() > 50 {
-            let msg = bar_msg.split('/').collect::<Vec<&str>>();
-            bar.set_message(format!(".../.../{}", msg.last().unwrap()));
-        } else {
-            bar.set_message(bar_msg.to_string());
-        }
-
-        // Open image and grab its dimensions.
-        let img = image::open(&file.path())?;
-        let (width, height) = img.dimensions();
+    for (idx, image) in images.iter().enumerate() {
+        let (width, height) = image::image_dimensions(&image.path)?;
         let ratio = width as f64 / height as f64;
-        let afile = file.clone();
-        rayon::spawn(move || {
-            // Generate a thumbnail and blur if they doesn't already exist.
-            let stem = afile
-                .path()
-                .file_stem()
-                .and_then(|p| p.to_str())
-                .expect("File stem unwrap issue.");
-            let ext = afile
-                .path()
-                .extension()
-                .and_then(|p| p.to_str())
-                .expect("Extension unwrap issue.");
-            let thumbnail = format!("{}_small.{}", stem, ext);
-            let blur = format!("{}_blur.{}", stem, ext);
-            let thumb_width = if ratio < 3.0 { 500 } else { 900 };
-            if !afile.path().with_file_name(&thumbnail).exists()
-                && !afile.path().with_file_name(&blur).exists()
-            {
-                let thumb = img.resize(thumb_width, 500, Lanczos3);
-                thumb
-                    .save(afile.path().with_file_name(thumbnail))
-                    .expect("Failed to save thumbnail.");
-                thumb
-                    .blur(30.0)
-                    .save(afile.path().with_file_name(blur))
-                    .expect("Failed to save blur.");
-            } else if !afile.path().with_file_name(&thumbnail).exists() {
-                img.resize(thumb_width, 500, Lanczos3)
-                    .save(afile.path().with_file_name(thumbnail))
-                    .expect("Failed to save thumbnail.");
-            } else if !afile.path().with_file_name(&blur).exists() {
-                img.resize(thumb_width, 500, Lanczos3)
-                    .blur(30.0)
-                    .save(afile.path().with_file_name(blur))
-                    .expect("Failed to save blur.");
-            }
-        });
 
         // Get image decription if it exists, create file if not.
         let mut description = String::new();
-        let _ = File::open(file.path().with_extension("desc"))
-            .or_else(|_| File::create(file.path().with_extension("desc")))
+        let _ = File::open(image.path.with_extension("desc"))
+            .or_else(|_| File::create(image.path.with_extension("desc")))
             .and_then(|mut f| f.read_to_string(&mut description));
 
         // Build a manifest of all files. We do this entirely each time as descriptions or filenames may have changed.
-        let mut path_iter = file.path().strip_prefix("../dist/gallery/")?.iter().rev();
-
-        let name = path_iter
-            .next()
-            .and_then(|p| p.to_str())
-            .ok_or(anyhow!("File name unwrap issue."))?;
-        let location_str = path_iter
-            .next()
-            .and_then(|p| p.to_str())
-            .ok_or(anyhow!("Location unwrap issue."))?;
-        let location = to_location_identfier_string(&location_str).parse::<Location>()?;
-        let _country = path_iter.next();
-        let month = path_iter
-            .next()
-            .and_then(|p| p.to_str())
-            .ok_or(anyhow!("Month unwrap issue."))?
-            .parse::<Month>()?;
-        let year = path_iter
-            .next()
-            .and_then(|p| p.to_str())
-            .ok_or(anyhow!("Year unwrap issue."))?;
+        let image_trips = image.trips(&trips)?;
+
+        for trip in &image_trips {
+            trip_images.entry(trip.id()).or_default().push(image);
+        }
 
         if idx != 0 {
-            write!(
-                manifest,
-                "    , Image \"{}\" (Date {} {:?}) {:?} {:.3} \"{}\"\n",
-                name,
-                year,
-                month,
-                location,
-                ratio,
-                description.trim()
-            )?;
+            write!(manifest, "    , ")?;
         } else {
-            write!(
-                manifest,
-                "    [ Image \"{}\" (Date {} {:?}) {:?} {:.3} \"{}\"\n",
-                name,
-                year,
-                month,
-                location,
-                ratio,
-                description.trim()
-            )?;
+            write!(manifest, "    [ ")?;
         }
+        write!(
+            manifest,
+            "Image \"{}\" (Date {} {}) {} {:.3} \"{}\" [ ",
+            image.name,
+            image.year,
+            Month::try_from(image.month)?,
+            image.location,
+            ratio,
+            description.trim()
+        )?;
+        write_list(manifest, image_trips.iter().map(|trip| trip.id_string()))?;
+        writeln!(manifest, " ]")?;
     }
     writeln!(manifest, "    ]")?;
-    bar.finish();
+
+    writeln!(manifest, "tripImages : Trip -> List String")?;
+    writeln!(manifest, "tripImages trip =")?;
+    writeln!(manifest, "    case trip of")?;
+    for trip in &trips {
+        writeln!(manifest, "        {} ->", trip.id_string())?;
+        write!(manifest, "            [ ")?;
+        write_list(
+            manifest,
+            trip_images
+                .get(&trip.id())
+                .into_iter()
+                .flatten()
+                .map(|image| format!("\"{}\"", image.gallery_path())),
+        )?;
+        writeln!(manifest, " ]")?;
+    }
+
+    Ok(trip_images)
+}
+
+fn write_statistics(
+    manifest: &mut File,
+    project: &Project,
+    locations_information: &[LocationInformation],
+    trip_images: &BTreeMap<String, Vec<&GalleryImage>>,
+    photos: usize,
+) -> Result<()> {
+    let position = |location: &Location| {
+        locations_information
+            .iter()
+            .find(|info| info.id == *location)
+            .and_then(|info| {
+                Some((
+                    *info.coordinates.first()? as f64,
+                    *info.coordinates.get(1)? as f64,
+                ))
+            })
+            .ok_or(anyhow!("Could not find coordinates for {}.", location))
+    };
+
+    writeln!(manifest, "type alias Statistics =")?;
+    writeln!(manifest, "    {{ trips : Int")?;
+    writeln!(manifest, "    , distance : Float")?;
+    writeln!(manifest, "    , countries : Int")?;
+    writeln!(manifest, "    , cities : Int")?;
+    writeln!(manifest, "    , days : Int")?;
+    writeln!(manifest, "    , photos : Int")?;
+    writeln!(manifest, "    }}")?;
+
+    let trips = chronological_trips(&project.manifest);
+
+    writeln!(manifest, "tripStatistics : Trip -> Statistics")?;
+    writeln!(manifest, "tripStatistics trip =")?;
+    writeln!(manifest, "    case trip of")?;
+    for trip in &trips {
+        let photos = trip_images.get(&trip.id()).map_or(0, |images| images.len());
+        writeln!(manifest, "        {} ->", trip.id_string())?;
+        write_statistic(
+            manifest,
+            &trip_statistics(&project.manifest.places, trip, position, photos)?,
+            "            ",
+        )?;
+    }
+
+    writeln!(manifest, "lifetimeStatistics : Statistics")?;
+    writeln!(manifest, "lifetimeStatistics =")?;
+    write_statistic(
+        manifest,
+        &lifetime_statistics(&project.manifest.places, &trips, position, photos)?,
+        "    ",
+    )?;
 
     Ok(())
 }
 
+fn write_statistic(manifest: &mut File, stats: &Statistics, indent: &str) -> Result<()> {
+    writeln!(
+        manifest,
+        "{}Statistics {} {:.1} {} {} {} {}",
+        indent,
+        stats.trips,
+        stats.distance,
+        stats.countries,
+        stats.cities,
+        stats.days,
+        stats.photos
+    )?;
+    Ok(())
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 enum Month {
     Jan,
@@ -836,6 +1533,14 @@ impl FromStr for Month {
     }
 }
 
+impl TryFrom<u32> for Month {
+    type Error = Error;
+
+    fn try_from(month: u32) -> Result<Month, Error> {
+        Month::from_str(&format!("{:02}", month))
+    }
+}
+
 impl fmt::Display for Month {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         fmt::Debug::fmt(self, f)
@@ -1,36 +1,190 @@
 use anyhow::{bail, Result};
-use reqwest::header::USER_AGENT;
+use reqwest::blocking::{Client, Response};
+use reqwest::header::{RETRY_AFTER, USER_AGENT};
+use reqwest::StatusCode;
+use std::sync::{Mutex, Once};
+use std::thread;
+use std::time::{Duration, Instant};
+use url::Url;
 
-static NOMINATIM_ENDPOINT: &str = "http://nominatim.openstreetmap.org";
-const AUTHORS: &str = env!("CARGO_PKG_AUTHORS");
 const VERSION: &str = env!("CARGO_PKG_VERSION");
 const NAME: &str = env!("CARGO_PKG_NAME");
 
+/// How long a request may take before it counts as failed, and is retried.
+const TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Warns about a missing `email` once rather than on every request.
+static MISSING_EMAIL: Once = Once::new();
+
+lazy_static! {
+    static ref CLIENT: Client = Client::new();
+    /// When the last request went out, shared by every caller so the limit holds across them.
+    static ref LAST_REQUEST: Mutex<Option<Instant>> = Mutex::new(None);
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Geocoding {
+    /// Base URL of a Nominatim compatible server.
+    #[serde(default = "default_endpoint")]
+    pub endpoint: Url,
+    /// Contact address sent in the User-Agent, as the Nominatim usage policy asks for.
+    pub email: Option<String>,
+    #[serde(default = "default_requests_per_second")]
+    pub requests_per_second: f64,
+    /// Attempts after the first for requests that were throttled or hit a server error.
+    #[serde(default = "default_retries")]
+    pub retries: u32,
+}
+
+fn default_endpoint() -> Url {
+    Url::parse("https://nominatim.openstreetmap.org").unwrap()
+}
+
+fn default_requests_per_second() -> f64 {
+    1.0
+}
+
+fn default_retries() -> u32 {
+    3
+}
+
+impl Default for Geocoding {
+    fn default() -> Self {
+        Geocoding {
+            endpoint: default_endpoint(),
+            email: None,
+            requests_per_second: default_requests_per_second(),
+            retries: default_retries(),
+        }
+    }
+}
+
+impl Geocoding {
+    pub fn validate(&self) -> Result<()> {
+        if !self.requests_per_second.is_finite() || self.requests_per_second <= 0.0 {
+            bail!(
+                "geocoding requests_per_second must be above 0, not {}",
+                self.requests_per_second
+            );
+        }
+        if self.endpoint.cannot_be_a_base() {
+            bail!("geocoding endpoint {} is not a base URL", self.endpoint);
+        }
+
+        Ok(())
+    }
+
+    fn user_agent(&self) -> String {
+        match &self.email {
+            Some(email) => format!("{} v{} - {}", NAME, VERSION, email),
+            None => {
+                MISSING_EMAIL.call_once(|| {
+                    eprintln!(
+                        "Warning: set geocoding: email: in manifest.yaml, the Nominatim usage policy asks for a contact address in the User-Agent"
+                    )
+                });
+                format!("{} v{}", NAME, VERSION)
+            }
+        }
+    }
+
+    fn url(&self, endpoint: &str, params: &[(&str, &str)]) -> Url {
+        let mut url = self.endpoint.clone();
+        if let Ok(mut segments) = url.path_segments_mut() {
+            segments.pop_if_empty().push(endpoint);
+        }
+        url.query_pairs_mut().extend_pairs(params);
+        url
+    }
+
+    /// Waits until a request would keep to `requests_per_second`.
+    fn throttle(&self) {
+        let mut last = LAST_REQUEST.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(previous) = *last {
+            let interval = Duration::from_secs_f64(1.0 / self.requests_per_second);
+            if let Some(wait) = interval.checked_sub(previous.elapsed()) {
+                thread::sleep(wait);
+            }
+        }
+        *last = Some(Instant::now());
+    }
+}
+
 #[derive(Deserialize, Debug)]
 pub struct Nominatim {
     lat: String,
     lon: String,
+    #[serde(default)]
+    display_name: String,
+    #[serde(default)]
+    osm_type: Option<String>,
+    #[serde(default)]
+    osm_id: Option<u64>,
 }
 
 impl Nominatim {
-    pub fn search(place_name: &str) -> Result<Self> {
-        let params = vec![("format", "jsonv2"), ("q", place_name), ("limit", "1")];
-        let query_string = Self::get_query_string(params);
+    pub fn search(geocoding: &Geocoding, place_name: &str) -> Result<Self> {
+        match Self::candidates(geocoding, place_name, 1)?
+            .into_iter()
+            .next()
+        {
+            Some(latlon) => Ok(latlon),
+            None => bail!("Search for {} did not find coordinates", place_name),
+        }
+    }
 
-        let url = format!("{}/search?{}", NOMINATIM_ENDPOINT, query_string);
-        let client = reqwest::blocking::Client::new();
+    /// Up to `limit` matches for `place_name`, best first.
+    pub fn candidates(geocoding: &Geocoding, place_name: &str, limit: usize) -> Result<Vec<Self>> {
+        let limit = limit.to_string();
+        let params = [("format", "jsonv2"), ("q", place_name), ("limit", &limit)];
 
-        let res = client
-            .get(&url)
-            .header(USER_AGENT, format!("{} v{} - {}", NAME, VERSION, AUTHORS))
-            .send()?;
+        Self::get(geocoding, "search", &params)
+    }
 
-        let mut results = res.json::<Vec<Self>>()?;
-        results.reverse();
+    /// The OpenStreetMap object `osm_id`, such as `R2297418`.
+    pub fn lookup(geocoding: &Geocoding, osm_id: &str) -> Result<Self> {
+        let params = [("format", "jsonv2"), ("osm_ids", osm_id)];
 
-        match results.pop() {
+        match Self::get(geocoding, "lookup", &params)?.into_iter().next() {
             Some(latlon) => Ok(latlon),
-            None => bail!("Search for {} did not find coordinates", place_name),
+            None => bail!("OpenStreetMap has no {}", osm_id),
+        }
+    }
+
+    /// Requests are retried with exponential backoff when the server is busy, throttled or
+    /// unreachable, waiting as long as a `Retry-After` header asks instead when there is one.
+    fn get(geocoding: &Geocoding, endpoint: &str, params: &[(&str, &str)]) -> Result<Vec<Self>> {
+        let url = geocoding.url(endpoint, params);
+
+        let mut attempt = 0;
+        loop {
+            geocoding.throttle();
+            let result = CLIENT
+                .get(url.clone())
+                .header(USER_AGENT, geocoding.user_agent())
+                .timeout(TIMEOUT)
+                .send();
+
+            let (reason, retry_after) = match result {
+                Ok(res) if res.status().is_success() => return Ok(res.json::<Vec<Self>>()?),
+                Ok(res) if retryable(res.status()) => (res.status().to_string(), retry_after(&res)),
+                Ok(res) => bail!("{} answered {}", url, res.status()),
+                Err(e) if e.is_connect() || e.is_timeout() => (e.to_string(), None),
+                Err(e) => return Err(e.into()),
+            };
+
+            if attempt >= geocoding.retries {
+                bail!(
+                    "Gave up on {} after {} attempts, the last one failed with {}",
+                    url,
+                    attempt + 1,
+                    reason
+                );
+            }
+            let wait = retry_after.unwrap_or(Duration::from_secs(1 << attempt.min(6)));
+            eprintln!("{} failed with {}, retrying in {:?}", url, reason, wait);
+            thread::sleep(wait);
+            attempt += 1;
         }
     }
 
@@ -42,11 +196,38 @@ impl Nominatim {
         self.lon.to_string()
     }
 
-    fn get_query_string(params: Vec<(&str, &str)>) -> String {
-        let pairs: Vec<String> = params
-            .into_iter()
-            .map(|(k, v)| format!("{}={}", k, v))
-            .collect();
-        pairs.join("&")
+    pub fn display_name(&self) -> &str {
+        &self.display_name
+    }
+
+    /// The `N`, `W` or `R` prefixed id used to pin a location to this result.
+    pub fn osm(&self) -> Option<String> {
+        let prefix = match self.osm_type.as_deref()? {
+            "node" => 'N',
+            "way" => 'W',
+            "relation" => 'R',
+            _ => return None,
+        };
+        Some(format!("{}{}", prefix, self.osm_id?))
+    }
+}
+
+fn retryable(status: StatusCode) -> bool {
+    status == StatusCode::TOO_MANY_REQUESTS || status.is_server_error()
+}
+
+/// How long a `Retry-After` header asks to wait, given either in seconds or as an HTTP date.
+fn retry_after(res: &Response) -> Option<Duration> {
+    let value = res.headers().get(RETRY_AFTER)?.to_str().ok()?.trim();
+
+    match value.parse::<u64>() {
+        Ok(seconds) => Some(Duration::from_secs(seconds)),
+        Err(_) => {
+            let date = chrono::DateTime::parse_from_rfc2822(value).ok()?;
+            (date.with_timezone(&chrono::Utc) - chrono::Utc::now())
+                .to_std()
+                .ok()
+                .or(Some(Duration::ZERO))
+        }
     }
 }
@@ -0,0 +1,289 @@
+use anyhow::{anyhow, bail, Error, Result};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::time::{Duration, Instant};
+
+use super::compress::Compress;
+use super::fingerprint::Fingerprint;
+use super::{npm_command, Project};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum Profile {
+    /// Unoptimized Elm and unminified assets, quicker to build and to debug.
+    Dev,
+    /// Optimized Elm and minified assets, for deploying.
+    Release,
+}
+
+impl Profile {
+    fn name(self) -> &'static str {
+        match self {
+            Profile::Dev => "dev",
+            Profile::Release => "release",
+        }
+    }
+}
+
+/// manifest.yaml `build:`, how the site is built once the manifest is. Paths are relative to the
+/// repository, which every step runs in.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Pipeline {
+    #[serde(default)]
+    pub elm: Elm,
+    /// Steps run in order after Elm is compiled, the README's by default.
+    #[serde(default = "default_post")]
+    pub post: Vec<PostProcessor>,
+    /// Content hashed asset names, given once everything else is built.
+    #[serde(default)]
+    pub fingerprint: Fingerprint,
+    /// Gzip and Brotli siblings of text files, made last so they are of the files deployed.
+    #[serde(default)]
+    pub compress: Compress,
+}
+
+impl Default for Pipeline {
+    fn default() -> Self {
+        Pipeline {
+            elm: Elm::default(),
+            post: default_post(),
+            fingerprint: Fingerprint::default(),
+            compress: Compress::default(),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Elm {
+    #[serde(default = "default_elm_main")]
+    pub main: PathBuf,
+    #[serde(default = "default_elm_output")]
+    pub output: PathBuf,
+}
+
+impl Default for Elm {
+    fn default() -> Self {
+        Elm {
+            main: default_elm_main(),
+            output: default_elm_output(),
+        }
+    }
+}
+
+fn default_elm_main() -> PathBuf {
+    PathBuf::from("src/Main.elm")
+}
+
+fn default_elm_output() -> PathBuf {
+    PathBuf::from("dist/assets/js/initprism.js")
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PostProcessor {
+    pub name: String,
+    #[serde(flatten)]
+    pub action: Action,
+    /// The profiles the step runs in, every one if not given.
+    #[serde(default = "all_profiles")]
+    pub profiles: Vec<Profile>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Action {
+    /// A shell command, which may pipe and redirect.
+    Run(String),
+    /// A file copied as it is.
+    Copy { from: PathBuf, to: PathBuf },
+}
+
+fn all_profiles() -> Vec<Profile> {
+    vec![Profile::Dev, Profile::Release]
+}
+
+fn default_post() -> Vec<PostProcessor> {
+    let step = |name: &str, action, profile| PostProcessor {
+        name: name.to_string(),
+        action,
+        profiles: vec![profile],
+    };
+
+    vec![
+        step(
+            "css",
+            Action::Run("crass src/gallery.css --optimize > dist/assets/css/gallery.css".to_string()),
+            Profile::Release,
+        ),
+        step(
+            "css",
+            Action::Copy {
+                from: PathBuf::from("src/gallery.css"),
+                to: PathBuf::from("dist/assets/css/gallery.css"),
+            },
+            Profile::Dev,
+        ),
+        step(
+            "js",
+            Action::Run(
+                "uglifyjs dist/assets/js/initprism.js --compress 'pure_funcs=\"F2,F3,F4,F5,F6,F7,F8,F9,A2,A3,A4,A5,A6,A7,A8,A9\",pure_getters,keep_fargs=false,unsafe_comps,unsafe' | uglifyjs --mangle > dist/assets/js/initprism.min.js"
+                    .to_string(),
+            ),
+            Profile::Release,
+        ),
+        // index.html loads the minified script whatever the profile.
+        step(
+            "js",
+            Action::Copy {
+                from: PathBuf::from("dist/assets/js/initprism.js"),
+                to: PathBuf::from("dist/assets/js/initprism.min.js"),
+            },
+            Profile::Dev,
+        ),
+    ]
+}
+
+impl Elm {
+    fn make(&self, root: &Path, profile: Profile) -> Result<()> {
+        let mut command = npm_command("elm");
+        command
+            .current_dir(root)
+            .arg("make")
+            .arg(&self.main)
+            .arg(format!("--output={}", self.output.display()));
+        if profile == Profile::Release {
+            command.arg("--optimize");
+        }
+        run(&mut command, "elm make")
+    }
+}
+
+impl Action {
+    fn run(&self, root: &Path) -> Result<()> {
+        match self {
+            Action::Run(script) => {
+                let mut command = if cfg!(windows) {
+                    let mut command = Command::new("cmd");
+                    command.arg("/c");
+                    command
+                } else {
+                    let mut command = Command::new("sh");
+                    command.arg("-c");
+                    command
+                };
+                run(command.arg(script).current_dir(root), script)
+            }
+            Action::Copy { from, to } => {
+                let (from, to) = (root.join(from), root.join(to));
+                if let Some(parent) = to.parent() {
+                    fs::create_dir_all(parent)?;
+                }
+                fs::copy(&from, &to).map_err(|e| {
+                    anyhow!(
+                        "Copying {} to {} failed: {}",
+                        from.display(),
+                        to.display(),
+                        e
+                    )
+                })?;
+                Ok(())
+            }
+        }
+    }
+}
+
+fn run(command: &mut Command, name: &str) -> Result<()> {
+    match command.status() {
+        Ok(status) if status.success() => Ok(()),
+        Ok(status) => bail!("`{}` failed with {}", name, status),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+            bail!("`{}` can't be run, is it installed and on the path?", name)
+        }
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// How a stage of the build went.
+enum Outcome {
+    Done(Duration),
+    Failed(Duration, Error),
+    Skipped,
+}
+
+/// Builds the manifest, compiles Elm, runs the post-processors of `profile`, fingerprints and compresses, stopping at the first
+/// stage that fails. Each stage's time, or how it failed, is summed up at the end.
+pub fn run_pipeline(project: &Project, profile: Profile) -> Result<()> {
+    let pipeline = &project.manifest.build;
+    let root = &project.config.root;
+
+    type Stage<'a> = Box<dyn Fn() -> Result<()> + 'a>;
+    let mut stages: Vec<(&str, Stage)> = vec![
+        ("manifest", Box::new(|| project.build())),
+        ("elm", Box::new(|| pipeline.elm.make(root, profile))),
+    ];
+    for step in pipeline
+        .post
+        .iter()
+        .filter(|step| step.profiles.contains(&profile))
+    {
+        stages.push((&step.name, Box::new(|| step.action.run(root))));
+    }
+    stages.push((
+        "fingerprint",
+        Box::new(|| {
+            pipeline
+                .fingerprint
+                .apply(root, &project.config.dist, profile == Profile::Release)?;
+            Ok(())
+        }),
+    ));
+    stages.push((
+        "compress",
+        Box::new(|| {
+            let written = pipeline.compress.apply(&project.config.dist)?;
+            println!("{} compressed files written.", written);
+            Ok(())
+        }),
+    ));
+
+    let mut outcomes = Vec::new();
+    let mut failed = None;
+    for (name, stage) in &stages {
+        if failed.is_some() {
+            outcomes.push((name, Outcome::Skipped));
+            continue;
+        }
+
+        println!("==> {}", name);
+        let start = Instant::now();
+        match stage() {
+            Ok(()) => outcomes.push((name, Outcome::Done(start.elapsed()))),
+            Err(error) => {
+                failed = Some(*name);
+                outcomes.push((name, Outcome::Failed(start.elapsed(), error)));
+            }
+        }
+    }
+
+    let width = stages.iter().map(|(name, _)| name.len()).max().unwrap_or(0);
+    println!("\nBuild ({}):", profile.name());
+    for (name, outcome) in &outcomes {
+        match outcome {
+            Outcome::Done(elapsed) => {
+                println!("  {:width$}  {:>6.1}s", name, elapsed.as_secs_f32())
+            }
+            Outcome::Failed(elapsed, error) => println!(
+                "  {:width$}  {:>6.1}s  failed: {:#}",
+                name,
+                elapsed.as_secs_f32(),
+                error
+            ),
+            Outcome::Skipped => println!("  {:width$}  skipped", name),
+        }
+    }
+
+    match failed {
+        Some(name) => bail!("The build failed at the {} stage", name),
+        None => Ok(()),
+    }
+}
@@ -0,0 +1,131 @@
+use anyhow::Result;
+use std::path::{Path, PathBuf};
+
+use super::deploy::{deploy, roll_back, DeployTarget};
+use super::export::{export, ExportFormat};
+use super::gallery::{read_gallery, GalleryImage};
+use super::geocode::{geocode, review};
+use super::pipeline::{run_pipeline, Profile};
+use super::suggest::suggest_trips;
+use super::{derivatives, write_outputs, Manifest};
+
+/// Where a project keeps its gallery and writes what it builds. The defaults are this repository's
+/// layout, seen from the manifester directory.
+#[derive(Debug, Clone)]
+pub struct Config {
+    /// The repository, which the site's build steps run in.
+    pub root: PathBuf,
+    /// The site, which the gallery is part of.
+    pub dist: PathBuf,
+    pub gallery: PathBuf,
+    pub cities: PathBuf,
+    pub trips: PathBuf,
+    pub countries: PathBuf,
+    pub elm: PathBuf,
+    /// Run elm-format over the generated Manifest.elm.
+    pub format_elm: bool,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            root: PathBuf::from(".."),
+            dist: PathBuf::from("../dist/"),
+            gallery: PathBuf::from("../dist/gallery/"),
+            cities: PathBuf::from("cities.json"),
+            trips: PathBuf::from("trips.json"),
+            countries: PathBuf::from("countries.json"),
+            elm: PathBuf::from("../src/Manifest.elm"),
+            format_elm: true,
+        }
+    }
+}
+
+/// A gallery and the manifest of the places and trips its photos were taken on.
+pub struct Project {
+    pub(super) manifest: Manifest,
+    pub(super) config: Config,
+}
+
+impl Project {
+    /// Validates `manifest` up front, so nothing is built from one that doesn't make sense. The paths it
+    /// gives relative to the repository are resolved against `config.root` first.
+    pub fn new(mut manifest: Manifest, config: Config) -> Result<Self> {
+        manifest.resolve_paths(&config.root);
+        manifest.validate()?;
+        Ok(Project { manifest, config })
+    }
+
+    pub fn manifest(&self) -> &Manifest {
+        &self.manifest
+    }
+
+    pub fn config(&self) -> &Config {
+        &self.config
+    }
+
+    /// Every original image in the gallery, with where and when it was taken.
+    pub fn scan(&self) -> Result<Vec<GalleryImage>> {
+        read_gallery(&self.config.gallery, &self.manifest)
+    }
+
+    /// Geocodes new and re-pinned places into cities.json without building anything else.
+    pub fn geocode(&self) -> Result<()> {
+        geocode(&self.manifest, &self.config.cities)
+    }
+
+    /// Prints the best `candidates` geocoding matches for `locations`, or every place.
+    pub fn review(&self, candidates: usize, locations: &[String]) -> Result<()> {
+        review(&self.manifest, &self.config.cities, candidates, locations)
+    }
+
+    /// Generates the thumbnails and blurs `images` are missing.
+    pub fn derivatives(&self, images: &[GalleryImage]) -> Result<()> {
+        derivatives(images)
+    }
+
+    /// Scans the gallery, makes the missing derivatives and writes every output.
+    pub fn build(&self) -> Result<()> {
+        let images = self.scan()?;
+        self.derivatives(&images)?;
+        self.write(&images)
+    }
+
+    /// Geocodes new places and writes cities.json, trips.json, countries.json and Manifest.elm for
+    /// `images`, the whole gallery as `scan` reads it.
+    pub fn write(&self, images: &[GalleryImage]) -> Result<()> {
+        write_outputs(self, images)
+    }
+
+    /// Builds the manifest and then the site around it, compiling Elm and running the post-processors
+    /// of `profile`.
+    pub fn build_site(&self, profile: Profile) -> Result<()> {
+        run_pipeline(self, profile)
+    }
+
+    /// Publishes dist to the bucket or directory the manifest deploys to, see `deploy`.
+    pub fn deploy(&self, target: Option<DeployTarget>, dry_run: bool, delete: bool) -> Result<()> {
+        deploy(self, target, dry_run, delete)
+    }
+
+    /// Points the directory deploy back at `to`, or the release before the current one.
+    pub fn rollback(&self, to: Option<&str>) -> Result<()> {
+        roll_back(self, to)
+    }
+
+    /// Prints trips proposed from photos no trip covers yet, see `suggest_trips`.
+    pub fn suggest_trips(&self, gap_days: i64, home: &[String]) -> Result<()> {
+        suggest_trips(&self.manifest, &self.config.gallery, gap_days, home)
+    }
+
+    /// Writes the places and trips from cities.json and trips.json to `out`.
+    pub fn export(&self, format: ExportFormat, out: &Path) -> Result<()> {
+        export(
+            format,
+            &self.manifest.codes,
+            &self.config.cities,
+            &self.config.trips,
+            out,
+        )
+    }
+}
@@ -0,0 +1,229 @@
+use anyhow::{bail, Result};
+use reqwest::blocking::Client;
+use reqwest::header::USER_AGENT;
+use std::path::Path;
+use std::time::Duration;
+use url::Url;
+
+use super::map::{Feature, Mode, Trip};
+use super::track::{read_track, simplify};
+
+/// Longitude and latitude in degrees.
+pub type Position = (f64, f64);
+
+/// Mean radius of the earth.
+pub const EARTH_RADIUS_KM: f64 = 6371.0088;
+
+/// How long a route may take to come back before the build gives up on it.
+const OSRM_TIMEOUT: Duration = Duration::from_secs(30);
+
+lazy_static! {
+    static ref CLIENT: Client = Client::new();
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Routing {
+    /// Base URL of an OSRM compatible server, drive legs are routed through it when set.
+    pub osrm: Option<Url>,
+    #[serde(default = "default_profile")]
+    pub profile: String,
+}
+
+fn default_profile() -> String {
+    "driving".to_string()
+}
+
+impl Default for Routing {
+    fn default() -> Self {
+        Routing {
+            osrm: None,
+            profile: default_profile(),
+        }
+    }
+}
+
+/// The path travelled on a trip, split wherever it crosses the antimeridian.
+///
+/// Each leg is travelled from the last city of the previous leg through its own cities: flights follow
+/// great circles, drives follow the router if one is configured and anything with a track follows that,
+/// simplified to `tolerance` metres. The segments of a track stay apart rather than being joined across
+/// the gaps between them.
+pub fn trip_path(
+    trip: &Trip,
+    features: &[Feature],
+    routing: &Routing,
+    tolerance: f64,
+) -> Result<Vec<Vec<Position>>> {
+    let mut parts: Vec<Vec<Position>> = Vec::new();
+    let mut path: Vec<Position> = Vec::new();
+    let mut previous: Option<Position> = None;
+
+    for leg in &trip.legs {
+        let mut waypoints: Vec<Position> = previous.into_iter().collect();
+        for city in &leg.cities {
+            let position = match city.feature_coordinates(features)?[..] {
+                [lon, lat, ..] => (lon as f64, lat as f64),
+                ref coordinates => bail!(
+                    "{} has a Point with {} coordinates instead of a longitude and latitude",
+                    city.name(),
+                    coordinates.len()
+                ),
+            };
+            if waypoints.last() != Some(&position) {
+                waypoints.push(position);
+            }
+        }
+
+        let travelled = match (&leg.track, &leg.mode, &routing.osrm) {
+            (Some(track), _, _) => read_track(Path::new(track))?
+                .iter()
+                .map(|segment| simplify(segment, tolerance))
+                .collect(),
+            (None, Mode::Flight, _) => vec![waypoints
+                .windows(2)
+                .flat_map(|pair| great_circle(pair[0], pair[1]))
+                .collect()],
+            (None, Mode::Drive, Some(osrm)) if waypoints.len() > 1 => {
+                vec![route(osrm, &routing.profile, &waypoints)?]
+            }
+            _ => vec![waypoints.clone()],
+        };
+
+        for (idx, segment) in travelled.into_iter().enumerate() {
+            if idx > 0 && !path.is_empty() {
+                parts.push(std::mem::take(&mut path));
+            }
+            for position in segment {
+                if path.last() != Some(&position) {
+                    path.push(position);
+                }
+            }
+        }
+        previous = waypoints.last().copied();
+    }
+    parts.push(path);
+
+    Ok(parts
+        .iter()
+        .flat_map(|part| split_antimeridian(part))
+        .collect())
+}
+
+/// Points along the great circle from `from` to `to`, roughly one per degree of arc.
+pub fn great_circle(from: Position, to: Position) -> Vec<Position> {
+    let (lon1, lat1) = (from.0.to_radians(), from.1.to_radians());
+    let (lon2, lat2) = (to.0.to_radians(), to.1.to_radians());
+
+    let arc = central_angle(from, to);
+    // Antipodes have no single great circle, the straight line is as good as any.
+    if arc < 1e-9 || arc.sin().abs() < 1e-9 {
+        return vec![from, to];
+    }
+
+    let steps = arc.to_degrees().ceil().max(1.0) as usize;
+    (0..=steps)
+        .map(|step| {
+            let fraction = step as f64 / steps as f64;
+            let a = ((1.0 - fraction) * arc).sin() / arc.sin();
+            let b = (fraction * arc).sin() / arc.sin();
+            let x = a * lat1.cos() * lon1.cos() + b * lat2.cos() * lon2.cos();
+            let y = a * lat1.cos() * lon1.sin() + b * lat2.cos() * lon2.sin();
+            let z = a * lat1.sin() + b * lat2.sin();
+            (
+                y.atan2(x).to_degrees(),
+                z.atan2((x * x + y * y).sqrt()).to_degrees(),
+            )
+        })
+        .collect()
+}
+
+/// Angle between two positions at the centre of the earth, in radians.
+pub fn central_angle(from: Position, to: Position) -> f64 {
+    let (lat1, lat2) = (from.1.to_radians(), to.1.to_radians());
+    let dlat = lat2 - lat1;
+    let dlon = (to.0 - from.0).to_radians();
+    let h = (dlat / 2.0).sin().powi(2) + lat1.cos() * lat2.cos() * (dlon / 2.0).sin().powi(2);
+    2.0 * h.sqrt().min(1.0).asin()
+}
+
+/// Breaks a path into parts that never jump across the antimeridian, so maps don't draw
+/// a line across the whole world. Crossings are interpolated onto ±180°.
+pub fn split_antimeridian(path: &[Position]) -> Vec<Vec<Position>> {
+    let mut parts: Vec<Vec<Position>> = vec![Vec::new()];
+
+    for &(lon, lat) in path {
+        let part = parts.last_mut().expect("parts always has a last part");
+        if let Some(&(previous_lon, previous_lat)) = part.last() {
+            if (lon - previous_lon).abs() > 180.0 {
+                let (edge, unwrapped) = if previous_lon > 0.0 {
+                    (180.0, lon + 360.0)
+                } else {
+                    (-180.0, lon - 360.0)
+                };
+                let fraction = (edge - previous_lon) / (unwrapped - previous_lon);
+                let crossing = previous_lat + fraction * (lat - previous_lat);
+                part.push((edge, crossing));
+                parts.push(vec![(-edge, crossing)]);
+            }
+        }
+        parts
+            .last_mut()
+            .expect("parts always has a last part")
+            .push((lon, lat));
+    }
+
+    parts.retain(|part| !part.is_empty());
+    parts
+}
+
+#[derive(Deserialize)]
+struct OsrmResponse {
+    code: String,
+    #[serde(default)]
+    routes: Vec<OsrmRoute>,
+}
+
+#[derive(Deserialize)]
+struct OsrmRoute {
+    geometry: OsrmGeometry,
+}
+
+#[derive(Deserialize)]
+struct OsrmGeometry {
+    coordinates: Vec<Vec<f64>>,
+}
+
+fn route(osrm: &Url, profile: &str, waypoints: &[Position]) -> Result<Vec<Position>> {
+    let stops = waypoints
+        .iter()
+        .map(|(lon, lat)| format!("{:.6},{:.6}", lon, lat))
+        .collect::<Vec<String>>()
+        .join(";");
+    let url = format!(
+        "{}/route/v1/{}/{}?overview=full&geometries=geojson",
+        osrm.as_str().trim_end_matches('/'),
+        profile,
+        stops
+    );
+
+    let response = CLIENT
+        .get(&url)
+        .header(
+            USER_AGENT,
+            format!("{} v{}", env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION")),
+        )
+        .timeout(OSRM_TIMEOUT)
+        .send()?
+        .json::<OsrmResponse>()?;
+
+    match response.routes.into_iter().next() {
+        Some(route) if response.code == "Ok" => Ok(route
+            .geometry
+            .coordinates
+            .into_iter()
+            .filter(|c| c.len() >= 2)
+            .map(|c| (c[0], c[1]))
+            .collect()),
+        _ => bail!("Routing {} failed with {}", stops, response.code),
+    }
+}
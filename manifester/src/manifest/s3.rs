@@ -0,0 +1,336 @@
+use anyhow::{anyhow, bail, Result};
+use chrono::Utc;
+use hmac::{Hmac, Mac};
+use percent_encoding::{utf8_percent_encode, AsciiSet, NON_ALPHANUMERIC};
+use quick_xml::events::Event;
+use quick_xml::Reader;
+use reqwest::blocking::{Client, Response};
+use reqwest::Method;
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::env;
+use url::Url;
+
+/// Characters left as they are in a signed path or query, the unreserved ones of RFC 3986.
+const UNRESERVED: &AsciiSet = &NON_ALPHANUMERIC
+    .remove(b'-')
+    .remove(b'.')
+    .remove(b'_')
+    .remove(b'~');
+
+/// manifest.yaml `deploy: s3:`, the bucket the site is published to. The credentials are read from
+/// `AWS_ACCESS_KEY_ID`, `AWS_SECRET_ACCESS_KEY` and, for temporary ones, `AWS_SESSION_TOKEN`.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct S3 {
+    /// Base URL of an S3 compatible service, such as `https://s3.eu-west-1.amazonaws.com` or a local
+    /// MinIO at `http://127.0.0.1:9000`.
+    pub endpoint: Url,
+    #[serde(default = "default_region")]
+    pub region: String,
+    pub bucket: String,
+    /// Folder of the bucket the site is published under, which ends with a `/` even if it's written
+    /// without one, so that `site` doesn't take in `site-old`.
+    #[serde(default, deserialize_with = "folder")]
+    pub prefix: String,
+    /// Name the bucket in the path instead of the host name, as MinIO and most local services need.
+    #[serde(default)]
+    pub path_style: bool,
+}
+
+fn default_region() -> String {
+    "us-east-1".to_string()
+}
+
+fn folder<'de, D>(deserializer: D) -> Result<String, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    let mut prefix = <String as serde::Deserialize>::deserialize(deserializer)?;
+    if !prefix.is_empty() && !prefix.ends_with('/') {
+        prefix.push('/');
+    }
+    Ok(prefix)
+}
+
+impl S3 {
+    pub fn validate(&self) -> Result<()> {
+        if self.endpoint.host_str().is_none() {
+            bail!("deploy s3 endpoint {} has no host", self.endpoint);
+        }
+        if self.bucket.is_empty() {
+            bail!("deploy s3 bucket is empty");
+        }
+
+        Ok(())
+    }
+}
+
+struct Credentials {
+    access_key: String,
+    secret_key: String,
+    session_token: Option<String>,
+}
+
+impl Credentials {
+    fn from_env() -> Result<Self> {
+        let var = |name| env::var(name).map_err(|_| anyhow!("{} is not set", name));
+        Ok(Credentials {
+            access_key: var("AWS_ACCESS_KEY_ID")?,
+            secret_key: var("AWS_SECRET_ACCESS_KEY")?,
+            session_token: env::var("AWS_SESSION_TOKEN").ok(),
+        })
+    }
+}
+
+/// The objects of a bucket under the configured prefix, with requests signed by AWS Signature
+/// Version 4.
+pub struct Bucket<'a> {
+    config: &'a S3,
+    credentials: Credentials,
+    client: Client,
+}
+
+fn hmac(key: &[u8], data: &str) -> Vec<u8> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("HMAC takes keys of any length");
+    mac.update(data.as_bytes());
+    mac.finalize().into_bytes().to_vec()
+}
+
+fn encode(value: &str) -> String {
+    utf8_percent_encode(value, UNRESERVED).to_string()
+}
+
+impl<'a> Bucket<'a> {
+    pub fn connect(config: &'a S3) -> Result<Self> {
+        Ok(Bucket {
+            config,
+            credentials: Credentials::from_env()?,
+            client: Client::new(),
+        })
+    }
+
+    /// The full key of `name`, a path relative to dist.
+    pub fn key(&self, name: &str) -> String {
+        format!("{}{}", self.config.prefix, name)
+    }
+
+    /// The host requests go to, with the bucket in it unless the path names it.
+    fn host(&self) -> String {
+        let endpoint = &self.config.endpoint;
+        let host = endpoint.host_str().unwrap_or_default();
+        let host = match endpoint.port() {
+            Some(port) => format!("{}:{}", host, port),
+            None => host.to_string(),
+        };
+        if self.config.path_style {
+            host
+        } else {
+            format!("{}.{}", self.config.bucket, host)
+        }
+    }
+
+    /// The encoded path of `key`, or of the bucket without one.
+    fn path(&self, key: Option<&str>) -> String {
+        let mut path = String::new();
+        if self.config.path_style {
+            path.push('/');
+            path.push_str(&encode(&self.config.bucket));
+        }
+        path.push('/');
+        if let Some(key) = key {
+            path.push_str(&key.split('/').map(encode).collect::<Vec<_>>().join("/"));
+        }
+        path
+    }
+
+    /// The `Authorization` header of a request with the given parts, all but the payload hash already
+    /// canonical.
+    fn authorization(
+        &self,
+        method: &Method,
+        path: &str,
+        query: &str,
+        headers: &BTreeMap<String, String>,
+        timestamp: &str,
+    ) -> String {
+        let date = &timestamp[..8];
+        let scope = format!("{}/{}/s3/aws4_request", date, self.config.region);
+
+        let signed_headers = headers.keys().cloned().collect::<Vec<_>>().join(";");
+        let canonical_headers = headers
+            .iter()
+            .map(|(name, value)| format!("{}:{}\n", name, value.trim()))
+            .collect::<String>();
+        let canonical_request = format!(
+            "{}\n{}\n{}\n{}\n{}\n{}",
+            method, path, query, canonical_headers, signed_headers, headers["x-amz-content-sha256"]
+        );
+        let string_to_sign = format!(
+            "AWS4-HMAC-SHA256\n{}\n{}\n{}",
+            timestamp,
+            scope,
+            hex::encode(Sha256::digest(canonical_request.as_bytes()))
+        );
+
+        let mut key = format!("AWS4{}", self.credentials.secret_key).into_bytes();
+        for part in [date, &self.config.region, "s3", "aws4_request"] {
+            key = hmac(&key, part);
+        }
+        format!(
+            "AWS4-HMAC-SHA256 Credential={}/{},SignedHeaders={},Signature={}",
+            self.credentials.access_key,
+            scope,
+            signed_headers,
+            hex::encode(hmac(&key, &string_to_sign))
+        )
+    }
+
+    /// Signs and sends a request, failing on any status but a success.
+    fn send(
+        &self,
+        method: Method,
+        key: Option<&str>,
+        query: &[(&str, &str)],
+        extra_headers: &[(&str, &str)],
+        body: Vec<u8>,
+    ) -> Result<Response> {
+        let path = self.path(key);
+        let mut query = query
+            .iter()
+            .map(|(name, value)| format!("{}={}", encode(name), encode(value)))
+            .collect::<Vec<_>>();
+        query.sort();
+        let query = query.join("&");
+
+        let timestamp = Utc::now().format("%Y%m%dT%H%M%SZ").to_string();
+        let mut headers = BTreeMap::new();
+        headers.insert("host".to_string(), self.host());
+        headers.insert(
+            "x-amz-content-sha256".to_string(),
+            hex::encode(Sha256::digest(&body)),
+        );
+        headers.insert("x-amz-date".to_string(), timestamp.clone());
+        if let Some(token) = &self.credentials.session_token {
+            headers.insert("x-amz-security-token".to_string(), token.clone());
+        }
+        let authorization = self.authorization(&method, &path, &query, &headers, &timestamp);
+
+        let mut url = format!(
+            "{}://{}{}",
+            self.config.endpoint.scheme(),
+            self.host(),
+            path
+        );
+        if !query.is_empty() {
+            url.push('?');
+            url.push_str(&query);
+        }
+        let mut request = self
+            .client
+            .request(method.clone(), &url)
+            .header("Authorization", authorization)
+            .body(body);
+        for (name, value) in headers.iter().filter(|(name, _)| *name != "host") {
+            request = request.header(name, value);
+        }
+        for (name, value) in extra_headers {
+            request = request.header(*name, *value);
+        }
+
+        let response = request.send()?;
+        if !response.status().is_success() {
+            bail!(
+                "{} {} failed with {}: {}",
+                method,
+                url,
+                response.status(),
+                response.text().unwrap_or_default()
+            );
+        }
+        Ok(response)
+    }
+
+    /// Every object under the prefix, by key, with its ETag unquoted. The ETag of an object uploaded
+    /// in a single part is the MD5 of its content.
+    pub fn list(&self) -> Result<BTreeMap<String, String>> {
+        let mut objects = BTreeMap::new();
+        let mut continuation: Option<String> = None;
+
+        loop {
+            let mut query = vec![("list-type", "2"), ("prefix", self.config.prefix.as_str())];
+            if let Some(token) = &continuation {
+                query.push(("continuation-token", token));
+            }
+            let xml = self
+                .send(Method::GET, None, &query, &[], Vec::new())?
+                .text()?;
+            let page = read_list(&xml)?;
+            objects.extend(page.objects);
+
+            match page.continuation {
+                Some(token) if page.truncated => continuation = Some(token),
+                _ => return Ok(objects),
+            }
+        }
+    }
+
+    pub fn put(&self, key: &str, body: Vec<u8>, headers: &[(&str, &str)]) -> Result<()> {
+        self.send(Method::PUT, Some(key), &[], headers, body)?;
+        Ok(())
+    }
+
+    pub fn delete(&self, key: &str) -> Result<()> {
+        self.send(Method::DELETE, Some(key), &[], &[], Vec::new())?;
+        Ok(())
+    }
+}
+
+/// One page of a ListObjectsV2 response.
+#[derive(Default)]
+struct ListPage {
+    objects: BTreeMap<String, String>,
+    truncated: bool,
+    continuation: Option<String>,
+}
+
+fn read_list(xml: &str) -> Result<ListPage> {
+    let mut reader = Reader::from_str(xml);
+    let mut page = ListPage::default();
+    let mut text = String::new();
+    let (mut key, mut etag) = (None, None);
+
+    loop {
+        match reader.read_event()? {
+            Event::Start(_) => text.clear(),
+            Event::Text(raw) => text.push_str(&raw.decode()?),
+            Event::CData(raw) => text.push_str(&String::from_utf8_lossy(&raw)),
+            // Entities such as the &quot; around ETags come apart from the text around them.
+            Event::GeneralRef(reference) => {
+                text.push_str(&format!("&{};", reference.decode()?));
+            }
+            Event::End(end) => {
+                let value = quick_xml::escape::unescape(&text)?.into_owned();
+                match end.local_name().as_ref() {
+                    b"Key" => key = Some(value),
+                    b"ETag" => etag = Some(value.trim_matches('"').to_lowercase()),
+                    b"Contents" => {
+                        if let (Some(key), Some(etag)) = (key.take(), etag.take()) {
+                            page.objects.insert(key, etag);
+                        }
+                    }
+                    b"IsTruncated" => page.truncated = value == "true",
+                    b"NextContinuationToken" => page.continuation = Some(value),
+                    _ => {}
+                }
+                text.clear();
+            }
+            Event::Eof => break,
+            _ => {}
+        }
+    }
+
+    if page.truncated && page.continuation.is_none() {
+        bail!("The bucket listing was cut short without a continuation token");
+    }
+    Ok(page)
+}
@@ -0,0 +1,112 @@
+use anyhow::{anyhow, Result};
+use chrono::{Days, NaiveDate};
+use std::collections::{BTreeMap, BTreeSet};
+
+use super::map::{Country, Location, Places, Trip};
+use super::route::{central_angle, Position, EARTH_RADIUS_KM};
+
+/// Travel statistics for one trip, or for every trip when `trips` is more than one.
+#[derive(Debug, Default)]
+pub struct Statistics {
+    pub trips: usize,
+    /// Kilometres along great circles between the cities in visiting order.
+    pub distance: f64,
+    pub countries: usize,
+    pub cities: usize,
+    pub days: i64,
+    pub photos: usize,
+}
+
+pub fn trip_statistics<F>(
+    places: &BTreeMap<Country, Places>,
+    trip: &Trip,
+    position: F,
+    photos: usize,
+) -> Result<Statistics>
+where
+    F: Fn(&Location) -> Result<Position>,
+{
+    let cities = trip.cities();
+
+    let mut distance = 0.0;
+    for pair in cities.windows(2) {
+        distance += central_angle(position(pair[0])?, position(pair[1])?) * EARTH_RADIUS_KM;
+    }
+
+    Ok(Statistics {
+        trips: 1,
+        distance,
+        countries: countries(places, &cities)?.len(),
+        cities: cities.iter().collect::<BTreeSet<_>>().len(),
+        days: trip.days(),
+        photos,
+    })
+}
+
+/// Totals over every trip. Countries and cities visited on more than one trip count once, as do the
+/// days of trips that overlap. `photos` is every photo in the gallery, taken on a trip or not.
+pub fn lifetime_statistics<F>(
+    places: &BTreeMap<Country, Places>,
+    trips: &[&Trip],
+    position: F,
+    photos: usize,
+) -> Result<Statistics>
+where
+    F: Fn(&Location) -> Result<Position>,
+{
+    let mut lifetime = Statistics::default();
+    let mut cities: Vec<&Location> = Vec::new();
+
+    for trip in trips {
+        let stats = trip_statistics(places, trip, &position, 0)?;
+        lifetime.trips += 1;
+        lifetime.distance += stats.distance;
+        cities.extend(trip.cities());
+    }
+
+    lifetime.countries = countries(places, &cities)?.len();
+    lifetime.cities = cities.iter().collect::<BTreeSet<_>>().len();
+    lifetime.days = days(trips);
+    lifetime.photos = photos;
+
+    Ok(lifetime)
+}
+
+/// Days spent on the legs of `trips`, each day once however many legs or trips it is part of.
+fn days(trips: &[&Trip]) -> i64 {
+    let mut legs = trips
+        .iter()
+        .flat_map(|trip| trip.legs.iter().map(|leg| (leg.start, leg.end)))
+        .collect::<Vec<(NaiveDate, NaiveDate)>>();
+    legs.sort();
+
+    let mut days = 0;
+    let mut counted: Option<NaiveDate> = None;
+    for (start, end) in legs {
+        let first = match counted {
+            Some(last) if last >= start => last + Days::new(1),
+            _ => start,
+        };
+        if end >= first {
+            days += (end - first).num_days() + 1;
+        }
+        counted = counted.max(Some(end));
+    }
+    days
+}
+
+fn countries<'a>(
+    places: &'a BTreeMap<Country, Places>,
+    cities: &[&Location],
+) -> Result<BTreeSet<&'a Country>> {
+    cities
+        .iter()
+        .map(|city| {
+            places
+                .iter()
+                .find(|(_, places)| places.locations.contains_key(city))
+                .map(|(country, _)| country)
+                .ok_or(anyhow!("{} is not in places", city.name()))
+        })
+        .collect()
+}
@@ -0,0 +1,347 @@
+use anyhow::Result;
+use chrono::{Datelike, Months, NaiveDate};
+use std::collections::BTreeSet;
+use std::path::Path;
+
+use super::gallery::{read_gallery, GalleryImage};
+use super::map::{find_location, Leg, Location, Mode, Taken, Trip};
+use super::Manifest;
+use crate::utils::to_slug;
+
+/// An image reduced to where it was taken and the days it could have been taken on.
+struct Sighting {
+    location: Location,
+    country: Option<String>,
+    first: NaiveDate,
+    last: NaiveDate,
+    exact: bool,
+}
+
+impl Sighting {
+    fn new(manifest: &Manifest, image: GalleryImage) -> Option<Self> {
+        let (first, last, exact) = match image.taken {
+            Taken::Day(day) => (day, day, true),
+            Taken::Month(year, month) => {
+                let first = NaiveDate::from_ymd_opt(year, month, 1)?;
+                let last = first.checked_add_months(Months::new(1))?.pred_opt()?;
+                (first, last, false)
+            }
+        };
+
+        Some(Sighting {
+            country: country(manifest, &image.location),
+            location: image.location,
+            first,
+            last,
+            exact,
+        })
+    }
+}
+
+/// The name of the country `location` is in.
+fn country(manifest: &Manifest, location: &Location) -> Option<String> {
+    manifest
+        .places
+        .iter()
+        .find(|(_, places)| places.locations.contains_key(location))
+        .map(|(country, _)| country.name())
+}
+
+/// Days between the photo and the trip, none if it was taken during it.
+fn distance(trip: &Trip, sighting: &Sighting) -> i64 {
+    match (trip.start(), trip.end()) {
+        (Some(start), _) if sighting.last < start => (start - sighting.last).num_days(),
+        (_, Some(end)) if sighting.first > end => (sighting.first - end).num_days(),
+        (Some(_), Some(_)) => 0,
+        _ => i64::MAX,
+    }
+}
+
+/// The span of the images with a capture day, month only images make for poor comparisons.
+fn exact_span(sightings: &[&Sighting]) -> Option<(NaiveDate, NaiveDate)> {
+    let exact = sightings.iter().filter(|s| s.exact);
+    let first = exact.clone().map(|s| s.first).min()?;
+    let last = exact.map(|s| s.last).max()?;
+    Some((first, last))
+}
+
+/// Images of one journey in capture order, see `cluster`.
+struct Cluster {
+    sightings: Vec<Sighting>,
+    first: NaiveDate,
+    last: NaiveDate,
+}
+
+impl Cluster {
+    /// Whether `sighting` carries on this journey: it was taken no more than `gap_days` after the
+    /// last image, and in the same country unless that was the day before or the same day.
+    fn continues(&self, sighting: &Sighting, gap_days: i64) -> bool {
+        let gap = (sighting.first - self.last).num_days();
+        let country = self.sightings.last().map(|last| &last.country);
+        gap <= gap_days && (gap <= 1 || country == Some(&sighting.country))
+    }
+
+    /// Splits the images among the `trips` they overlap, each going to the trip it was taken
+    /// during or else the nearest one, so trips close together aren't held to each other's photos.
+    fn assign<'a>(&'a self, trips: &[&'a Trip]) -> Vec<(&'a Trip, Vec<&'a Sighting>)> {
+        let mut assigned = trips
+            .iter()
+            .map(|trip| (*trip, Vec::new()))
+            .collect::<Vec<(&Trip, Vec<&Sighting>)>>();
+        for sighting in &self.sightings {
+            if let Some((_, sightings)) = assigned
+                .iter_mut()
+                .min_by_key(|(trip, _)| distance(trip, sighting))
+            {
+                sightings.push(sighting);
+            }
+        }
+        assigned
+    }
+
+    fn overlaps(&self, trip: &Trip) -> bool {
+        match (trip.start(), trip.end()) {
+            (Some(start), Some(end)) => start <= self.last && self.first <= end,
+            _ => false,
+        }
+    }
+
+    /// A new leg starts whenever the location changes.
+    fn legs(&self) -> Vec<Leg> {
+        let mut legs: Vec<Leg> = Vec::new();
+
+        for sighting in &self.sightings {
+            match legs.last_mut() {
+                Some(leg) if leg.cities.last() == Some(&sighting.location) => {
+                    leg.end = leg.end.max(sighting.last);
+                }
+                // Images only known by month can't be put in order, keep their cities in one leg.
+                Some(leg)
+                    if !sighting.exact
+                        && (leg.start, leg.end) == (sighting.first, sighting.last) =>
+                {
+                    if !leg.cities.contains(&sighting.location) {
+                        leg.cities.push(sighting.location.clone());
+                    }
+                }
+                previous => {
+                    let start = match previous {
+                        Some(leg) => sighting.first.max(leg.end),
+                        None => sighting.first,
+                    };
+                    legs.push(Leg {
+                        cities: vec![sighting.location.clone()],
+                        start,
+                        end: sighting.last.max(start),
+                        mode: Mode::default(),
+                        track: None,
+                    });
+                }
+            }
+        }
+
+        legs
+    }
+}
+
+#[derive(Serialize)]
+struct Suggestions {
+    trips: Vec<Trip>,
+}
+
+/// Prints trips: entries for manifest.yaml built from clusters of photos no trip covers yet, and reports
+/// existing trips that disagree with the photos taken during them.
+pub fn suggest_trips(
+    manifest: &Manifest,
+    gallery: &Path,
+    gap_days: i64,
+    home: &[String],
+) -> Result<()> {
+    let home = home
+        .iter()
+        .map(|h| find_location(&manifest.places, h).cloned())
+        .collect::<Result<Vec<Location>>>()?;
+
+    let mut sightings: Vec<Sighting> = Vec::new();
+    for image in read_gallery(gallery, manifest)? {
+        if let Some(sighting) = Sighting::new(manifest, image) {
+            if !home.contains(&sighting.location) {
+                sightings.push(sighting);
+            }
+        }
+    }
+    sightings.sort_by_key(|s| (s.first, s.last));
+
+    let clusters = cluster(sightings, gap_days);
+
+    let mut ids = manifest.trips.iter().map(Trip::id).collect::<Vec<String>>();
+    let mut suggestions: Vec<Trip> = Vec::new();
+
+    for cluster in &clusters {
+        let covering = manifest
+            .trips
+            .iter()
+            .filter(|trip| cluster.overlaps(trip))
+            .collect::<Vec<&Trip>>();
+
+        if covering.is_empty() {
+            let trip = propose(manifest, cluster, &ids);
+            ids.push(trip.id());
+            suggestions.push(trip);
+        } else {
+            for (trip, sightings) in cluster.assign(&covering) {
+                compare(trip, &sightings);
+            }
+        }
+    }
+
+    for trip in &manifest.trips {
+        if !clusters.iter().any(|cluster| cluster.overlaps(trip)) {
+            eprintln!("{}: no photos were taken during this trip", trip.id());
+        }
+    }
+
+    if suggestions.is_empty() {
+        eprintln!("Every cluster of photos is covered by a trip.");
+    } else {
+        print!(
+            "{}",
+            serde_yaml::to_string(&Suggestions { trips: suggestions })?
+        );
+    }
+
+    Ok(())
+}
+
+/// Groups the sightings, sorted by capture day, into journeys: a new one starts after more than
+/// `gap_days` without photos, or when the photos move to another country after a day without any.
+fn cluster(sightings: Vec<Sighting>, gap_days: i64) -> Vec<Cluster> {
+    let mut clusters: Vec<Cluster> = Vec::new();
+
+    for sighting in sightings {
+        match clusters.last_mut() {
+            Some(cluster) if cluster.continues(&sighting, gap_days) => {
+                cluster.last = cluster.last.max(sighting.last);
+                cluster.sightings.push(sighting);
+            }
+            _ => clusters.push(Cluster {
+                first: sighting.first,
+                last: sighting.last,
+                sightings: vec![sighting],
+            }),
+        }
+    }
+
+    clusters
+}
+
+fn propose(manifest: &Manifest, cluster: &Cluster, ids: &[String]) -> Trip {
+    let legs = cluster.legs();
+
+    let mut cities: Vec<&Location> = Vec::new();
+    for city in legs.iter().flat_map(|leg| &leg.cities) {
+        if !cities.contains(&city) {
+            cities.push(city);
+        }
+    }
+
+    let mut countries: Vec<String> = Vec::new();
+    for city in &cities {
+        if let Some(country) = country(manifest, city) {
+            if !countries.contains(&country) {
+                countries.push(country);
+            }
+        }
+    }
+
+    let description = format!(
+        "{} {} {}",
+        cities
+            .iter()
+            .map(|c| c.name())
+            .collect::<Vec<String>>()
+            .join(" "),
+        countries.join(" "),
+        cluster.first.year()
+    );
+
+    // Trips to the same places in the same year would share a slug, the start day tells them apart.
+    let id = if ids.contains(&to_slug(&description)) {
+        Some(format!(
+            "{}-{}",
+            to_slug(&description),
+            cluster.first.format("%m-%d")
+        ))
+    } else {
+        None
+    };
+
+    let mut trip = Trip {
+        id,
+        name: format!(
+            "{} {}",
+            cities.first().map(|c| c.name()).unwrap_or_default(),
+            cluster.first.format("%Y/%m")
+        ),
+        description,
+        legs,
+        tracks: Vec::new(),
+        tolerance: None,
+        colour: None,
+    };
+
+    // Cities sharing a name with one in another country go by their id.
+    for leg in &mut trip.legs {
+        leg.cities = leg
+            .cities
+            .iter()
+            .map(|city| city.unambiguous(&manifest.places))
+            .collect();
+    }
+
+    trip
+}
+
+/// Reports where `trip` disagrees with the photos assigned to it.
+fn compare(trip: &Trip, sightings: &[&Sighting]) {
+    let trip_cities = trip.cities();
+    let photo_cities = sightings
+        .iter()
+        .map(|s| &s.location)
+        .collect::<BTreeSet<&Location>>();
+
+    for city in &photo_cities {
+        if !trip_cities.contains(city) {
+            eprintln!(
+                "{}: has photos from {} but the trip doesn't visit it",
+                trip.id(),
+                city.name()
+            );
+        }
+    }
+
+    for city in &trip_cities {
+        if !photo_cities.contains(city) {
+            eprintln!(
+                "{}: visits {} but has no photos from it",
+                trip.id(),
+                city.name()
+            );
+        }
+    }
+
+    if let (Some((first, last)), Some(start), Some(end)) =
+        (exact_span(sightings), trip.start(), trip.end())
+    {
+        if first < start || last > end {
+            eprintln!(
+                "{}: photos run from {} to {} but the trip is from {} to {}",
+                trip.id(),
+                first,
+                last,
+                start,
+                end
+            );
+        }
+    }
+}
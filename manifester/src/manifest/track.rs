@@ -0,0 +1,314 @@
+use anyhow::{anyhow, bail, Result};
+use quick_xml::events::{BytesStart, Event};
+use quick_xml::reader::Reader;
+use serde_json::Value;
+use std::fs::File;
+use std::path::Path;
+
+use super::route::{central_angle, Position, EARTH_RADIUS_KM};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TrackPoint {
+    pub position: Position,
+    pub elevation: Option<f64>,
+}
+
+/// Reads the segments of a GPX, KML or GeoJSON track, in recorded order.
+pub fn read_track(path: &Path) -> Result<Vec<Vec<TrackPoint>>> {
+    let extension = path
+        .extension()
+        .and_then(|e| e.to_str())
+        .map(|e| e.to_lowercase());
+
+    let mut segments = match extension.as_deref() {
+        Some("gpx") => read_gpx(path)?,
+        Some("kml") => read_kml(path)?,
+        Some("geojson") | Some("json") => read_geojson(path)?,
+        _ => bail!("{} is not a GPX, KML or GeoJSON track", path.display()),
+    };
+
+    segments.retain(|segment| !segment.is_empty());
+    if segments.is_empty() {
+        bail!("{} has no track points", path.display());
+    }
+
+    Ok(segments)
+}
+
+pub fn is_track(path: &Path) -> bool {
+    matches!(
+        path.extension()
+            .and_then(|e| e.to_str())
+            .map(|e| e.to_lowercase())
+            .as_deref(),
+        Some("gpx") | Some("kml") | Some("geojson") | Some("json")
+    )
+}
+
+/// Track and route points of a GPX file, one segment per `trkseg` or `rte`.
+fn read_gpx(path: &Path) -> Result<Vec<Vec<TrackPoint>>> {
+    let mut reader = Reader::from_file(path)?;
+    let mut buf = Vec::new();
+    let mut segments: Vec<Vec<TrackPoint>> = Vec::new();
+    let mut in_point = false;
+    let mut in_elevation = false;
+
+    loop {
+        match reader.read_event_into(&mut buf)? {
+            Event::Start(e) => match e.local_name().as_ref() {
+                b"trkseg" | b"rte" => segments.push(Vec::new()),
+                b"trkpt" | b"rtept" => {
+                    push_point(&mut segments, gpx_point(&e)?);
+                    in_point = true;
+                }
+                b"ele" if in_point => in_elevation = true,
+                _ => (),
+            },
+            Event::Empty(e) if matches!(e.local_name().as_ref(), b"trkpt" | b"rtept") => {
+                push_point(&mut segments, gpx_point(&e)?);
+            }
+            Event::Text(e) if in_elevation => {
+                if let Some(point) = segments.last_mut().and_then(|s| s.last_mut()) {
+                    point.elevation = e.decode()?.trim().parse::<f64>().ok();
+                }
+            }
+            Event::End(e) => match e.local_name().as_ref() {
+                b"trkpt" | b"rtept" => in_point = false,
+                b"ele" => in_elevation = false,
+                _ => (),
+            },
+            Event::Eof => break,
+            _ => (),
+        }
+        buf.clear();
+    }
+
+    Ok(segments)
+}
+
+fn gpx_point(e: &BytesStart) -> Result<TrackPoint> {
+    Ok(TrackPoint {
+        position: (attribute(e, "lon")?, attribute(e, "lat")?),
+        elevation: None,
+    })
+}
+
+fn attribute(e: &BytesStart, name: &str) -> Result<f64> {
+    let value = e
+        .try_get_attribute(name)?
+        .ok_or(anyhow!("GPX point is missing its {} attribute", name))?;
+    Ok(value.unescape_value()?.trim().parse::<f64>()?)
+}
+
+fn push_point(segments: &mut Vec<Vec<TrackPoint>>, point: TrackPoint) {
+    match segments.last_mut() {
+        Some(segment) => segment.push(point),
+        None => segments.push(vec![point]),
+    }
+}
+
+enum KmlText {
+    /// `lon,lat[,alt]` tuples separated by whitespace.
+    Coordinates,
+    /// A single `lon lat [alt]`.
+    Coord,
+}
+
+/// `LineString` coordinates and `gx:Track` coordinates of a KML file, one segment per line or track.
+fn read_kml(path: &Path) -> Result<Vec<Vec<TrackPoint>>> {
+    let mut reader = Reader::from_file(path)?;
+    let mut buf = Vec::new();
+    let mut segments: Vec<Vec<TrackPoint>> = Vec::new();
+    let mut in_line = false;
+    let mut text: Option<KmlText> = None;
+
+    loop {
+        match reader.read_event_into(&mut buf)? {
+            Event::Start(e) => match e.local_name().as_ref() {
+                b"LineString" | b"Track" => {
+                    segments.push(Vec::new());
+                    in_line = true;
+                }
+                b"coordinates" if in_line => text = Some(KmlText::Coordinates),
+                b"coord" if in_line => text = Some(KmlText::Coord),
+                _ => (),
+            },
+            Event::Text(e) => match text {
+                Some(KmlText::Coordinates) => {
+                    for tuple in e.decode()?.split_whitespace() {
+                        push_point(&mut segments, kml_point(tuple.split(','))?);
+                    }
+                }
+                Some(KmlText::Coord) => {
+                    push_point(&mut segments, kml_point(e.decode()?.split_whitespace())?)
+                }
+                None => (),
+            },
+            Event::End(e) => match e.local_name().as_ref() {
+                b"LineString" | b"Track" => in_line = false,
+                b"coordinates" | b"coord" => text = None,
+                _ => (),
+            },
+            Event::Eof => break,
+            _ => (),
+        }
+        buf.clear();
+    }
+
+    Ok(segments)
+}
+
+fn kml_point<'a, I>(mut values: I) -> Result<TrackPoint>
+where
+    I: Iterator<Item = &'a str>,
+{
+    let lon = values
+        .next()
+        .ok_or(anyhow!("KML coordinate is missing its longitude"))?;
+    let lat = values
+        .next()
+        .ok_or(anyhow!("KML coordinate is missing its latitude"))?;
+
+    Ok(TrackPoint {
+        position: (lon.trim().parse::<f64>()?, lat.trim().parse::<f64>()?),
+        elevation: values.next().and_then(|alt| alt.trim().parse::<f64>().ok()),
+    })
+}
+
+/// Every `LineString` and `MultiLineString` in a GeoJSON file, whether bare or in features.
+fn read_geojson(path: &Path) -> Result<Vec<Vec<TrackPoint>>> {
+    let geojson: Value = serde_json::from_reader(File::open(path)?)?;
+    let mut segments: Vec<Vec<TrackPoint>> = Vec::new();
+    collect_geojson(&geojson, &mut segments)?;
+    Ok(segments)
+}
+
+fn collect_geojson(value: &Value, segments: &mut Vec<Vec<TrackPoint>>) -> Result<()> {
+    match value["type"].as_str() {
+        Some("FeatureCollection") => {
+            for feature in value["features"].as_array().into_iter().flatten() {
+                collect_geojson(feature, segments)?;
+            }
+        }
+        Some("Feature") => collect_geojson(&value["geometry"], segments)?,
+        Some("GeometryCollection") => {
+            for geometry in value["geometries"].as_array().into_iter().flatten() {
+                collect_geojson(geometry, segments)?;
+            }
+        }
+        Some("LineString") => segments.push(geojson_line(&value["coordinates"])?),
+        Some("MultiLineString") => {
+            for line in value["coordinates"].as_array().into_iter().flatten() {
+                segments.push(geojson_line(line)?);
+            }
+        }
+        _ => (),
+    }
+    Ok(())
+}
+
+fn geojson_line(coordinates: &Value) -> Result<Vec<TrackPoint>> {
+    coordinates
+        .as_array()
+        .ok_or(anyhow!("GeoJSON line has no coordinates"))?
+        .iter()
+        .map(|position| {
+            let lon = position[0].as_f64();
+            let lat = position[1].as_f64();
+            match (lon, lat) {
+                (Some(lon), Some(lat)) => Ok(TrackPoint {
+                    position: (lon, lat),
+                    elevation: position[2].as_f64(),
+                }),
+                _ => bail!(
+                    "GeoJSON position {} is not a longitude and latitude",
+                    position
+                ),
+            }
+        })
+        .collect()
+}
+
+/// Douglas-Peucker simplification, dropping points closer than `tolerance` metres to the line kept.
+pub fn simplify(points: &[TrackPoint], tolerance: f64) -> Vec<Position> {
+    if points.len() < 3 {
+        return points.iter().map(|p| p.position).collect();
+    }
+
+    // A local equirectangular projection in metres is plenty accurate over the length of a track.
+    let latitude = points[0].position.1.to_radians().cos();
+    let metres = 1000.0 * EARTH_RADIUS_KM * std::f64::consts::PI / 180.0;
+    let projected = points
+        .iter()
+        .map(|p| (p.position.0 * metres * latitude, p.position.1 * metres))
+        .collect::<Vec<(f64, f64)>>();
+
+    let mut keep = vec![false; points.len()];
+    keep[0] = true;
+    keep[points.len() - 1] = true;
+
+    let mut stack = vec![(0, points.len() - 1)];
+    while let Some((first, last)) = stack.pop() {
+        let mut furthest = (first, 0.0);
+        for idx in first + 1..last {
+            let distance = perpendicular(projected[idx], projected[first], projected[last]);
+            if distance > furthest.1 {
+                furthest = (idx, distance);
+            }
+        }
+
+        if furthest.1 > tolerance {
+            keep[furthest.0] = true;
+            stack.push((first, furthest.0));
+            stack.push((furthest.0, last));
+        }
+    }
+
+    points
+        .iter()
+        .zip(keep)
+        .filter(|(_, keep)| *keep)
+        .map(|(p, _)| p.position)
+        .collect()
+}
+
+fn perpendicular(point: (f64, f64), start: (f64, f64), end: (f64, f64)) -> f64 {
+    let (dx, dy) = (end.0 - start.0, end.1 - start.1);
+    let length = (dx * dx + dy * dy).sqrt();
+    if length == 0.0 {
+        return ((point.0 - start.0).powi(2) + (point.1 - start.1).powi(2)).sqrt();
+    }
+    (dy * point.0 - dx * point.1 + end.0 * start.1 - end.1 * start.0).abs() / length
+}
+
+/// Distance and climbing over recorded tracks, from the unsimplified points.
+#[derive(Debug, Default)]
+pub struct TrackStats {
+    /// Kilometres.
+    pub distance: f64,
+    /// Metres climbed and descended.
+    pub ascent: f64,
+    pub descent: f64,
+    pub highest: Option<f64>,
+    pub lowest: Option<f64>,
+}
+
+impl TrackStats {
+    pub fn add(&mut self, segment: &[TrackPoint]) {
+        for pair in segment.windows(2) {
+            self.distance += central_angle(pair[0].position, pair[1].position) * EARTH_RADIUS_KM;
+            if let (Some(from), Some(to)) = (pair[0].elevation, pair[1].elevation) {
+                if to > from {
+                    self.ascent += to - from;
+                } else {
+                    self.descent += from - to;
+                }
+            }
+        }
+
+        for elevation in segment.iter().filter_map(|p| p.elevation) {
+            self.highest = Some(self.highest.map_or(elevation, |h| h.max(elevation)));
+            self.lowest = Some(self.lowest.map_or(elevation, |l| l.min(elevation)));
+        }
+    }
+}
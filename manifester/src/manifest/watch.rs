@@ -0,0 +1,272 @@
+use anyhow::{anyhow, Result};
+use notify::event::{AccessKind, AccessMode};
+use notify::{EventKind, RecursiveMode, Watcher};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc;
+use std::time::{Duration, Instant};
+
+use super::gallery::{is_original, GalleryImage};
+use super::{Config, Manifest, Project};
+
+/// What a batch of debounced events asks to be redone.
+#[derive(Default)]
+struct Changes {
+    manifest: bool,
+    rescan: bool,
+    added: BTreeSet<PathBuf>,
+    removed: BTreeSet<PathBuf>,
+    sidecars: BTreeSet<PathBuf>,
+}
+
+impl Changes {
+    fn is_empty(&self) -> bool {
+        !self.manifest
+            && !self.rescan
+            && self.added.is_empty()
+            && self.removed.is_empty()
+            && self.sidecars.is_empty()
+    }
+}
+
+/// The project being watched and the gallery as last read, by path relative to the gallery.
+struct Watched {
+    manifest_yaml: PathBuf,
+    gallery: PathBuf,
+    project: Project,
+    images: BTreeMap<PathBuf, GalleryImage>,
+    /// Descriptions the last build created empty, whose events are the build's own.
+    created: BTreeSet<PathBuf>,
+}
+
+/// Builds once, then watches the gallery, its sidecars and `manifest_yaml`, and rebuilds once changes
+/// have settled for `debounce`. Only new and modified photos get new thumbnails and blurs, the
+/// outputs are rewritten each time, and `on_build` is called after every build that succeeds. A
+/// failed rebuild is reported and watching goes on.
+pub fn watch<F: FnMut()>(
+    manifest_yaml: &Path,
+    config: Config,
+    debounce: Duration,
+    mut on_build: F,
+) -> Result<()> {
+    let manifest_yaml = manifest_yaml.canonicalize()?;
+    let gallery = config.gallery.canonicalize()?;
+    let project = Project::new(Manifest::read(&manifest_yaml)?, config)?;
+
+    let mut watched = Watched {
+        manifest_yaml,
+        gallery,
+        project,
+        images: BTreeMap::new(),
+        created: BTreeSet::new(),
+    };
+    let start = Instant::now();
+    watched.rescan(false)?;
+    watched.project.derivatives(&watched.gallery_images())?;
+    watched.write()?;
+    watched.created.clear();
+    println!("Built in {:.1}s.", start.elapsed().as_secs_f32());
+    on_build();
+
+    let (tx, rx) = mpsc::channel();
+    let mut watcher = notify::recommended_watcher(tx)?;
+    watcher.watch(&watched.gallery, RecursiveMode::Recursive)?;
+    let manifest_dir = watched
+        .manifest_yaml
+        .parent()
+        .ok_or(anyhow!("{} has no parent", watched.manifest_yaml.display()))?;
+    watcher.watch(manifest_dir, RecursiveMode::NonRecursive)?;
+    println!(
+        "Watching {} and {} for changes.",
+        watched.gallery.display(),
+        watched.manifest_yaml.display()
+    );
+
+    while let Ok(event) = rx.recv() {
+        let mut paths = BTreeSet::new();
+        let mut next = Some(event);
+        while let Some(event) = next {
+            match event {
+                // Opening and reading files, as every build does, changes nothing.
+                Ok(event)
+                    if matches!(event.kind, EventKind::Access(kind)
+                        if kind != AccessKind::Close(AccessMode::Write)) => {}
+                Ok(event) => paths.extend(event.paths),
+                Err(error) => eprintln!("Watch error: {}", error),
+            }
+            next = rx.recv_timeout(debounce).ok();
+        }
+
+        let changes = watched.classify(&paths);
+        if changes.is_empty() {
+            continue;
+        }
+
+        let start = Instant::now();
+        match watched.rebuild(changes) {
+            Ok(()) => {
+                println!("Rebuilt in {:.1}s.", start.elapsed().as_secs_f32());
+                on_build();
+            }
+            Err(error) => eprintln!("Rebuild failed: {:#}", error),
+        }
+    }
+    Ok(())
+}
+
+impl Watched {
+    fn gallery_images(&self) -> Vec<GalleryImage> {
+        self.images.values().cloned().collect()
+    }
+
+    /// Sorts changed paths into the manifest, originals and sidecars, dropping the derivatives the
+    /// builds make themselves and anything else in the manifest's directory.
+    fn classify(&mut self, paths: &BTreeSet<PathBuf>) -> Changes {
+        let mut changes = Changes::default();
+
+        for path in paths {
+            if *path == self.manifest_yaml {
+                changes.manifest = true;
+                continue;
+            }
+            let relative = match path.strip_prefix(&self.gallery) {
+                Ok(relative) => relative.to_path_buf(),
+                Err(_) => continue,
+            };
+
+            if path.is_dir() {
+                // A directory moved in brings photos no event was sent for.
+                changes.rescan = true;
+            } else if is_original(path) {
+                if path.exists() {
+                    changes.added.insert(relative);
+                } else {
+                    changes.removed.insert(relative);
+                }
+            } else if !path.exists() && self.images.keys().any(|p| p.starts_with(&relative)) {
+                // A directory was removed, with every photo in it.
+                changes.removed.extend(
+                    self.images
+                        .keys()
+                        .filter(|p| p.starts_with(&relative))
+                        .cloned(),
+                );
+            } else if matches!(
+                path.extension().and_then(|e| e.to_str()),
+                Some("desc") | Some("trip")
+            ) && !self.created.contains(&relative)
+            {
+                changes.sidecars.insert(relative);
+            }
+        }
+        // The sidecars of removed photos went with them.
+        let removed = &changes.removed;
+        changes.sidecars.retain(|sidecar| {
+            self.gallery.join(sidecar).exists()
+                || !removed
+                    .iter()
+                    .any(|photo| photo.with_extension("") == sidecar.with_extension(""))
+        });
+        // Events for what the last build created come in the batch right after it.
+        self.created.clear();
+        changes
+    }
+
+    fn rebuild(&mut self, changes: Changes) -> Result<()> {
+        if changes.manifest {
+            println!("~ {}", self.manifest_yaml.display());
+            let manifest = Manifest::read(&self.manifest_yaml)?;
+            self.project = Project::new(manifest, self.project.config.clone())?;
+        }
+
+        for relative in &changes.removed {
+            println!("- {}", relative.display());
+            if let Some(image) = self.images.remove(relative) {
+                let (thumbnail, blur) = image.derivative_paths()?;
+                let _ = fs::remove_file(thumbnail);
+                let _ = fs::remove_file(blur);
+            }
+        }
+
+        let mut changed = Vec::new();
+        for relative in &changes.added {
+            let path = self.project.config.gallery.join(relative);
+            let image =
+                GalleryImage::read(&path, &self.project.config.gallery, &self.project.manifest)?;
+            match self.images.insert(relative.clone(), image.clone()) {
+                Some(_) => {
+                    println!("~ {}", relative.display());
+                    // The old thumbnail and blur are of what the photo was.
+                    let (thumbnail, blur) = image.derivative_paths()?;
+                    let _ = fs::remove_file(thumbnail);
+                    let _ = fs::remove_file(blur);
+                }
+                None => println!("+ {}", relative.display()),
+            }
+            changed.push(image);
+        }
+
+        for relative in &changes.sidecars {
+            println!("* {}", relative.display());
+        }
+
+        // Where photos are filed depends on the places in the manifest.
+        if changes.manifest || changes.rescan {
+            self.rescan(true)?;
+        }
+        if changes.rescan {
+            self.project.derivatives(&self.gallery_images())?;
+        } else if !changed.is_empty() {
+            self.project.derivatives(&changed)?;
+        }
+        self.write()
+    }
+
+    /// Reads the whole gallery again, listing what was added or removed since it was last read if `log`.
+    fn rescan(&mut self, log: bool) -> Result<()> {
+        let images: BTreeMap<PathBuf, GalleryImage> = self
+            .project
+            .scan()?
+            .into_iter()
+            .map(|image| {
+                let relative = image
+                    .path
+                    .strip_prefix(&self.project.config.gallery)?
+                    .to_path_buf();
+                Ok((relative, image))
+            })
+            .collect::<Result<_>>()?;
+
+        if log {
+            for relative in images.keys().filter(|p| !self.images.contains_key(*p)) {
+                println!("+ {}", relative.display());
+            }
+            for relative in self.images.keys().filter(|p| !images.contains_key(*p)) {
+                println!("- {}", relative.display());
+            }
+        }
+        self.images = images;
+        Ok(())
+    }
+
+    /// Writes the outputs, remembering the empty descriptions the write creates.
+    fn write(&mut self) -> Result<()> {
+        let images = self.gallery_images();
+        let missing = self
+            .images
+            .iter()
+            .filter(|(_, image)| !image.path.with_extension("desc").exists())
+            .map(|(relative, _)| relative.with_extension("desc"))
+            .collect::<Vec<_>>();
+
+        self.project.write(&images)?;
+
+        self.created.extend(
+            missing
+                .into_iter()
+                .filter(|relative| self.gallery.join(relative).exists()),
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,279 @@
+use anyhow::{anyhow, Result};
+use percent_encoding::percent_decode_str;
+use std::fs::{self, File};
+use std::io::{self, Read, Seek, SeekFrom};
+use std::path::{Component, Path, PathBuf};
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread;
+use std::time::Duration;
+use tiny_http::{Header, Method, Request, Response, Server, StatusCode};
+
+use super::manifest::{make_derivatives, original_of};
+use super::utils::mime_type;
+
+/// Pages served with live reload long-poll this for the number of builds so far.
+const LIVE_RELOAD_PATH: &str = "/__livereload";
+
+/// How long a live reload poll waits for a build before answering with the same count.
+const LIVE_RELOAD_POLL: Duration = Duration::from_secs(30);
+
+const LIVE_RELOAD_SCRIPT: &str = r#"<script>
+(function poll(build) {
+  fetch("/__livereload?since=" + build)
+    .then(function (response) { return response.text(); })
+    .then(function (next) { if (build !== "" && next !== build) location.reload(); else poll(next); })
+    .catch(function () { setTimeout(function () { poll(build); }, 1000); });
+})("");
+</script>
+"#;
+
+/// Counts builds, and wakes up the pages waiting for the next one so they reload.
+#[derive(Default)]
+pub struct LiveReload {
+    builds: Mutex<u64>,
+    built: Condvar,
+}
+
+impl LiveReload {
+    /// Tells every page served that a build has finished.
+    pub fn reload(&self) {
+        *self.builds.lock().unwrap() += 1;
+        self.built.notify_all();
+    }
+
+    /// The number of builds, once it is no longer `since` or the poll times out.
+    fn wait(&self, since: Option<u64>) -> u64 {
+        let builds = self.builds.lock().unwrap();
+        match since {
+            Some(since) => {
+                *self
+                    .built
+                    .wait_timeout_while(builds, LIVE_RELOAD_POLL, |builds| *builds == since)
+                    .unwrap()
+                    .0
+            }
+            None => *builds,
+        }
+    }
+}
+
+/// What is served and how.
+struct Site {
+    root: PathBuf,
+    derivatives: bool,
+    live_reload: Option<Arc<LiveReload>>,
+}
+
+/// Serves the files under `root` at `address` like the static host does: paths without an extension
+/// that aren't files get `index.html` so the Elm application routes them, anything else missing gets
+/// `404.html`. With `derivatives`, missing thumbnails and blurs are made from their originals when
+/// first asked for. With `live_reload`, HTML pages reload after every build it is told of.
+pub fn serve(
+    root: &Path,
+    address: &str,
+    derivatives: bool,
+    live_reload: Option<Arc<LiveReload>>,
+) -> Result<()> {
+    let server =
+        Server::http(address).map_err(|e| anyhow!("Can't listen on {}: {}", address, e))?;
+    let site = Arc::new(Site {
+        root: root.to_path_buf(),
+        derivatives,
+        live_reload,
+    });
+    println!(
+        "Serving {} at http://{}/",
+        root.display(),
+        server
+            .server_addr()
+            .to_ip()
+            .ok_or(anyhow!("{} is not an IP address", address))?
+    );
+
+    for request in server.incoming_requests() {
+        let site = Arc::clone(&site);
+        // Live reload polls wait for a build, so every request gets its own thread.
+        thread::spawn(move || {
+            let url = request.url().to_string();
+            if let Err(error) = site.respond(request) {
+                eprintln!("{}: {}", url, error);
+            }
+        });
+    }
+    Ok(())
+}
+
+impl Site {
+    fn respond(&self, request: Request) -> io::Result<()> {
+        if ![Method::Get, Method::Head].contains(request.method()) {
+            return request.respond(Response::empty(405));
+        }
+
+        let url = request.url().to_string();
+        let (path, query) = url.split_once('?').unwrap_or((&url, ""));
+
+        if let (LIVE_RELOAD_PATH, Some(live_reload)) = (path, &self.live_reload) {
+            let since = query
+                .split('&')
+                .find_map(|pair| pair.strip_prefix("since="))
+                .and_then(|since| since.parse().ok());
+            let builds = live_reload.wait(since);
+            return request.respond(
+                Response::from_string(builds.to_string())
+                    .with_header(header("Cache-Control", "no-store")),
+            );
+        }
+
+        let (file, status) = match self.resolve(path) {
+            Some(file) if file.is_file() => (file, 200),
+            // Routes of the Elm application, which are pushed to the history and look like paths.
+            Some(file) if file.extension().is_none() => (self.root.join("index.html"), 200),
+            _ => (self.root.join("404.html"), 404),
+        };
+        if !file.is_file() {
+            return request.respond(Response::from_string("Not Found").with_status_code(404));
+        }
+
+        if mime_type(&file) == "text/html; charset=utf-8" {
+            let mut html = fs::read_to_string(&file)?;
+            if self.live_reload.is_some() {
+                let end = html.rfind("</body>").unwrap_or(html.len());
+                html.insert_str(end, LIVE_RELOAD_SCRIPT);
+            }
+            return request.respond(
+                Response::from_string(html)
+                    .with_status_code(status)
+                    .with_header(header("Content-Type", mime_type(&file)))
+                    .with_header(header("Cache-Control", "no-cache")),
+            );
+        }
+
+        let mut body = File::open(&file)?;
+        let len = body.metadata()?.len();
+        let mut headers = vec![
+            header("Content-Type", mime_type(&file)),
+            header("Cache-Control", "no-cache"),
+            header("Accept-Ranges", "bytes"),
+        ];
+        let range = request
+            .headers()
+            .iter()
+            .find(|h| h.field.equiv("Range"))
+            .map(|h| byte_range(h.value.as_str(), len))
+            .unwrap_or(Range::Whole);
+
+        let response = match range {
+            Range::Whole => Response::new(
+                StatusCode(status),
+                headers,
+                Box::new(body) as Box<dyn Read + Send>,
+                Some(len as usize),
+                None,
+            ),
+            Range::Part(start, end) => {
+                body.seek(SeekFrom::Start(start))?;
+                headers.push(header(
+                    "Content-Range",
+                    &format!("bytes {}-{}/{}", start, end, len),
+                ));
+                Response::new(
+                    StatusCode(206),
+                    headers,
+                    Box::new(body.take(end - start + 1)) as Box<dyn Read + Send>,
+                    Some((end - start + 1) as usize),
+                    None,
+                )
+            }
+            Range::Unsatisfiable => {
+                headers.push(header("Content-Range", &format!("bytes */{}", len)));
+                Response::new(
+                    StatusCode(416),
+                    headers,
+                    Box::new(io::empty()) as Box<dyn Read + Send>,
+                    Some(0),
+                    None,
+                )
+            }
+        };
+        request.respond(response)
+    }
+
+    /// The file under the root a request path names, with the derivative it asks for made first if
+    /// that's wanted. Paths leaving the root name nothing.
+    fn resolve(&self, path: &str) -> Option<PathBuf> {
+        let decoded = percent_decode_str(path).decode_utf8().ok()?;
+        let relative = Path::new(decoded.trim_start_matches('/'));
+        if !relative
+            .components()
+            .all(|component| matches!(component, Component::Normal(_)))
+        {
+            return None;
+        }
+
+        let mut file = self.root.join(relative);
+        if file.is_dir() {
+            file.push("index.html");
+        }
+
+        if self.derivatives && !file.exists() {
+            if let Some(original) = original_of(&file).filter(|original| original.is_file()) {
+                if let Err(error) = make_derivatives(&original) {
+                    eprintln!("{}: {:#}", original.display(), error);
+                }
+            }
+        }
+        Some(file)
+    }
+}
+
+/// What a `Range` header asks of a file.
+#[derive(Debug, PartialEq)]
+enum Range {
+    /// No range, or one this server doesn't take apart such as several ranges at once.
+    Whole,
+    /// The bytes from the first to the second offset, inclusive.
+    Part(u64, u64),
+    /// A range starting past the end of the file.
+    Unsatisfiable,
+}
+
+/// Reads a `bytes=start-end`, `bytes=start-` or `bytes=-suffix` range of a `len` byte file.
+fn byte_range(header: &str, len: u64) -> Range {
+    let spec = match header.trim().strip_prefix("bytes=") {
+        Some(spec) if !spec.contains(',') => spec,
+        _ => return Range::Whole,
+    };
+    let (start, end) = match spec.split_once('-') {
+        Some((start, end)) => (start.trim(), end.trim()),
+        None => return Range::Whole,
+    };
+
+    if start.is_empty() {
+        return match end.parse::<u64>() {
+            Ok(suffix) if suffix.min(len) > 0 => Range::Part(len - suffix.min(len), len - 1),
+            Ok(_) => Range::Unsatisfiable,
+            Err(_) => Range::Whole,
+        };
+    }
+    let start = match start.parse::<u64>() {
+        Ok(start) => start,
+        Err(_) => return Range::Whole,
+    };
+    let end = match end {
+        "" => u64::MAX,
+        end => match end.parse::<u64>() {
+            Ok(end) if end >= start => end,
+            _ => return Range::Whole,
+        },
+    };
+
+    if start >= len {
+        Range::Unsatisfiable
+    } else {
+        Range::Part(start, end.min(len - 1))
+    }
+}
+
+fn header(field: &str, value: &str) -> Header {
+    Header::from_bytes(field, value).expect("headers are ASCII")
+}
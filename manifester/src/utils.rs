@@ -1,12 +1,75 @@
-pub fn to_location_identfier_string(from: &str) -> String {
-    let mut identifier = from.clone().to_string();
+use std::path::Path;
 
-    identifier.retain(|c| c != ' ' && c != '_');
+/// Lowercase, hyphen separated ASCII slug suitable for URLs: "Jeju Korea 2021/08" becomes "jeju-korea-2021-08".
+pub fn to_slug(from: &str) -> String {
+    let mut slug = String::new();
 
-    if identifier == "Singapore" || identifier == "HongKong" {
-        identifier.push_str("City");
+    for c in from.chars() {
+        if c.is_ascii_alphanumeric() {
+            slug.push(c.to_ascii_lowercase());
+        } else if !slug.is_empty() && !slug.ends_with('-') {
+            slug.push('-');
+        }
     }
 
-    identifier
+    while slug.ends_with('-') {
+        slug.pop();
+    }
+
+    slug
+}
+
+/// Whether `from` is already a slug, i.e. `to_slug` leaves it untouched.
+pub fn is_slug(from: &str) -> bool {
+    !from.is_empty() && to_slug(from) == from
+}
+
+/// Turns a slug into a valid Elm constructor: "2021-jeju" becomes "Trip2021Jeju".
+pub fn to_constructor(slug: &str, prefix: &str) -> String {
+    let mut constructor = String::new();
+
+    for part in slug.split('-') {
+        let mut chars = part.chars();
+        if let Some(first) = chars.next() {
+            constructor.push(first.to_ascii_uppercase());
+            constructor.extend(chars);
+        }
+    }
+
+    if !constructor.starts_with(|c: char| c.is_ascii_uppercase()) {
+        constructor.insert_str(0, prefix);
+    }
+
+    constructor
 }
 
+/// The Content-Type a file is served with, after its extension.
+pub fn mime_type(path: &Path) -> &'static str {
+    let extension = path
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .unwrap_or_default()
+        .to_lowercase();
+
+    match extension.as_str() {
+        "html" | "htm" => "text/html; charset=utf-8",
+        "css" => "text/css; charset=utf-8",
+        "js" | "mjs" => "text/javascript; charset=utf-8",
+        "json" | "map" => "application/json",
+        "geojson" => "application/geo+json",
+        "topojson" => "application/json",
+        "webmanifest" => "application/manifest+json",
+        "xml" => "application/xml",
+        "txt" | "desc" | "trip" => "text/plain; charset=utf-8",
+        "png" => "image/png",
+        "jpg" | "jpeg" => "image/jpeg",
+        "gif" => "image/gif",
+        "webp" => "image/webp",
+        "avif" => "image/avif",
+        "svg" => "image/svg+xml",
+        "ico" => "image/x-icon",
+        "woff" => "font/woff",
+        "woff2" => "font/woff2",
+        _ => "application/octet-stream",
+    }
+}
@@ -0,0 +1,199 @@
+//! Country outlines from a shapefile written by the tests: a .shp of polygon records and a .dbf of
+//! their ADM0_A3 codes.
+
+mod common;
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use common::{feature, MockNominatim, Project};
+use serde_json::Value;
+
+/// A polygon record: its rings, and whether the .dbf flags it as deleted.
+struct Record {
+    code: &'static str,
+    rings: Vec<Vec<(f64, f64)>>,
+    deleted: bool,
+}
+
+/// A square ring, clockwise as shapefile outlines are unless `hole`.
+fn square(x: f64, y: f64, size: f64, hole: bool) -> Vec<(f64, f64)> {
+    let mut ring = vec![
+        (x, y),
+        (x, y + size),
+        (x + size, y + size),
+        (x + size, y),
+        (x, y),
+    ];
+    if hole {
+        ring.reverse();
+    }
+    ring
+}
+
+fn write_shp(path: &Path, records: &[Record]) {
+    let mut body = Vec::new();
+    for (idx, record) in records.iter().enumerate() {
+        let points = record.rings.iter().flatten().collect::<Vec<_>>();
+        let mut content = Vec::new();
+        content.extend(5i32.to_le_bytes());
+        content.extend([0u8; 32]);
+        content.extend((record.rings.len() as i32).to_le_bytes());
+        content.extend((points.len() as i32).to_le_bytes());
+        let mut start = 0;
+        for ring in &record.rings {
+            content.extend((start as i32).to_le_bytes());
+            start += ring.len();
+        }
+        for (x, y) in points {
+            content.extend(x.to_le_bytes());
+            content.extend(y.to_le_bytes());
+        }
+
+        body.extend((idx as i32 + 1).to_be_bytes());
+        body.extend((content.len() as i32 / 2).to_be_bytes());
+        body.extend(content);
+    }
+
+    let mut shp = vec![0u8; 100];
+    shp[..4].copy_from_slice(&9994i32.to_be_bytes());
+    shp[24..28].copy_from_slice(&((100 + body.len()) as i32 / 2).to_be_bytes());
+    shp[28..32].copy_from_slice(&1000i32.to_le_bytes());
+    shp[32..36].copy_from_slice(&5i32.to_le_bytes());
+    shp.extend(body);
+    fs::write(path, shp).unwrap();
+}
+
+fn write_dbf(path: &Path, records: &[Record]) {
+    let mut dbf = vec![0u8; 32];
+    dbf[0] = 3;
+    dbf[4..8].copy_from_slice(&(records.len() as u32).to_le_bytes());
+    dbf[8..10].copy_from_slice(&(32u16 + 32 + 1).to_le_bytes());
+    dbf[10..12].copy_from_slice(&(1u16 + 3).to_le_bytes());
+
+    let mut field = [0u8; 32];
+    field[..7].copy_from_slice(b"ADM0_A3");
+    field[11] = b'C';
+    field[16] = 3;
+    dbf.extend(field);
+    dbf.push(0x0D);
+
+    for record in records {
+        dbf.push(if record.deleted { b'*' } else { b' ' });
+        dbf.extend(record.code.as_bytes());
+    }
+    dbf.push(0x1A);
+    fs::write(path, dbf).unwrap();
+}
+
+/// A project whose boundaries come from a shapefile of `records`, returned with its path.
+fn project_with(nominatim: &MockNominatim, records: &[Record]) -> (Project, PathBuf) {
+    let project = Project::new("manifest.yaml", nominatim);
+    let shp = project.path("countries.shp");
+    write_shp(&shp, records);
+    write_dbf(&shp.with_extension("dbf"), records);
+    // Relative to the repository rather than to where the manifester runs.
+    project.append_manifest(
+        "boundaries:\n  source: countries.shp\n  tolerance: 1\n  topojson: true\n",
+    );
+    (project, shp)
+}
+
+#[test]
+fn outlines_keep_their_holes_and_skip_deleted_records() {
+    let nominatim = MockNominatim::start();
+    let (project, _) = project_with(
+        &nominatim,
+        &[
+            Record {
+                code: "KOR",
+                rings: vec![
+                    square(126.0, 34.0, 4.0, false),
+                    square(127.0, 35.0, 1.0, true),
+                ],
+                deleted: false,
+            },
+            Record {
+                code: "KOR",
+                rings: vec![square(140.0, 40.0, 1.0, false)],
+                deleted: true,
+            },
+        ],
+    );
+
+    let output = project.run(&[]);
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+
+    let countries = project.read_json("manifester/countries.json");
+    let korea = &feature(&countries, "KOR")["geometry"];
+    assert_eq!(korea["type"], "MultiPolygon");
+    let polygons = korea["coordinates"].as_array().unwrap();
+    assert_eq!(polygons.len(), 1, "{}", korea);
+    assert_eq!(polygons[0].as_array().unwrap().len(), 2, "{}", korea);
+
+    let topojson = project.read_json("manifester/countries.topojson");
+    let bbox = topojson["bbox"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(Value::as_f64)
+        .collect::<Option<Vec<_>>>()
+        .unwrap();
+    assert_eq!(bbox, [126.0, 34.0, 130.0, 38.0]);
+}
+
+#[test]
+fn a_layer_without_outlines_writes_no_topojson() {
+    let nominatim = MockNominatim::start();
+    let (project, _) = project_with(
+        &nominatim,
+        &[Record {
+            code: "KOR",
+            rings: vec![square(126.0, 34.0, 4.0, false)],
+            deleted: true,
+        }],
+    );
+
+    let output = project.run(&[]);
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    assert!(String::from_utf8_lossy(&output.stdout).contains("Korea has no boundary"));
+    assert!(!project.path("manifester/countries.topojson").exists());
+}
+
+#[test]
+fn truncated_files_fail_the_build() {
+    let nominatim = MockNominatim::start();
+    let records = [Record {
+        code: "KOR",
+        rings: vec![square(126.0, 34.0, 4.0, false)],
+        deleted: false,
+    }];
+    let (project, shp) = project_with(&nominatim, &records);
+    let dbf = shp.with_extension("dbf");
+
+    // Cut inside the field descriptors, which the header says are there.
+    let whole = fs::read(&dbf).unwrap();
+    fs::write(&dbf, &whole[..40]).unwrap();
+    let output = project.run(&[]);
+    assert!(!output.status.success());
+    assert!(
+        String::from_utf8_lossy(&output.stderr).contains("is truncated"),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+
+    fs::write(&dbf, &whole).unwrap();
+    let whole = fs::read(&shp).unwrap();
+    fs::write(&shp, &whole[..whole.len() - 20]).unwrap();
+    let output = project.run(&[]);
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("is truncated"));
+}
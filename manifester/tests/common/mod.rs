@@ -0,0 +1,268 @@
+// Each test binary uses some of these helpers.
+#![allow(dead_code)]
+
+use std::fs;
+use std::io::Cursor;
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, Output, Stdio};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+
+use serde_json::Value;
+use tempfile::TempDir;
+use tiny_http::{Header, Response, Server};
+use url::Url;
+
+const FIXTURES: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures");
+
+/// A stand-in Nominatim answering `search` and `lookup` from the JSON files under
+/// `tests/fixtures/nominatim`, named after the slug of the query or the lowercased OSM id. Queries
+/// without a fixture find nothing.
+pub struct MockNominatim {
+    server: Arc<Server>,
+    handle: Option<JoinHandle<()>>,
+    requests: Arc<Mutex<Vec<String>>>,
+}
+
+impl MockNominatim {
+    pub fn start() -> Self {
+        Self::serve(None)
+    }
+
+    /// A server that answers every request with `status`.
+    pub fn failing(status: u16) -> Self {
+        Self::serve(Some(status))
+    }
+
+    fn serve(status: Option<u16>) -> Self {
+        let server = Arc::new(Server::http("127.0.0.1:0").unwrap());
+        let requests = Arc::new(Mutex::new(Vec::new()));
+
+        let handle = {
+            let server = Arc::clone(&server);
+            let requests = Arc::clone(&requests);
+            thread::spawn(move || {
+                for request in server.incoming_requests() {
+                    let url = Url::parse(&format!("http://localhost{}", request.url())).unwrap();
+                    let query = url
+                        .query_pairs()
+                        .find(|(key, _)| key == "q" || key == "osm_ids")
+                        .map(|(_, value)| value.to_string())
+                        .unwrap_or_default();
+                    requests.lock().unwrap().push(query.clone());
+
+                    let response = match status {
+                        Some(status) => Response::from_string("").with_status_code(status),
+                        None => {
+                            let endpoint =
+                                url.path_segments().and_then(|mut s| s.next_back()).unwrap();
+                            let fixture = Path::new(FIXTURES)
+                                .join("nominatim")
+                                .join(endpoint)
+                                .join(format!("{}.json", slug(&query)));
+                            Response::from_string(
+                                fs::read_to_string(fixture).unwrap_or_else(|_| "[]".to_string()),
+                            )
+                            .with_header(
+                                Header::from_bytes("Content-Type", "application/json").unwrap(),
+                            )
+                        }
+                    };
+                    let _ = request.respond(response);
+                }
+            })
+        };
+
+        MockNominatim {
+            server,
+            handle: Some(handle),
+            requests,
+        }
+    }
+
+    pub fn endpoint(&self) -> String {
+        format!("http://{}/", self.server.server_addr().to_ip().unwrap())
+    }
+
+    /// The search queries and OSM ids requested so far, in order.
+    pub fn requests(&self) -> Vec<String> {
+        self.requests.lock().unwrap().clone()
+    }
+}
+
+impl Drop for MockNominatim {
+    fn drop(&mut self) {
+        self.server.unblock();
+        if let Some(handle) = self.handle.take() {
+            let _ = handle.join();
+        }
+    }
+}
+
+fn slug(query: &str) -> String {
+    query
+        .to_lowercase()
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|part| !part.is_empty())
+        .collect::<Vec<_>>()
+        .join("-")
+}
+
+/// A checkout in a temporary directory, laid out like the repository so the manifester finds the
+/// gallery and the Elm sources where it expects them.
+pub struct Project {
+    root: TempDir,
+    manifest: PathBuf,
+}
+
+impl Project {
+    /// A project building from the `fixture` manifest, geocoding against `nominatim`.
+    pub fn new(fixture: &str, nominatim: &MockNominatim) -> Self {
+        let root = tempfile::tempdir().unwrap();
+        for dir in ["manifester", "src", "dist/gallery"] {
+            fs::create_dir_all(root.path().join(dir)).unwrap();
+        }
+
+        let mut yaml = fs::read_to_string(Path::new(FIXTURES).join(fixture)).unwrap();
+        yaml.push_str(&format!(
+            "\ngeocoding:\n  endpoint: {}\n  email: tests@example.com\n  requests_per_second: 100\n  retries: 1\n",
+            nominatim.endpoint()
+        ));
+        let manifest = root.path().join("manifest.yaml");
+        fs::write(&manifest, yaml).unwrap();
+
+        Project { root, manifest }
+    }
+
+    pub fn path(&self, relative: &str) -> PathBuf {
+        self.root.path().join(relative)
+    }
+
+    /// Files a small image in the gallery, under a `year/month/country/location` path.
+    pub fn add_photo(&self, relative: &str) {
+        let path = self.path("dist/gallery").join(relative);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        image::RgbImage::new(6, 4).save(path).unwrap();
+    }
+
+    /// Files a photo like `add_photo`, with `day`, `YYYY:MM:DD`, as its EXIF capture date.
+    pub fn add_photo_taken(&self, relative: &str, day: &str) {
+        let path = self.path("dist/gallery").join(relative);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        let mut png = Vec::new();
+        image::RgbImage::new(6, 4)
+            .write_to(&mut Cursor::new(&mut png), image::ImageFormat::Png)
+            .unwrap();
+
+        let taken = exif::Field {
+            tag: exif::Tag::DateTimeOriginal,
+            ifd_num: exif::In::PRIMARY,
+            value: exif::Value::Ascii(vec![format!("{} 12:00:00", day).into_bytes()]),
+        };
+        let mut writer = exif::experimental::Writer::new();
+        writer.push_field(&taken);
+        let mut tiff = Cursor::new(Vec::new());
+        writer.write(&mut tiff, false).unwrap();
+        let tiff = tiff.into_inner();
+
+        // An eXIf chunk right after the 8 byte signature and the 25 byte IHDR chunk.
+        let mut chunk = b"eXIf".to_vec();
+        chunk.extend(&tiff);
+        let mut crc = flate2::Crc::new();
+        crc.update(&chunk);
+        let mut exif_chunk = (tiff.len() as u32).to_be_bytes().to_vec();
+        exif_chunk.extend(chunk);
+        exif_chunk.extend(crc.sum().to_be_bytes());
+        png.splice(33..33, exif_chunk);
+        fs::write(path, png).unwrap();
+    }
+
+    pub fn write_json(&self, relative: &str, value: &Value) {
+        fs::write(self.path(relative), serde_json::to_string(value).unwrap()).unwrap();
+    }
+
+    pub fn read_json(&self, relative: &str) -> Value {
+        serde_json::from_str(&fs::read_to_string(self.path(relative)).unwrap()).unwrap()
+    }
+
+    /// Adds `yaml` to the end of the manifest.
+    pub fn append_manifest(&self, yaml: &str) {
+        let mut manifest = fs::read_to_string(&self.manifest).unwrap();
+        manifest.push_str(yaml);
+        fs::write(&self.manifest, manifest).unwrap();
+    }
+
+    /// Replaces `from` in the manifest with `to`.
+    pub fn replace_in_manifest(&self, from: &str, to: &str) {
+        let manifest = fs::read_to_string(&self.manifest).unwrap();
+        assert!(manifest.contains(from), "the manifest has no {:?}", from);
+        fs::write(&self.manifest, manifest.replacen(from, to, 1)).unwrap();
+    }
+
+    pub fn run(&self, args: &[&str]) -> Output {
+        self.command(args).output().unwrap()
+    }
+
+    pub fn run_with_env(&self, args: &[&str], vars: &[(&str, &str)]) -> Output {
+        self.command(args)
+            .envs(vars.iter().copied())
+            .output()
+            .unwrap()
+    }
+
+    /// Runs with the programs in `bin` found before any installed ones.
+    pub fn run_with_path(&self, args: &[&str], bin: &Path) -> Output {
+        let path = std::env::var_os("PATH").unwrap_or_default();
+        let paths = std::iter::once(bin.to_path_buf()).chain(std::env::split_paths(&path));
+        self.command(args)
+            .env("PATH", std::env::join_paths(paths).unwrap())
+            .output()
+            .unwrap()
+    }
+
+    /// Starts a run that keeps going, such as `watch`, with its output piped.
+    pub fn spawn(&self, args: &[&str]) -> Child {
+        self.command(args)
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .unwrap()
+    }
+
+    fn command(&self, args: &[&str]) -> Command {
+        let mut command = Command::new(env!("CARGO_BIN_EXE_manifester"));
+        command
+            .args(args)
+            .current_dir(self.path("manifester"))
+            .env("MANIFESTER_MANIFEST", &self.manifest);
+        command
+    }
+}
+
+/// The feature in a FeatureCollection with the `id` property.
+pub fn feature<'a>(collection: &'a Value, id: &str) -> &'a Value {
+    collection["features"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .find(|f| f["properties"]["id"] == id)
+        .unwrap_or_else(|| panic!("no feature {}", id))
+}
+
+/// Checks a `[lon, lat]` position to within the precision cities.json keeps.
+pub fn assert_position(position: &Value, expected: [f64; 2]) {
+    let actual = position
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|c| c.as_f64().unwrap())
+        .collect::<Vec<_>>();
+    assert!(
+        actual.len() == 2
+            && (actual[0] - expected[0]).abs() < 1e-4
+            && (actual[1] - expected[1]).abs() < 1e-4,
+        "{:?} is not at {:?}",
+        actual,
+        expected
+    );
+}
@@ -0,0 +1,420 @@
+//! Deploying dist to a stand-in S3 bucket, which keeps the objects it is sent in memory, and
+//! releasing it into a web root.
+
+mod common;
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+
+use common::{MockNominatim, Project};
+use md5::{Digest, Md5};
+use percent_encoding::percent_decode_str;
+use tiny_http::{Header, Method, Response, Server};
+use url::Url;
+
+const BUCKET: &str = "photos";
+
+/// An object as it was put, with the names of its headers lowercased.
+#[derive(Clone, Debug)]
+struct Object {
+    body: Vec<u8>,
+    headers: BTreeMap<String, String>,
+}
+
+/// A path style bucket answering ListObjectsV2 a page of two keys at a time, PutObject and
+/// DeleteObject, after checking each request is signed.
+struct MockS3 {
+    server: Arc<Server>,
+    handle: Option<JoinHandle<()>>,
+    objects: Arc<Mutex<BTreeMap<String, Object>>>,
+    puts: Arc<Mutex<usize>>,
+}
+
+fn etag(body: &[u8]) -> String {
+    format!("\"{}\"", hex::encode(Md5::digest(body)))
+}
+
+fn list(objects: &BTreeMap<String, Object>, query: &BTreeMap<String, String>) -> String {
+    let prefix = query.get("prefix").cloned().unwrap_or_default();
+    let mut keys = objects
+        .iter()
+        .filter(|(key, _)| key.starts_with(&prefix))
+        .filter(|(key, _)| match query.get("continuation-token") {
+            Some(after) => *key > after,
+            None => true,
+        })
+        .collect::<Vec<_>>();
+    let truncated = keys.len() > 2;
+    keys.truncate(2);
+
+    let mut xml = String::from(r#"<?xml version="1.0" encoding="UTF-8"?><ListBucketResult>"#);
+    for (key, object) in &keys {
+        xml.push_str(&format!(
+            "<Contents><Key>{}</Key><ETag>{}</ETag></Contents>",
+            key.replace('&', "&amp;"),
+            etag(&object.body).replace('"', "&quot;")
+        ));
+    }
+    xml.push_str(&format!("<IsTruncated>{}</IsTruncated>", truncated));
+    if truncated {
+        xml.push_str(&format!(
+            "<NextContinuationToken>{}</NextContinuationToken>",
+            keys[1].0
+        ));
+    }
+    xml.push_str("</ListBucketResult>");
+    xml
+}
+
+impl MockS3 {
+    fn start() -> Self {
+        let server = Arc::new(Server::http("127.0.0.1:0").unwrap());
+        let objects = Arc::new(Mutex::new(BTreeMap::new()));
+        let puts = Arc::new(Mutex::new(0));
+
+        let handle = {
+            let server = Arc::clone(&server);
+            let objects = Arc::clone(&objects);
+            let puts = Arc::clone(&puts);
+            thread::spawn(move || {
+                for mut request in server.incoming_requests() {
+                    let url = Url::parse(&format!("http://localhost{}", request.url())).unwrap();
+                    let signed = request.headers().iter().any(|h| {
+                        h.field.equiv("Authorization")
+                            && h.value
+                                .as_str()
+                                .starts_with("AWS4-HMAC-SHA256 Credential=test-key/")
+                    });
+                    let key = percent_decode_str(url.path())
+                        .decode_utf8()
+                        .unwrap()
+                        .trim_start_matches(&format!("/{}", BUCKET))
+                        .trim_start_matches('/')
+                        .to_string();
+
+                    let response = match (signed, request.method()) {
+                        (false, _) => Response::from_string("").with_status_code(403),
+                        (_, Method::Get) => {
+                            let query = url.query_pairs().into_owned().collect();
+                            Response::from_string(list(&objects.lock().unwrap(), &query))
+                        }
+                        (_, Method::Put) => {
+                            let mut body = Vec::new();
+                            request.as_reader().read_to_end(&mut body).unwrap();
+                            let headers = request
+                                .headers()
+                                .iter()
+                                .map(|h| (h.field.to_string().to_lowercase(), h.value.to_string()))
+                                .collect();
+                            let tag = etag(&body);
+                            objects
+                                .lock()
+                                .unwrap()
+                                .insert(key, Object { body, headers });
+                            *puts.lock().unwrap() += 1;
+                            Response::from_string("")
+                                .with_header(Header::from_bytes("ETag", tag).unwrap())
+                        }
+                        (_, Method::Delete) => {
+                            objects.lock().unwrap().remove(&key);
+                            Response::from_string("").with_status_code(204)
+                        }
+                        _ => Response::from_string("").with_status_code(405),
+                    };
+                    let _ = request.respond(response);
+                }
+            })
+        };
+
+        MockS3 {
+            server,
+            handle: Some(handle),
+            objects,
+            puts,
+        }
+    }
+
+    fn endpoint(&self) -> String {
+        format!("http://{}/", self.server.server_addr().to_ip().unwrap())
+    }
+
+    fn object(&self, key: &str) -> Option<Object> {
+        self.objects.lock().unwrap().get(key).cloned()
+    }
+
+    fn insert(&self, key: &str, body: &str) {
+        self.objects.lock().unwrap().insert(
+            key.to_string(),
+            Object {
+                body: body.as_bytes().to_vec(),
+                headers: BTreeMap::new(),
+            },
+        );
+    }
+
+    fn puts(&self) -> usize {
+        *self.puts.lock().unwrap()
+    }
+}
+
+impl Drop for MockS3 {
+    fn drop(&mut self) {
+        self.server.unblock();
+        if let Some(handle) = self.handle.take() {
+            let _ = handle.join();
+        }
+    }
+}
+
+/// A built site deploying under `site/` in the bucket, whose prefix is written without the slash.
+fn site(nominatim: &MockNominatim, s3: &MockS3) -> Project {
+    let project = Project::new("manifest.yaml", nominatim);
+    project.append_manifest(&format!(
+        "deploy:\n  s3:\n    endpoint: {}\n    bucket: {}\n    prefix: site\n    path_style: true\n",
+        s3.endpoint(),
+        BUCKET
+    ));
+
+    let dist = project.path("dist");
+    fs::write(dist.join("index.html"), "<html>index</html>").unwrap();
+    fs::write(dist.join("index.html.gz"), "gzipped index").unwrap();
+    fs::write(dist.join("cities.json"), "{}").unwrap();
+    fs::create_dir_all(dist.join("assets/css")).unwrap();
+    fs::write(dist.join("assets/css/gallery.0123456789.css"), "body {}").unwrap();
+    fs::write(
+        dist.join("asset-manifest.json"),
+        r#"{"assets/css/gallery.css": "assets/css/gallery.0123456789.css"}"#,
+    )
+    .unwrap();
+    project.add_photo("2021/08/Korea/Seoul/palace.png");
+    fs::write(
+        dist.join("gallery/2021/08/Korea/Seoul/palace.desc"),
+        "Palace",
+    )
+    .unwrap();
+    project
+}
+
+fn deploy(project: &Project, args: &[&str]) -> String {
+    let output = project.run_with_env(
+        &[&["deploy"], args].concat(),
+        &[
+            ("AWS_ACCESS_KEY_ID", "test-key"),
+            ("AWS_SECRET_ACCESS_KEY", "test-secret"),
+        ],
+    );
+    let stdout = String::from_utf8_lossy(&output.stdout).into_owned();
+    assert!(
+        output.status.success(),
+        "{}{}",
+        stdout,
+        String::from_utf8_lossy(&output.stderr)
+    );
+    stdout
+}
+
+#[test]
+fn only_changed_files_are_uploaded_with_their_headers() {
+    let nominatim = MockNominatim::start();
+    let s3 = MockS3::start();
+    let project = site(&nominatim, &s3);
+
+    deploy(&project, &[]);
+    let index = s3.object("site/index.html").unwrap();
+    assert_eq!(index.body, b"gzipped index");
+    assert_eq!(index.headers["content-encoding"], "gzip");
+    assert_eq!(index.headers["content-type"], "text/html; charset=utf-8");
+    assert_eq!(index.headers["cache-control"], "no-cache");
+    let css = s3.object("site/assets/css/gallery.0123456789.css").unwrap();
+    assert_eq!(css.headers["content-type"], "text/css; charset=utf-8");
+    assert_eq!(
+        css.headers["cache-control"],
+        "public, max-age=31536000, immutable"
+    );
+    assert!(!css.headers.contains_key("content-encoding"));
+    let photo = s3
+        .object("site/gallery/2021/08/Korea/Seoul/palace.png")
+        .unwrap();
+    assert_eq!(photo.headers["content-type"], "image/png");
+    assert_eq!(photo.headers["cache-control"], "public, max-age=604800");
+    assert!(s3.object("site/index.html.gz").is_none());
+    assert!(s3
+        .object("site/gallery/2021/08/Korea/Seoul/palace.desc")
+        .is_none());
+    let uploaded = s3.puts();
+    assert_eq!(uploaded, 5);
+
+    let stdout = deploy(&project, &[]);
+    assert_eq!(s3.puts(), uploaded, "{}", stdout);
+    assert!(stdout.contains("0 uploaded, 0 deleted, 5 unchanged."));
+
+    fs::write(
+        project.path("dist/cities.json"),
+        r#"{"type": "FeatureCollection"}"#,
+    )
+    .unwrap();
+    let stdout = deploy(&project, &[]);
+    assert_eq!(s3.puts(), uploaded + 1);
+    assert!(stdout.contains("~ cities.json"), "{}", stdout);
+    assert_eq!(
+        s3.object("site/cities.json").unwrap().body,
+        br#"{"type": "FeatureCollection"}"#
+    );
+}
+
+#[test]
+fn dry_runs_change_nothing_and_only_deletes_remove_objects() {
+    let nominatim = MockNominatim::start();
+    let s3 = MockS3::start();
+    let project = site(&nominatim, &s3);
+    s3.insert("site/old.html", "old");
+    s3.insert("elsewhere/keep.html", "keep");
+    s3.insert("site-old/keep.html", "keep");
+
+    let stdout = deploy(&project, &["--dry-run", "--delete"]);
+    assert_eq!(s3.puts(), 0);
+    assert!(s3.object("site/old.html").is_some());
+    assert!(stdout.contains("+ index.html"), "{}", stdout);
+    assert!(stdout.contains("- site/old.html"), "{}", stdout);
+    assert!(stdout.contains("Dry run: 5 to upload, 1 to delete, 0 unchanged."));
+
+    let stdout = deploy(&project, &[]);
+    assert!(s3.object("site/old.html").is_some());
+    assert!(!stdout.contains("- site/old.html"), "{}", stdout);
+
+    deploy(&project, &["--delete"]);
+    assert!(s3.object("site/old.html").is_none());
+    assert!(s3.object("elsewhere/keep.html").is_some());
+    assert!(s3.object("site-old/keep.html").is_some());
+    assert!(s3.object("site/index.html").is_some());
+}
+
+/// A site released into `www` under the project, keeping two releases.
+fn web_root(nominatim: &MockNominatim) -> Project {
+    let project = Project::new("manifest.yaml", nominatim);
+    project.append_manifest("deploy:\n  directory:\n    root: www\n    keep: 2\n");
+    let dist = project.path("dist");
+    fs::write(dist.join("index.html"), "<html>index</html>").unwrap();
+    fs::write(dist.join("cities.json"), "{}").unwrap();
+    project.add_photo("2021/08/Korea/Seoul/palace.png");
+    fs::write(
+        dist.join("gallery/2021/08/Korea/Seoul/palace.desc"),
+        "Palace",
+    )
+    .unwrap();
+    project
+}
+
+/// The release `www/current` points at.
+fn current(project: &Project) -> String {
+    fs::read_link(project.path("www/current"))
+        .unwrap()
+        .file_name()
+        .unwrap()
+        .to_string_lossy()
+        .into_owned()
+}
+
+fn releases(project: &Project) -> Vec<String> {
+    let mut releases = fs::read_dir(project.path("www/releases"))
+        .unwrap()
+        .map(|entry| entry.unwrap())
+        .filter(|entry| entry.file_type().unwrap().is_dir())
+        .map(|entry| entry.file_name().to_string_lossy().into_owned())
+        .collect::<Vec<_>>();
+    releases.sort();
+    releases
+}
+
+#[cfg(unix)]
+#[test]
+fn directory_deploys_switch_current_to_a_verified_release() {
+    use std::os::unix::fs::MetadataExt;
+
+    let nominatim = MockNominatim::start();
+    let project = web_root(&nominatim);
+
+    deploy(&project, &["--dry-run"]);
+    assert!(!project.path("www/current").exists());
+
+    deploy(&project, &[]);
+    let first = current(&project);
+    assert_eq!(
+        fs::read_to_string(project.path("www/current/index.html")).unwrap(),
+        "<html>index</html>"
+    );
+    assert!(!project
+        .path("www/current/gallery/2021/08/Korea/Seoul/palace.desc")
+        .exists());
+    let checksums =
+        fs::read_to_string(project.path(&format!("www/releases/{}.sha256", first))).unwrap();
+    assert!(checksums.contains("  index.html\n"), "{}", checksums);
+
+    fs::write(project.path("dist/cities.json"), "[]").unwrap();
+    let stdout = deploy(&project, &[]);
+    let second = current(&project);
+    assert!(second > first, "{} after {}", second, first);
+    assert!(stdout.contains("2 of them unchanged"), "{}", stdout);
+    assert_eq!(
+        fs::read_to_string(project.path("www/current/cities.json")).unwrap(),
+        "[]"
+    );
+    let photo = "gallery/2021/08/Korea/Seoul/palace.png";
+    let inode = |release: &str| {
+        fs::metadata(project.path(&format!("www/releases/{}/{}", release, photo)))
+            .unwrap()
+            .ino()
+    };
+    assert_eq!(inode(&first), inode(&second));
+
+    let stdout = deploy(&project, &["--dry-run"]);
+    assert!(stdout.contains(&format!("- {}", first)), "{}", stdout);
+    assert_eq!(releases(&project), [first.clone(), second.clone()]);
+
+    deploy(&project, &[]);
+    let third = current(&project);
+    assert_eq!(releases(&project), [second.clone(), third]);
+    assert!(!project
+        .path(&format!("www/releases/{}.sha256", first))
+        .exists());
+}
+
+#[cfg(unix)]
+#[test]
+fn rollback_returns_to_an_intact_earlier_release() {
+    let nominatim = MockNominatim::start();
+    let project = web_root(&nominatim);
+    deploy(&project, &[]);
+    let first = current(&project);
+    fs::write(project.path("dist/cities.json"), "[]").unwrap();
+    deploy(&project, &[]);
+    let second = current(&project);
+
+    let output = project.run(&["rollback"]);
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    assert_eq!(current(&project), first);
+    assert_eq!(
+        fs::read_to_string(project.path("www/current/cities.json")).unwrap(),
+        "{}"
+    );
+
+    let output = project.run(&["rollback", &first]);
+    assert!(output.status.success());
+    assert!(!project.run(&["rollback"]).status.success());
+
+    fs::write(
+        project.path(&format!("www/releases/{}/index.html", second)),
+        "tampered",
+    )
+    .unwrap();
+    let output = project.run(&["rollback", &second]);
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("index.html"));
+    assert_eq!(current(&project), first);
+}
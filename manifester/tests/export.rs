@@ -0,0 +1,237 @@
+//! Places and trips exported as KML and GPX.
+
+mod common;
+
+use std::fs;
+
+use common::{MockNominatim, Project};
+use quick_xml::events::Event;
+use quick_xml::Reader;
+use serde_json::json;
+
+/// Exports in `format`, checking the file is well-formed XML.
+fn export(project: &Project, format: &str) -> String {
+    let output = project.run(&["export", "--format", format, "--output", "travels"]);
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    let xml = fs::read_to_string(project.path("manifester/travels")).unwrap();
+
+    let mut reader = Reader::from_str(&xml);
+    loop {
+        match reader.read_event() {
+            Ok(Event::Eof) => break,
+            Ok(_) => {}
+            Err(error) => panic!("{} at {}: {}", error, reader.buffer_position(), xml),
+        }
+    }
+    xml
+}
+
+/// The elements of `xml` by their path from the root, such as `kml/Document/name`, in order.
+fn elements(xml: &str) -> Vec<String> {
+    let mut reader = Reader::from_str(xml);
+    let mut path = Vec::new();
+    let mut elements = Vec::new();
+    loop {
+        match reader.read_event().unwrap() {
+            Event::Start(start) => {
+                path.push(String::from_utf8(start.name().as_ref().to_vec()).unwrap());
+                elements.push(path.join("/"));
+            }
+            Event::Empty(empty) => {
+                let name = String::from_utf8(empty.name().as_ref().to_vec()).unwrap();
+                elements.push(format!("{}/{}", path.join("/"), name));
+            }
+            Event::End(_) => {
+                path.pop();
+            }
+            Event::Eof => return elements,
+            _ => {}
+        }
+    }
+}
+
+fn count(elements: &[String], path: &str) -> usize {
+    elements.iter().filter(|element| *element == path).count()
+}
+
+/// Cities and trips written as they are, instead of built from the manifest.
+fn project_with(
+    nominatim: &MockNominatim,
+    cities: serde_json::Value,
+    trips: serde_json::Value,
+) -> Project {
+    let project = Project::new("manifest.yaml", nominatim);
+    project.write_json(
+        "manifester/cities.json",
+        &json!({ "type": "FeatureCollection", "features": cities }),
+    );
+    project.write_json(
+        "manifester/trips.json",
+        &json!({ "type": "FeatureCollection", "features": trips }),
+    );
+    project
+}
+
+#[test]
+fn kml_has_a_folder_per_country_and_per_trip() {
+    let nominatim = MockNominatim::start();
+    let project = Project::new("manifest.yaml", &nominatim);
+    assert!(project.run(&[]).status.success());
+
+    let kml = export(&project, "kml");
+    let elements = elements(&kml);
+    let country = "kml/Document/Folder/Folder";
+    assert_eq!(count(&elements, country), 2);
+    assert_eq!(count(&elements, &format!("{}/Placemark", country)), 4);
+    assert_eq!(
+        count(
+            &elements,
+            &format!("{}/Placemark/Point/coordinates", country)
+        ),
+        3
+    );
+    assert_eq!(
+        count(
+            &elements,
+            &format!("{}/Placemark/LineString/coordinates", country)
+        ),
+        1
+    );
+
+    assert!(
+        kml.contains("<Folder>\n      <name>Korea</name>"),
+        "{}",
+        kml
+    );
+    assert!(
+        kml.contains("<name>Jeju</name>\n        <description>제주</description>"),
+        "{}",
+        kml
+    );
+    assert!(
+        kml.contains("<Folder>\n      <name>K01</name>\n      <Placemark>\n        <name>K01</name>\n        <description>seoul-jeju-busan-korea-2021</description>"),
+        "{}",
+        kml
+    );
+}
+
+#[test]
+fn gpx_has_a_waypoint_per_city_and_a_route_per_trip() {
+    let nominatim = MockNominatim::start();
+    let project = Project::new("manifest.yaml", &nominatim);
+    assert!(project.run(&[]).status.success());
+
+    let gpx = export(&project, "gpx");
+    let elements = elements(&gpx);
+    assert_eq!(count(&elements, "gpx/wpt"), 3);
+    assert_eq!(count(&elements, "gpx/wpt/type"), 3);
+    assert_eq!(count(&elements, "gpx/rte"), 1);
+    assert!(count(&elements, "gpx/rte/rtept") > 3);
+
+    assert!(
+        gpx.contains(r#"<wpt lat="33.499622" lon="126.53119">"#),
+        "{}",
+        gpx
+    );
+    assert!(gpx.contains("<rte>\n    <name>K01</name>"), "{}", gpx);
+    assert!(
+        gpx.contains(r#"<rtept lat="35.179955" lon="129.07524"/>"#),
+        "{}",
+        gpx
+    );
+}
+
+#[test]
+fn names_and_descriptions_are_escaped() {
+    let nominatim = MockNominatim::start();
+    let project = project_with(
+        &nominatim,
+        json!([{
+            "type": "Feature",
+            "properties": { "name": "Fish & Chips <Bay>", "localname": "\"Port\" 'Louis'", "country": "CIV", "id": "civ-fish-chips-bay" },
+            "geometry": { "type": "Point", "coordinates": [-4.0, 5.3] }
+        }]),
+        json!([{
+            "type": "Feature",
+            "properties": { "name": "Coast & <Lagoons>", "id": "coast-lagoons" },
+            "geometry": { "type": "LineString", "coordinates": [[-4.0, 5.3], [-5.0, 5.2]] }
+        }]),
+    );
+
+    let kml = export(&project, "kml");
+    for escaped in [
+        "<name>Côte d&apos;Ivoire</name>",
+        "<name>Fish &amp; Chips &lt;Bay&gt;</name>",
+        "<description>&quot;Port&quot; &apos;Louis&apos;</description>",
+        "<name>Coast &amp; &lt;Lagoons&gt;</name>",
+    ] {
+        assert!(kml.contains(escaped), "{} in {}", escaped, kml);
+    }
+
+    let gpx = export(&project, "gpx");
+    for escaped in [
+        "<name>Fish &amp; Chips &lt;Bay&gt;</name>",
+        "<desc>&quot;Port&quot; &apos;Louis&apos;</desc>",
+        "<type>Côte d&apos;Ivoire</type>",
+        "<name>Coast &amp; &lt;Lagoons&gt;</name>",
+    ] {
+        assert!(gpx.contains(escaped), "{} in {}", escaped, gpx);
+    }
+}
+
+#[test]
+fn trips_split_at_the_antimeridian_stay_apart() {
+    let nominatim = MockNominatim::start();
+    let project = project_with(
+        &nominatim,
+        json!([]),
+        json!([{
+            "type": "Feature",
+            "properties": { "name": "Fiji Samoa", "id": "fiji-samoa" },
+            "geometry": {
+                "type": "MultiLineString",
+                "coordinates": [
+                    [[178.4, -18.1], [180.0, -16.5]],
+                    [[-180.0, -16.5], [-171.8, -13.8]]
+                ]
+            }
+        }]),
+    );
+
+    let kml = export(&project, "kml");
+    assert_eq!(
+        count(
+            &elements(&kml),
+            "kml/Document/Folder/Folder/Placemark/MultiGeometry/LineString"
+        ),
+        2
+    );
+    assert!(
+        kml.contains("<coordinates>178.4,-18.1 180,-16.5</coordinates>"),
+        "{}",
+        kml
+    );
+    assert!(
+        kml.contains("<coordinates>-180,-16.5 -171.8,-13.8</coordinates>"),
+        "{}",
+        kml
+    );
+
+    // A GPX route can't be broken, so each side is a route of its own.
+    let gpx = export(&project, "gpx");
+    assert_eq!(count(&elements(&gpx), "gpx/rte"), 2);
+    assert!(
+        gpx.contains("<name>Fiji Samoa (1)</name>\n    <rtept lat=\"-18.1\" lon=\"178.4\"/>"),
+        "{}",
+        gpx
+    );
+    assert!(
+        gpx.contains("<name>Fiji Samoa (2)</name>\n    <rtept lat=\"-16.5\" lon=\"-180\"/>"),
+        "{}",
+        gpx
+    );
+}
@@ -0,0 +1,285 @@
+mod common;
+
+use common::{assert_position, feature, MockNominatim, Project};
+use serde_json::json;
+
+#[test]
+fn first_run_geocodes_every_place() {
+    let nominatim = MockNominatim::start();
+    let project = Project::new("manifest.yaml", &nominatim);
+
+    let output = project.run(&[]);
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+
+    assert_eq!(
+        nominatim.requests(),
+        vec!["R2679638", "Jeju, Korea", "Seoul, Korea"]
+    );
+
+    let cities = project.read_json("manifester/cities.json");
+    let ids = cities["features"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|f| f["properties"]["id"].as_str().unwrap())
+        .collect::<Vec<_>>();
+    assert_eq!(ids, vec!["kor-busan", "kor-jeju", "kor-seoul"]);
+
+    let seoul = feature(&cities, "kor-seoul");
+    assert_eq!(seoul["properties"]["name"], "Seoul");
+    assert_eq!(seoul["properties"]["localname"], "서울");
+    assert_eq!(seoul["properties"]["country"], "KOR");
+    assert_position(&seoul["geometry"]["coordinates"], [126.97829, 37.56668]);
+
+    let busan = feature(&cities, "kor-busan");
+    assert_eq!(busan["properties"]["osm"], "R2679638");
+    assert_position(&busan["geometry"]["coordinates"], [129.07524, 35.179955]);
+
+    assert!(project.path("src/Manifest.elm").exists());
+}
+
+#[test]
+fn geocoding_without_an_email_warns() {
+    let nominatim = MockNominatim::start();
+    let project = Project::new("manifest.yaml", &nominatim);
+    project.replace_in_manifest("  email: tests@example.com\n", "");
+
+    let output = project.run(&["geocode"]);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(output.status.success(), "{}", stderr);
+    assert_eq!(stderr.matches("geocoding: email:").count(), 1, "{}", stderr);
+}
+
+#[test]
+fn incremental_run_only_geocodes_new_places() {
+    let nominatim = MockNominatim::start();
+    let project = Project::new("manifest.yaml", &nominatim);
+    project.write_json(
+        "manifester/cities.json",
+        &json!({
+            "type": "FeatureCollection",
+            "features": [{
+                "type": "Feature",
+                "properties": { "name": "Seoul", "country": "KOR", "id": "kor-seoul" },
+                "geometry": { "type": "Point", "coordinates": [127.0, 37.5] }
+            }]
+        }),
+    );
+
+    let output = project.run(&["geocode"]);
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    assert_eq!(nominatim.requests(), vec!["R2679638", "Jeju, Korea"]);
+
+    let cities = project.read_json("manifester/cities.json");
+    let seoul = feature(&cities, "kor-seoul");
+    assert_position(&seoul["geometry"]["coordinates"], [127.0, 37.5]);
+    assert_eq!(seoul["properties"]["localname"], "서울");
+
+    // With every place geocoded a second run has nothing to ask for.
+    let output = project.run(&["geocode"]);
+    assert!(output.status.success());
+    assert_eq!(nominatim.requests().len(), 2);
+}
+
+#[test]
+fn cities_follow_places_without_being_geocoded_again() {
+    let nominatim = MockNominatim::start();
+    let project = Project::new("manifest.yaml", &nominatim);
+    project.write_json(
+        "manifester/cities.json",
+        &json!({
+            "type": "FeatureCollection",
+            "features": [{
+                "type": "Feature",
+                "properties": { "name": "Seoul", "localname": "경성", "country": "JPN", "id": "kor-seoul" },
+                "geometry": { "type": "Point", "coordinates": [127.0, 37.5] }
+            }, {
+                "type": "Feature",
+                "properties": { "name": "Gaeseong", "country": "KOR", "id": "kor-gaeseong" },
+                "geometry": { "type": "Point", "coordinates": [126.55, 37.97] }
+            }]
+        }),
+    );
+
+    let output = project.run(&["geocode"]);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(output.status.success(), "{}", stdout);
+    assert!(
+        stdout.contains("Removed Gaeseong (kor-gaeseong) from cities.json"),
+        "{}",
+        stdout
+    );
+    assert!(!nominatim.requests().contains(&"Seoul, Korea".to_string()));
+
+    let cities = project.read_json("manifester/cities.json");
+    let ids = cities["features"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|f| f["properties"]["id"].as_str().unwrap())
+        .collect::<Vec<_>>();
+    assert_eq!(ids, vec!["kor-busan", "kor-jeju", "kor-seoul"]);
+    let seoul = &feature(&cities, "kor-seoul")["properties"];
+    assert_eq!(seoul["localname"], "서울");
+    assert_eq!(seoul["country"], "KOR");
+
+    // A new local name is taken over as it is, the place is where it was.
+    project.replace_in_manifest("    Seoul: 서울", "    Seoul: 서울특별시");
+    let requests = nominatim.requests().len();
+    assert!(project.run(&["geocode"]).status.success());
+    assert_eq!(nominatim.requests().len(), requests);
+
+    let cities = project.read_json("manifester/cities.json");
+    let seoul = feature(&cities, "kor-seoul");
+    assert_eq!(seoul["properties"]["localname"], "서울특별시");
+    assert_position(&seoul["geometry"]["coordinates"], [127.0, 37.5]);
+}
+
+#[cfg(unix)]
+#[test]
+fn unreadable_cities_fail_geocoding() {
+    let nominatim = MockNominatim::start();
+    let project = Project::new("manifest.yaml", &nominatim);
+    // A link to itself can't be opened, but it isn't missing either.
+    std::os::unix::fs::symlink("cities.json", project.path("manifester/cities.json")).unwrap();
+
+    let output = project.run(&["geocode"]);
+    assert!(!output.status.success());
+    assert!(
+        String::from_utf8_lossy(&output.stderr).contains("cities.json failed"),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    assert!(nominatim.requests().is_empty());
+}
+
+#[test]
+fn unpinned_places_are_searched_for_again() {
+    let nominatim = MockNominatim::start();
+    let project = Project::new("manifest.yaml", &nominatim);
+    assert!(project.run(&["geocode"]).status.success());
+
+    project.replace_in_manifest("      osm: R2679638", "");
+    let output = project.run(&["geocode"]);
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    assert_eq!(nominatim.requests().last().unwrap(), "Busan, Korea");
+
+    let cities = project.read_json("manifester/cities.json");
+    let busan = feature(&cities, "kor-busan");
+    assert!(busan["properties"].get("osm").is_none(), "{}", busan);
+    assert_position(&busan["geometry"]["coordinates"], [129.0422, 35.1151]);
+
+    // Searched for without a pin it stays where the search put it.
+    let requests = nominatim.requests().len();
+    assert!(project.run(&["geocode"]).status.success());
+    assert_eq!(nominatim.requests().len(), requests);
+}
+
+#[test]
+fn review_lists_matches_with_their_distance_and_pin() {
+    let nominatim = MockNominatim::start();
+    let project = Project::new("manifest.yaml", &nominatim);
+
+    let output = project.run(&["geocode", "--review", "Jeju"]);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(output.status.success(), "{}", stdout);
+    assert!(
+        stdout.contains("Jeju (kor-jeju) not geocoded yet"),
+        "{}",
+        stdout
+    );
+    assert!(!stdout.contains("Seoul"), "{}", stdout);
+    // Reviewing only reads cities.json.
+    assert!(!project.path("manifester/cities.json").exists());
+
+    assert!(project.run(&["geocode"]).status.success());
+    let output = project.run(&["geocode", "--review", "--candidates", "2"]);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(output.status.success(), "{}", stdout);
+    assert!(
+        stdout.contains("Jeju (kor-jeju) at 126.5312, 33.4996\n"),
+        "{}",
+        stdout
+    );
+    assert!(
+        stdout.contains(
+            "  1. 제주시, 제주특별자치도, 대한민국 (126.5312, 33.4996) 0.0 km away\n     osm: R2400498"
+        ),
+        "{}",
+        stdout
+    );
+    assert!(
+        stdout.contains("Busan (kor-busan) at 129.0752, 35.1800, pinned to R2679638"),
+        "{}",
+        stdout
+    );
+    assert!(
+        stdout.contains("(129.0422, 35.1151) 7.8 km away"),
+        "{}",
+        stdout
+    );
+    assert!(
+        nominatim.requests().ends_with(&[
+            "Busan, Korea".to_string(),
+            "Jeju, Korea".to_string(),
+            "Seoul, Korea".to_string()
+        ]),
+        "{:?}",
+        nominatim.requests()
+    );
+}
+
+#[test]
+fn review_tells_when_nothing_matches() {
+    let nominatim = MockNominatim::start();
+    let project = Project::new("unknown_place.yaml", &nominatim);
+
+    let output = project.run(&["geocode", "--review", "kor-atlantis"]);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(output.status.success(), "{}", stdout);
+    assert!(
+        stdout.contains("  nothing found for \"Atlantis, Korea\""),
+        "{}",
+        stdout
+    );
+
+    let output = project.run(&["geocode", "--review", "Nowhere"]);
+    assert!(!output.status.success());
+}
+
+#[test]
+fn place_nominatim_cannot_find_fails_the_build() {
+    let nominatim = MockNominatim::start();
+    let project = Project::new("unknown_place.yaml", &nominatim);
+
+    let output = project.run(&[]);
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr)
+        .contains("Search for Atlantis, Korea did not find coordinates"));
+    assert!(!project.path("manifester/cities.json").exists());
+    assert!(!project.path("src/Manifest.elm").exists());
+}
+
+#[test]
+fn server_errors_are_retried_then_fail_the_build() {
+    let nominatim = MockNominatim::failing(503);
+    let project = Project::new("manifest.yaml", &nominatim);
+
+    let output = project.run(&["geocode"]);
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("after 2 attempts"));
+    assert_eq!(nominatim.requests(), vec!["R2679638", "R2679638"]);
+    assert!(!project.path("manifester/cities.json").exists());
+}
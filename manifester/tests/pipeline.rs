@@ -0,0 +1,284 @@
+//! The build pipeline against a stand-in `elm`, which records its arguments and writes its output.
+#![cfg(unix)]
+
+mod common;
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::Read;
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
+
+use common::{MockNominatim, Project};
+
+const FAKE_ELM: &str = r#"#!/bin/sh
+echo "$@" > elm-args.txt
+for arg in "$@"; do
+    case "$arg" in --output=*) output="${arg#--output=}" ;; esac
+done
+mkdir -p "$(dirname "$output")"
+echo "compiled" > "$output"
+"#;
+
+/// A directory holding the stand-in `elm`.
+fn fake_bin(project: &Project) -> PathBuf {
+    let bin = project.path("bin");
+    fs::create_dir_all(&bin).unwrap();
+    let elm = bin.join("elm");
+    fs::write(&elm, FAKE_ELM).unwrap();
+    fs::set_permissions(&elm, fs::Permissions::from_mode(0o755)).unwrap();
+    bin
+}
+
+/// Where the last build put `name`, once fingerprinted.
+fn asset(project: &Project, name: &str) -> PathBuf {
+    let assets: BTreeMap<String, String> =
+        serde_json::from_value(project.read_json("dist/asset-manifest.json")).unwrap();
+    project.path("dist").join(&assets[name])
+}
+
+#[test]
+fn profiles_pick_elm_flags_and_post_processors() {
+    let nominatim = MockNominatim::start();
+    let project = Project::new("manifest.yaml", &nominatim);
+    project.append_manifest(
+        r#"build:
+  post:
+    - name: banner
+      run: (echo "/* banner */" && cat dist/assets/js/initprism.js) > dist/assets/js/initprism.min.js
+      profiles: [release]
+    - name: copy
+      copy: { from: dist/assets/js/initprism.js, to: dist/assets/js/initprism.min.js }
+      profiles: [dev]
+"#,
+    );
+    let bin = fake_bin(&project);
+
+    let output = project.run_with_path(&["build"], &bin);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(
+        output.status.success(),
+        "{}{}",
+        stdout,
+        String::from_utf8_lossy(&output.stderr)
+    );
+    assert_eq!(
+        fs::read_to_string(project.path("elm-args.txt")).unwrap(),
+        "make src/Main.elm --output=dist/assets/js/initprism.js --optimize\n"
+    );
+    assert_eq!(
+        fs::read_to_string(asset(&project, "assets/js/initprism.min.js")).unwrap(),
+        "/* banner */\ncompiled\n"
+    );
+    assert!(project.path("src/Manifest.elm").exists());
+    for stage in ["manifest", "elm", "banner"] {
+        assert!(
+            stdout
+                .lines()
+                .any(|line| line.trim_start().starts_with(stage) && line.trim_end().ends_with('s')),
+            "no timing for {} in\n{}",
+            stage,
+            stdout
+        );
+    }
+
+    let output = project.run_with_path(&["build", "--profile", "dev"], &bin);
+    assert!(output.status.success());
+    assert_eq!(
+        fs::read_to_string(project.path("elm-args.txt")).unwrap(),
+        "make src/Main.elm --output=dist/assets/js/initprism.js\n"
+    );
+    // Dev builds keep the plain asset names.
+    assert_eq!(
+        fs::read_to_string(project.path("dist/assets/js/initprism.min.js")).unwrap(),
+        "compiled\n"
+    );
+}
+
+#[test]
+fn a_failing_step_stops_the_build_and_is_summed_up() {
+    let nominatim = MockNominatim::start();
+    let project = Project::new("manifest.yaml", &nominatim);
+    project.append_manifest(
+        r#"build:
+  post:
+    - name: broken
+      run: exit 3
+    - name: after
+      run: touch after.txt
+"#,
+    );
+    let bin = fake_bin(&project);
+
+    let output = project.run_with_path(&["build"], &bin);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(!output.status.success());
+    assert!(
+        stdout
+            .lines()
+            .any(|line| line.contains("broken") && line.contains("failed: `exit 3` failed")),
+        "{}",
+        stdout
+    );
+    assert!(
+        stdout
+            .lines()
+            .any(|line| line.split_whitespace().eq(["after", "skipped"])),
+        "{}",
+        stdout
+    );
+    assert!(
+        stderr.contains("The build failed at the broken stage"),
+        "{}",
+        stderr
+    );
+    assert!(!project.path("after.txt").exists());
+}
+
+#[test]
+fn assets_are_fingerprinted_and_pages_follow_them() {
+    let nominatim = MockNominatim::start();
+    let project = Project::new("manifest.yaml", &nominatim);
+    project.append_manifest(
+        r#"build:
+  post:
+    - name: css
+      copy: { from: src/gallery.css, to: dist/assets/css/gallery.css }
+"#,
+    );
+    let template = r#"<link href="/assets/css/gallery.css"><script src="/assets/js/initprism.min.js"></script>"#;
+    fs::create_dir_all(project.path("src/pages")).unwrap();
+    fs::write(project.path("src/pages/index.html"), template).unwrap();
+    let bin = fake_bin(&project);
+
+    let build_with = |css: &str, profile: &str| {
+        fs::write(project.path("src/gallery.css"), css).unwrap();
+        let output = project.run_with_path(&["build", "--profile", profile], &bin);
+        assert!(
+            output.status.success(),
+            "{}",
+            String::from_utf8_lossy(&output.stdout)
+        );
+    };
+    let build = |css: &str| {
+        build_with(css, "release");
+        asset(&project, "assets/css/gallery.css")
+    };
+
+    let first = build("body { color: red }");
+    let name = first.file_name().unwrap().to_str().unwrap().to_string();
+    assert!(name.starts_with("gallery.") && name.ends_with(".css") && first.is_file());
+    assert!(!project.path("dist/assets/css/gallery.css").exists());
+    let index = fs::read_to_string(project.path("dist/index.html")).unwrap();
+    assert!(
+        index.contains(&format!(r#"href="/assets/css/{}""#, name)),
+        "{}",
+        index
+    );
+
+    let second = build("body { color: blue }");
+    assert!(!first.exists() && second.is_file());
+    let name = second.file_name().unwrap().to_str().unwrap();
+    let index = fs::read_to_string(project.path("dist/index.html")).unwrap();
+    assert!(
+        index.contains(&format!(r#"href="/assets/css/{}""#, name)),
+        "{}",
+        index
+    );
+    assert_eq!(index.matches("gallery.").count(), 1, "{}", index);
+    assert_eq!(
+        fs::read_to_string(project.path("src/pages/index.html")).unwrap(),
+        template
+    );
+
+    // A dev build, as elm-live serves, keeps the plain names the template has.
+    build_with("body { color: green }", "dev");
+    assert!(!second.exists());
+    assert!(project.path("dist/assets/css/gallery.css").is_file());
+    assert_eq!(
+        fs::read_to_string(project.path("dist/index.html")).unwrap(),
+        template
+    );
+}
+
+#[test]
+fn text_files_get_compressed_siblings_that_follow_them() {
+    let nominatim = MockNominatim::start();
+    let project = Project::new("manifest.yaml", &nominatim);
+    project.append_manifest(
+        r#"build:
+  post:
+    - name: css
+      copy: { from: src/gallery.css, to: dist/assets/css/gallery.css }
+"#,
+    );
+    let dist = project.path("dist");
+    let index =
+        r#"<link href="/assets/css/gallery.css">"#.to_string() + &"<p>photo</p>".repeat(100);
+    fs::write(dist.join("index.html"), &index).unwrap();
+    fs::write(dist.join("site.webmanifest"), "{}").unwrap();
+    fs::write(dist.join("countries.json"), "[0, 0, 0]".repeat(100)).unwrap();
+    project.add_photo("2021/08/Korea/Seoul/palace.png");
+    let bin = fake_bin(&project);
+
+    let build = |css: &str| {
+        fs::write(project.path("src/gallery.css"), css.repeat(50)).unwrap();
+        let output = project.run_with_path(&["build"], &bin);
+        assert!(
+            output.status.success(),
+            "{}",
+            String::from_utf8_lossy(&output.stdout)
+        );
+    };
+    build("body { color: red }\n");
+
+    let mut decoded = String::new();
+    flate2::read::GzDecoder::new(fs::File::open(dist.join("index.html.gz")).unwrap())
+        .read_to_string(&mut decoded)
+        .unwrap();
+    assert_eq!(
+        decoded,
+        fs::read_to_string(dist.join("index.html")).unwrap()
+    );
+    assert!(dist.join("index.html.br").is_file());
+    // Too small to shrink, and already compressed.
+    assert!(!dist.join("site.webmanifest.gz").exists());
+    let skipped = fs::read_to_string(dist.join(".compress-skipped")).unwrap();
+    assert!(
+        skipped.contains("site.webmanifest.br\nsite.webmanifest.gz\n"),
+        "{}",
+        skipped
+    );
+    assert!(!dist
+        .join("gallery/2021/08/Korea/Seoul/palace.png.gz")
+        .exists());
+
+    let first = asset(&project, "assets/css/gallery.css");
+    assert!(sibling(&first, "gz").is_file() && sibling(&first, "br").is_file());
+    let countries = fs::metadata(dist.join("countries.json.br"))
+        .unwrap()
+        .modified()
+        .unwrap();
+
+    // Grown big enough to shrink, it is tried again.
+    fs::write(dist.join("site.webmanifest"), "{}".repeat(100)).unwrap();
+    build("body { color: blue }\n");
+    assert!(dist.join("site.webmanifest.gz").is_file());
+    let skipped = fs::read_to_string(dist.join(".compress-skipped")).unwrap_or_default();
+    assert!(!skipped.contains("site.webmanifest"), "{}", skipped);
+    let second = asset(&project, "assets/css/gallery.css");
+    assert!(!sibling(&first, "gz").exists() && !sibling(&first, "br").exists());
+    assert!(sibling(&second, "gz").is_file() && sibling(&second, "br").is_file());
+    assert_eq!(
+        fs::metadata(dist.join("countries.json.br"))
+            .unwrap()
+            .modified()
+            .unwrap(),
+        countries
+    );
+}
+
+fn sibling(path: &Path, extension: &str) -> PathBuf {
+    PathBuf::from(format!("{}.{}", path.display(), extension))
+}
@@ -0,0 +1,108 @@
+//! Flights along great circles and paths broken at the antimeridian.
+
+mod common;
+
+use common::{feature, MockNominatim, Project};
+use manifester::{great_circle, split_antimeridian, Position};
+use serde_json::json;
+
+const SUVA: Position = (178.44, -18.14);
+const APIA: Position = (-171.77, -13.83);
+
+fn assert_near(actual: Position, expected: Position) {
+    assert!(
+        (actual.0 - expected.0).abs() < 1e-6 && (actual.1 - expected.1).abs() < 1e-6,
+        "{:?} is not {:?}",
+        actual,
+        expected
+    );
+}
+
+#[test]
+fn great_circles_crossing_the_antimeridian_are_split_onto_it() {
+    let path = great_circle(SUVA, APIA);
+    assert_near(path[0], SUVA);
+    assert_near(*path.last().unwrap(), APIA);
+    // About a point per degree of the roughly 11° of arc.
+    assert_eq!(path.len(), 12);
+    assert!(path.iter().all(|(lon, _)| lon.abs() <= 180.0));
+
+    let parts = split_antimeridian(&path);
+    assert_eq!(parts.len(), 2);
+    let (east, west) = (parts[0].last().unwrap(), parts[1][0]);
+    assert_eq!(east.0, 180.0);
+    assert_eq!(west.0, -180.0);
+    assert_eq!(east.1, west.1);
+    assert!(east.1 > SUVA.1 && east.1 < APIA.1, "{:?}", east);
+    assert_eq!(parts[0].len() + parts[1].len(), path.len() + 2);
+
+    // Flown the other way the crossing is the same, from the other side.
+    let parts = split_antimeridian(&great_circle(APIA, SUVA));
+    assert_eq!(parts.len(), 2);
+    assert_eq!(parts[0].last().unwrap().0, -180.0);
+    assert_eq!(parts[1][0].0, 180.0);
+    assert!((parts[1][0].1 - east.1).abs() < 1e-6);
+}
+
+#[test]
+fn paths_that_stay_on_one_side_are_left_whole() {
+    let path = great_circle((126.97, 37.56), (139.69, 35.68));
+    assert_eq!(split_antimeridian(&path), vec![path.clone()]);
+    assert!(split_antimeridian(&[]).is_empty());
+}
+
+#[test]
+fn endpoints_at_nearly_the_same_point_are_joined_directly() {
+    assert_eq!(great_circle(SUVA, SUVA), vec![SUVA, SUVA]);
+
+    let next_door = (SUVA.0 + 1e-10, SUVA.1);
+    assert_eq!(great_circle(SUVA, next_door), vec![SUVA, next_door]);
+
+    let close = (SUVA.0 + 1e-5, SUVA.1);
+    let path = great_circle(SUVA, close);
+    assert_eq!(path.len(), 2);
+    assert!(path
+        .iter()
+        .all(|(lon, lat)| lon.is_finite() && lat.is_finite()));
+    assert_near(path[1], close);
+}
+
+#[test]
+fn flights_over_the_antimeridian_are_drawn_in_two_parts() {
+    let nominatim = MockNominatim::start();
+    let project = Project::new("manifest.yaml", &nominatim);
+    project.replace_in_manifest(
+        "trips:",
+        "  Fiji:\n    Suva: Suva\n  Samoa:\n    Apia: Apia\n\ntrips:\n  - name: P01\n    description: Suva Apia 2019\n    legs:\n      - cities:\n          - Suva\n        start: 2019-07-01\n        end: 2019-07-02\n      - cities:\n          - Apia\n        mode: flight\n        start: 2019-07-02\n        end: 2019-07-05",
+    );
+    project.write_json(
+        "manifester/cities.json",
+        &json!({
+            "type": "FeatureCollection",
+            "features": [{
+                "type": "Feature",
+                "properties": { "name": "Suva", "country": "FJI", "id": "fji-suva" },
+                "geometry": { "type": "Point", "coordinates": [SUVA.0, SUVA.1] }
+            }, {
+                "type": "Feature",
+                "properties": { "name": "Apia", "country": "WSM", "id": "wsm-apia" },
+                "geometry": { "type": "Point", "coordinates": [APIA.0, APIA.1] }
+            }]
+        }),
+    );
+
+    let output = project.run(&[]);
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+
+    let trips = project.read_json("manifester/trips.json");
+    let geometry = &feature(&trips, "suva-apia-2019")["geometry"];
+    assert_eq!(geometry["type"], "MultiLineString");
+    let parts = geometry["coordinates"].as_array().unwrap();
+    assert_eq!(parts.len(), 2);
+    assert_eq!(parts[0].as_array().unwrap().last().unwrap()[0], 180.0);
+    assert_eq!(parts[1][0][0], -180.0);
+}
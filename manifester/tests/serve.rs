@@ -0,0 +1,174 @@
+use std::fs;
+use std::io::{Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+use manifester::{serve, LiveReload};
+use reqwest::blocking::{Client, Response};
+use reqwest::StatusCode;
+use tempfile::TempDir;
+
+/// A dist directory like the repository's, served on a free port, next to a file that mustn't be.
+struct Site {
+    _root: TempDir,
+    dist: PathBuf,
+    address: String,
+}
+
+impl Site {
+    fn start(derivatives: bool, live_reload: Option<Arc<LiveReload>>) -> Self {
+        let root = tempfile::tempdir().unwrap();
+        fs::write(root.path().join("secret.txt"), "secret").unwrap();
+        let dist = root.path().join("dist");
+        fs::create_dir(&dist).unwrap();
+        fs::write(dist.join("index.html"), "<html><body>index</body></html>").unwrap();
+        fs::write(dist.join("404.html"), "<html><body>lost</body></html>").unwrap();
+        fs::create_dir_all(dist.join("assets/css")).unwrap();
+        fs::write(dist.join("assets/css/gallery.css"), "0123456789").unwrap();
+        fs::write(dist.join("site.webmanifest"), "{}").unwrap();
+
+        let port = TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+        let address = format!("127.0.0.1:{}", port);
+        {
+            let dist = dist.clone();
+            let address = address.clone();
+            thread::spawn(move || serve(&dist, &address, derivatives, live_reload).unwrap());
+        }
+        while TcpStream::connect(&address).is_err() {
+            thread::sleep(Duration::from_millis(10));
+        }
+
+        Site {
+            _root: root,
+            dist,
+            address,
+        }
+    }
+
+    /// Sends `path` as it is, where an HTTP client would resolve dot segments first.
+    fn get_raw(&self, path: &str) -> String {
+        let mut stream = TcpStream::connect(&self.address).unwrap();
+        write!(stream, "GET {} HTTP/1.0\r\n\r\n", path).unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        response
+    }
+
+    fn get(&self, path: &str) -> Response {
+        Client::new()
+            .get(format!("http://{}{}", self.address, path))
+            .send()
+            .unwrap()
+    }
+
+    fn get_range(&self, path: &str, range: &str) -> Response {
+        Client::new()
+            .get(format!("http://{}{}", self.address, path))
+            .header("Range", range)
+            .send()
+            .unwrap()
+    }
+}
+
+fn content_type(response: &Response) -> &str {
+    response.headers()["Content-Type"].to_str().unwrap()
+}
+
+#[test]
+fn routes_fall_back_to_the_elm_application_and_missing_files_to_404() {
+    let site = Site::start(false, None);
+
+    let index = site.get("/");
+    assert_eq!(index.status(), StatusCode::OK);
+    assert_eq!(content_type(&index), "text/html; charset=utf-8");
+    assert!(index.text().unwrap().contains("index"));
+
+    let route = site.get("/trips/K01");
+    assert_eq!(route.status(), StatusCode::OK);
+    assert!(route.text().unwrap().contains("index"));
+
+    let missing = site.get("/assets/js/missing.js");
+    assert_eq!(missing.status(), StatusCode::NOT_FOUND);
+    assert!(missing.text().unwrap().contains("lost"));
+
+    for outside in ["/../secret.txt", "/assets/%2E%2E/%2E%2E/secret.txt"] {
+        let response = site.get_raw(outside);
+        assert!(response.starts_with("HTTP/1.0 404"), "{}", response);
+        assert!(!response.contains("secret"), "{}", response);
+    }
+
+    assert_eq!(
+        content_type(&site.get("/assets/css/gallery.css")),
+        "text/css; charset=utf-8"
+    );
+    assert_eq!(
+        content_type(&site.get("/site.webmanifest")),
+        "application/manifest+json"
+    );
+}
+
+#[test]
+fn ranges_are_served_partially() {
+    let site = Site::start(false, None);
+    let css = "/assets/css/gallery.css";
+
+    let whole = site.get(css);
+    assert_eq!(whole.headers()["Accept-Ranges"], "bytes");
+    assert_eq!(whole.text().unwrap(), "0123456789");
+
+    let part = site.get_range(css, "bytes=2-5");
+    assert_eq!(part.status(), StatusCode::PARTIAL_CONTENT);
+    assert_eq!(part.headers()["Content-Range"], "bytes 2-5/10");
+    assert_eq!(part.text().unwrap(), "2345");
+
+    assert_eq!(site.get_range(css, "bytes=7-").text().unwrap(), "789");
+    assert_eq!(site.get_range(css, "bytes=-3").text().unwrap(), "789");
+    assert_eq!(site.get_range(css, "bytes=8-100").text().unwrap(), "89");
+
+    let past = site.get_range(css, "bytes=10-");
+    assert_eq!(past.status(), StatusCode::RANGE_NOT_SATISFIABLE);
+    assert_eq!(past.headers()["Content-Range"], "bytes */10");
+
+    let several = site.get_range(css, "bytes=0-1,4-5");
+    assert_eq!(several.status(), StatusCode::OK);
+}
+
+#[test]
+fn pages_wait_for_the_next_build_to_reload() {
+    let live_reload = Arc::new(LiveReload::default());
+    let site = Site::start(false, Some(Arc::clone(&live_reload)));
+
+    assert!(site.get("/").text().unwrap().contains("/__livereload"));
+    assert_eq!(site.get("/__livereload").text().unwrap(), "0");
+
+    thread::spawn(move || {
+        thread::sleep(Duration::from_millis(200));
+        live_reload.reload();
+    });
+    assert_eq!(site.get("/__livereload?since=0").text().unwrap(), "1");
+}
+
+#[test]
+fn missing_derivatives_are_made_when_asked_for() {
+    let site = Site::start(true, None);
+    let photos = site.dist.join("gallery/2021/08/Korea/Seoul");
+    fs::create_dir_all(&photos).unwrap();
+    image::RgbImage::new(6, 4)
+        .save(photos.join("palace.png"))
+        .unwrap();
+
+    let thumbnail = site.get("/gallery/2021/08/Korea/Seoul/palace_small.png");
+    assert_eq!(thumbnail.status(), StatusCode::OK);
+    assert_eq!(content_type(&thumbnail), "image/png");
+    assert!(photos.join("palace_blur.png").is_file());
+
+    let orphan = site.get("/gallery/2021/08/Korea/Seoul/gate_small.png");
+    assert_eq!(orphan.status(), StatusCode::NOT_FOUND);
+}
@@ -0,0 +1,125 @@
+//! Builds a fixture gallery and compares what it generates with the files under `tests/snapshots`, byte
+//! for byte. After an intended change to the outputs, rewrite the snapshots with
+//! `UPDATE_SNAPSHOTS=1 cargo test --test snapshots` and review them in the diff.
+
+use std::fs;
+use std::path::Path;
+
+use manifester::{Config, Manifest, Project};
+
+const FIXTURE: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/snapshot");
+const SNAPSHOTS: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/snapshots");
+
+/// A photo in the fixture gallery, with its description and trip sidecars if it has them.
+struct Photo {
+    path: &'static str,
+    size: (u32, u32),
+    description: Option<&'static str>,
+    trips: Option<&'static str>,
+}
+
+const fn photo(path: &'static str, size: (u32, u32)) -> Photo {
+    Photo {
+        path,
+        size,
+        description: None,
+        trips: None,
+    }
+}
+
+const PHOTOS: &[Photo] = &[
+    photo("2019/04/Japan/Tokyo/tower.png", (4, 4)),
+    Photo {
+        description: Some("Kiyomizu-dera"),
+        ..photo("2019/04/Japan/Kansai/Kyoto/temple.png", (8, 4))
+    },
+    photo("2019/04/Japan/Kansai/Osaka/castle.png", (6, 4)),
+    photo("2020/01/Korea/Seoul/snow.png", (4, 6)),
+    // An empty trip sidecar keeps the photo out of the trip it was taken during.
+    Photo {
+        trips: Some(""),
+        ..photo("2021/08/Korea/Seoul/airport.png", (6, 4))
+    },
+    photo("2021/08/Korea/Seoul/palace.png", (4, 6)),
+    Photo {
+        description: Some("Hyeopjae beach"),
+        ..photo("2021/08/Korea/Jeju/beach.png", (6, 4))
+    },
+];
+
+#[test]
+fn outputs_match_snapshots() {
+    let root = tempfile::tempdir().unwrap();
+    let gallery = root.path().join("gallery");
+    for photo in PHOTOS {
+        let path = gallery.join(photo.path);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        let (width, height) = photo.size;
+        image::RgbImage::new(width, height).save(&path).unwrap();
+        // Thumbnails and blurs aren't part of the snapshot, having them already skips making them.
+        for derivative in ["small", "blur"] {
+            let stem = path.file_stem().unwrap().to_string_lossy();
+            fs::copy(
+                &path,
+                path.with_file_name(format!("{}_{}.png", stem, derivative)),
+            )
+            .unwrap();
+        }
+        if let Some(description) = photo.description {
+            fs::write(path.with_extension("desc"), description).unwrap();
+        }
+        if let Some(trips) = photo.trips {
+            fs::write(path.with_extension("trip"), trips).unwrap();
+        }
+    }
+
+    let config = Config {
+        root: root.path().to_path_buf(),
+        dist: root.path().to_path_buf(),
+        gallery,
+        cities: root.path().join("cities.json"),
+        trips: root.path().join("trips.json"),
+        countries: root.path().join("countries.json"),
+        elm: root.path().join("Manifest.elm"),
+        // elm-format may or may not be installed, the snapshot is of the unformatted module.
+        format_elm: false,
+    };
+    fs::copy(Path::new(FIXTURE).join("cities.json"), &config.cities).unwrap();
+
+    let manifest = Manifest::read(Path::new(FIXTURE).join("manifest.yaml")).unwrap();
+    let project = Project::new(manifest, config.clone()).unwrap();
+    project.build().unwrap();
+
+    for output in [&config.elm, &config.cities, &config.trips] {
+        check_snapshot(output);
+    }
+}
+
+fn check_snapshot(output: &Path) {
+    let name = output.file_name().unwrap();
+    let snapshot = Path::new(SNAPSHOTS).join(name);
+    let actual = fs::read_to_string(output).unwrap();
+
+    if std::env::var_os("UPDATE_SNAPSHOTS").is_some() {
+        fs::write(&snapshot, &actual).unwrap();
+        return;
+    }
+
+    let expected = fs::read_to_string(&snapshot).unwrap_or_default();
+    if actual == expected {
+        return;
+    }
+
+    let line = actual
+        .lines()
+        .zip(expected.lines())
+        .position(|(a, e)| a != e)
+        .unwrap_or_else(|| actual.lines().count().min(expected.lines().count()));
+    panic!(
+        "{} differs from its snapshot from line {}:\n  expected: {}\n  actual:   {}\nRun UPDATE_SNAPSHOTS=1 cargo test --test snapshots if the change is intended.",
+        name.to_string_lossy(),
+        line + 1,
+        expected.lines().nth(line).unwrap_or("<end of file>"),
+        actual.lines().nth(line).unwrap_or("<end of file>"),
+    );
+}
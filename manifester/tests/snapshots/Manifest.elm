@@ -0,0 +1,323 @@
+module Manifest exposing (Country(..), Date, Day, Image, Leg, Location(..), Month(..), Region(..), Statistics, Trip(..), Year, countryId, countryList, countryLocalName, countryLocation, countryName, locationId, locationInformation, locationList, locationLocalName, locationRegion, manifest, regionCountry, regionId, regionList, regionLocalName, regionLocations, regionName, stringToCountry, lifetimeStatistics, stringToLocation, stringToRegion, stringToTrip, tripId, tripImages, tripInformation, tripList, tripStatistics)
+-- COUNTRIES
+type Country
+    = Japan
+    | Korea
+countryList : List Country
+countryList =
+    [ Japan
+    , Korea
+    ]
+countryId : Country -> String
+countryId country =
+    case country of
+        Japan ->
+            "JPN"
+        Korea ->
+            "KOR"
+countryName : Country -> String
+countryName country =
+    case country of
+        Japan ->
+            "Japan"
+        Korea ->
+            "Korea"
+stringToCountry : String -> Maybe Country
+stringToCountry country =
+    case country of
+        "Japan" ->
+            Just Japan
+        "Korea" ->
+            Just Korea
+        _ ->
+            Nothing
+countryLocalName : Country -> Maybe String
+countryLocalName country =
+    case country of
+        Japan ->
+            Just "日本"
+        Korea ->
+            Just "한국"
+-- REGIONS
+type Region
+    = Kansai
+regionList : List Region
+regionList =
+    [ Kansai ]
+regionId : Region -> String
+regionId region =
+    case region of
+        Kansai ->
+            "kansai"
+regionName : Region -> String
+regionName region =
+    case region of
+        Kansai ->
+            "Kansai"
+regionLocalName : Region -> Maybe String
+regionLocalName region =
+    case region of
+        Kansai ->
+            Just "関西"
+regionCountry : Region -> Country
+regionCountry region =
+    case region of
+        Kansai ->
+            Japan
+regionLocations : Region -> List Location
+regionLocations region =
+    case region of
+        Kansai ->
+            [ JpnKyoto, JpnOsaka ]
+stringToRegion : String -> Maybe Region
+stringToRegion region =
+    case region of
+        "Kansai" ->
+            Just Kansai
+        _ ->
+            Nothing
+locationRegion : Location -> Maybe Region
+locationRegion location =
+    case location of
+        JpnKyoto ->
+            Just Kansai
+        JpnOsaka ->
+            Just Kansai
+        _ ->
+            Nothing
+-- LOCATIONS
+type Location
+    = JpnKyoto
+    | JpnOsaka
+    | JpnTokyo
+    | KorJeju
+    | KorSeoul
+locationList : List Location
+locationList =
+    [ JpnKyoto
+    , JpnOsaka
+    , JpnTokyo
+    , KorJeju
+    , KorSeoul
+    ]
+locationId : Location -> String
+locationId location =
+    case location of
+        JpnKyoto ->
+            "jpn-kyoto"
+        JpnOsaka ->
+            "jpn-osaka"
+        JpnTokyo ->
+            "jpn-tokyo"
+        KorJeju ->
+            "kor-jeju"
+        KorSeoul ->
+            "kor-seoul"
+stringToLocation : String -> Maybe Location
+stringToLocation location =
+    case location of
+        "Kyoto" ->
+            Just JpnKyoto
+        "Osaka" ->
+            Just JpnOsaka
+        "Tokyo" ->
+            Just JpnTokyo
+        "Jeju" ->
+            Just KorJeju
+        "Seoul" ->
+            Just KorSeoul
+        _ ->
+            Nothing
+countryLocation : Country -> String -> Maybe Location
+countryLocation country location =
+    case ( country, location ) of
+        ( Japan, "Kyoto" ) ->
+            Just JpnKyoto
+        ( Japan, "Osaka" ) ->
+            Just JpnOsaka
+        ( Japan, "Tokyo" ) ->
+            Just JpnTokyo
+        ( Korea, "Jeju" ) ->
+            Just KorJeju
+        ( Korea, "Seoul" ) ->
+            Just KorSeoul
+        _ ->
+            Nothing
+locationLocalName : Location -> Maybe String
+locationLocalName location =
+    case location of
+        JpnKyoto ->
+            Just "京都"
+        JpnOsaka ->
+            Just "大阪"
+        JpnTokyo ->
+            Just "東京"
+        KorJeju ->
+            Just "제주"
+        KorSeoul ->
+            Just "서울"
+type alias LocationInformation =
+    { name : String
+    , country : Country
+    , coordinates : ( Float, Float )
+    }
+locationInformation : Location -> LocationInformation
+locationInformation location =
+    case location of
+    JpnKyoto ->
+        { name = "Kyoto"
+        , country = Japan
+        , coordinates = ( 135.768, 35.012 )
+        }
+    JpnOsaka ->
+        { name = "Osaka"
+        , country = Japan
+        , coordinates = ( 135.502, 34.694 )
+        }
+    JpnTokyo ->
+        { name = "Tokyo"
+        , country = Japan
+        , coordinates = ( 139.759, 35.683 )
+        }
+    KorJeju ->
+        { name = "Jeju"
+        , country = Korea
+        , coordinates = ( 126.531, 33.500 )
+        }
+    KorSeoul ->
+        { name = "Seoul"
+        , country = Korea
+        , coordinates = ( 126.978, 37.567 )
+        }
+-- TRIPS
+type Trip
+    = TokyoKyotoOsakaJapan2019
+    | SeoulJejuKorea2021
+tripList : List Trip
+tripList =
+    [ TokyoKyotoOsakaJapan2019
+    , SeoulJejuKorea2021
+    ]
+tripId : Trip -> String
+tripId trip =
+    case trip of
+        TokyoKyotoOsakaJapan2019 ->
+            "tokyo-kyoto-osaka-japan-2019"
+        SeoulJejuKorea2021 ->
+            "seoul-jeju-korea-2021"
+stringToTrip : String -> Maybe Trip
+stringToTrip trip =
+    case trip of
+        "tokyo-kyoto-osaka-japan-2019" ->
+            Just TokyoKyotoOsakaJapan2019
+        "seoul-jeju-korea-2021" ->
+            Just SeoulJejuKorea2021
+        _ ->
+            Nothing
+type alias TripInformation =
+    { name : String
+    , description : String
+    , locations : List Location
+    , legs : List Leg
+    , start : Day
+    , end : Day
+    , days : Int
+    , dates : List Date
+    }
+type alias Leg =
+    { locations : List Location
+    , start : Day
+    , end : Day
+    }
+tripInformation : Trip -> TripInformation
+tripInformation trip =
+    case trip of
+        TokyoKyotoOsakaJapan2019 ->
+            { name = "J01"
+            , description = "Tokyo Kyoto Osaka Japan 2019"
+            , locations = [ JpnTokyo, JpnKyoto, JpnOsaka ]
+            , legs = [ Leg [ JpnTokyo ] (Day 2019 Apr 2) (Day 2019 Apr 5), Leg [ JpnKyoto, JpnOsaka ] (Day 2019 Apr 5) (Day 2019 Apr 9) ]
+            , start = Day 2019 Apr 2
+            , end = Day 2019 Apr 9
+            , days = 8
+            , dates = [ Date 2019 Apr ]
+            }
+        SeoulJejuKorea2021 ->
+            { name = "K01"
+            , description = "Seoul Jeju Korea 2021"
+            , locations = [ KorSeoul, KorJeju ]
+            , legs = [ Leg [ KorSeoul ] (Day 2021 Aug 1) (Day 2021 Aug 3), Leg [ KorJeju ] (Day 2021 Aug 3) (Day 2021 Aug 6) ]
+            , start = Day 2021 Aug 1
+            , end = Day 2021 Aug 6
+            , days = 6
+            , dates = [ Date 2021 Aug ]
+            }
+type alias Year =
+    Int
+type Month
+    = Jan
+    | Feb
+    | Mar
+    | Apr
+    | May
+    | Jun
+    | Jul
+    | Aug
+    | Sep
+    | Oct
+    | Nov
+    | Dec
+type alias Date =
+    { year : Year
+    , month : Month
+    }
+type alias Day =
+    { year : Year
+    , month : Month
+    , day : Int
+    }
+-- MANIFEST
+type alias Image =
+    { file : String
+    , date : Date
+    , location : Location
+    , aspectRatio : Float
+    , description : String
+    , trips : List Trip
+    }
+manifest : List Image
+manifest =
+    [ Image "temple.png" (Date 2019 Apr) JpnKyoto 2.000 "Kiyomizu-dera" [ TokyoKyotoOsakaJapan2019 ]
+    , Image "castle.png" (Date 2019 Apr) JpnOsaka 1.500 "" [ TokyoKyotoOsakaJapan2019 ]
+    , Image "tower.png" (Date 2019 Apr) JpnTokyo 1.000 "" [ TokyoKyotoOsakaJapan2019 ]
+    , Image "snow.png" (Date 2020 Jan) KorSeoul 0.667 "" [  ]
+    , Image "beach.png" (Date 2021 Aug) KorJeju 1.500 "Hyeopjae beach" [ SeoulJejuKorea2021 ]
+    , Image "airport.png" (Date 2021 Aug) KorSeoul 1.500 "" [  ]
+    , Image "palace.png" (Date 2021 Aug) KorSeoul 0.667 "" [ SeoulJejuKorea2021 ]
+    ]
+tripImages : Trip -> List String
+tripImages trip =
+    case trip of
+        TokyoKyotoOsakaJapan2019 ->
+            [ "2019/04/Japan/Kansai/Kyoto/temple.png", "2019/04/Japan/Kansai/Osaka/castle.png", "2019/04/Japan/Tokyo/tower.png" ]
+        SeoulJejuKorea2021 ->
+            [ "2021/08/Korea/Jeju/beach.png", "2021/08/Korea/Seoul/palace.png" ]
+-- STATISTICS
+type alias Statistics =
+    { trips : Int
+    , distance : Float
+    , countries : Int
+    , cities : Int
+    , days : Int
+    , photos : Int
+    }
+tripStatistics : Trip -> Statistics
+tripStatistics trip =
+    case trip of
+        TokyoKyotoOsakaJapan2019 ->
+            Statistics 1 412.5 1 3 8 3
+        SeoulJejuKorea2021 ->
+            Statistics 1 454.0 1 2 6 2
+lifetimeStatistics : Statistics
+lifetimeStatistics =
+    Statistics 2 866.5 2 5 14 7
@@ -0,0 +1,153 @@
+//! Trips proposed from the photos in the gallery, and the existing trips they disagree with.
+
+mod common;
+
+use common::{MockNominatim, Project};
+use serde_yaml::Value;
+
+/// The fixture places with Japan added.
+fn project(nominatim: &MockNominatim) -> Project {
+    let project = Project::new("manifest.yaml", nominatim);
+    project.replace_in_manifest("trips:", "  Japan:\n    Tokyo: 東京\n\ntrips:");
+    project
+}
+
+/// The proposed trips and what was reported about the existing ones.
+fn suggest(project: &Project) -> (Vec<Value>, String) {
+    let output = project.run(&["suggest-trips"]);
+    let stderr = String::from_utf8_lossy(&output.stderr).into_owned();
+    assert!(output.status.success(), "{}", stderr);
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let suggestions = serde_yaml::from_str::<Value>(&stdout).unwrap();
+    let trips = suggestions["trips"]
+        .as_sequence()
+        .cloned()
+        .unwrap_or_default();
+    (trips, stderr)
+}
+
+fn cities(leg: &Value) -> Vec<&str> {
+    leg["cities"]
+        .as_sequence()
+        .unwrap()
+        .iter()
+        .map(|city| city.as_str().unwrap())
+        .collect()
+}
+
+#[test]
+fn photos_no_trip_covers_are_clustered_into_trips() {
+    let nominatim = MockNominatim::start();
+    let project = project(&nominatim);
+    project.add_photo_taken("2022/05/Korea/Seoul/a.png", "2022:05:01");
+    project.add_photo_taken("2022/05/Korea/Seoul/b.png", "2022:05:02");
+    project.add_photo_taken("2022/05/Korea/Busan/c.png", "2022:05:03");
+    // Another country after two days without photos is another journey.
+    project.add_photo_taken("2022/05/Japan/Tokyo/d.png", "2022:05:05");
+    // As are the photos after more than the gap.
+    project.add_photo_taken("2022/05/Korea/Seoul/e.png", "2022:05:20");
+
+    let (trips, _) = suggest(&project);
+    let descriptions = trips
+        .iter()
+        .map(|trip| trip["description"].as_str().unwrap())
+        .collect::<Vec<_>>();
+    assert_eq!(
+        descriptions,
+        [
+            "Seoul Busan Korea 2022",
+            "Tokyo Japan 2022",
+            "Seoul Korea 2022"
+        ]
+    );
+
+    let first = &trips[0];
+    assert_eq!(first["name"], "Seoul 2022/05");
+    let legs = first["legs"].as_sequence().unwrap();
+    assert_eq!(legs.len(), 2);
+    assert_eq!(cities(&legs[0]), ["Seoul"]);
+    assert_eq!(legs[0]["start"], "2022-05-01");
+    assert_eq!(legs[0]["end"], "2022-05-02");
+    assert_eq!(cities(&legs[1]), ["Busan"]);
+    assert_eq!(legs[1]["end"], "2022-05-03");
+}
+
+#[test]
+fn proposals_sharing_an_id_are_told_apart_by_their_start() {
+    let nominatim = MockNominatim::start();
+    let project = project(&nominatim);
+    project.add_photo_taken("2022/05/Korea/Seoul/a.png", "2022:05:01");
+    project.add_photo_taken("2022/09/Korea/Seoul/b.png", "2022:09:10");
+
+    let (trips, _) = suggest(&project);
+    assert_eq!(trips.len(), 2);
+    assert!(trips[0].get("id").is_none(), "{:?}", trips[0]);
+    assert_eq!(trips[1]["id"], "seoul-korea-2022-09-10");
+}
+
+#[test]
+fn trips_are_only_held_to_the_photos_taken_on_them() {
+    let nominatim = MockNominatim::start();
+    let project = project(&nominatim);
+    project.replace_in_manifest(
+        "        end: 2021-08-07",
+        "        end: 2021-08-07\n  - name: K02\n    description: Seoul Korea 2021 again\n    legs:\n      - cities:\n          - Seoul\n        start: 2021-08-09\n        end: 2021-08-10",
+    );
+    project.add_photo_taken("2021/08/Korea/Seoul/a.png", "2021:08:01");
+    project.add_photo_taken("2021/08/Korea/Jeju/b.png", "2021:08:03");
+    project.add_photo_taken("2021/08/Korea/Busan/c.png", "2021:08:06");
+    project.add_photo_taken("2021/08/Korea/Seoul/d.png", "2021:08:09");
+    project.add_photo_taken("2021/08/Korea/Jeju/e.png", "2021:08:10");
+    project.add_photo_taken("2021/08/Korea/Seoul/f.png", "2021:08:12");
+
+    let (trips, stderr) = suggest(&project);
+    assert!(trips.is_empty(), "{:?}", trips);
+    assert!(
+        !stderr.contains("seoul-jeju-busan-korea-2021:"),
+        "{}",
+        stderr
+    );
+    assert!(
+        stderr
+            .contains("seoul-korea-2021-again: has photos from Jeju but the trip doesn't visit it"),
+        "{}",
+        stderr
+    );
+    assert!(
+        stderr.contains(
+            "seoul-korea-2021-again: photos run from 2021-08-09 to 2021-08-12 but the trip is from 2021-08-09 to 2021-08-10"
+        ),
+        "{}",
+        stderr
+    );
+    assert!(
+        !stderr.contains("visits Seoul but has no photos"),
+        "{}",
+        stderr
+    );
+}
+
+#[test]
+fn trips_without_photos_or_cities_are_reported() {
+    let nominatim = MockNominatim::start();
+    let project = project(&nominatim);
+    project.add_photo_taken("2021/08/Korea/Seoul/a.png", "2021:08:01");
+    project.add_photo_taken("2021/08/Korea/Busan/b.png", "2021:08:06");
+
+    let (_, stderr) = suggest(&project);
+    assert!(
+        stderr.contains("seoul-jeju-busan-korea-2021: visits Jeju but has no photos from it"),
+        "{}",
+        stderr
+    );
+
+    let project = Project::new("manifest.yaml", &nominatim);
+    project.add_photo_taken("2022/05/Korea/Seoul/a.png", "2022:05:01");
+    let (_, stderr) = suggest(&project);
+    assert!(
+        stderr.contains("seoul-jeju-busan-korea-2021: no photos were taken during this trip"),
+        "{}",
+        stderr
+    );
+}
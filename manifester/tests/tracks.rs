@@ -0,0 +1,112 @@
+//! Recorded tracks, simplified and drawn as the path of a trip.
+
+mod common;
+
+use std::fs;
+
+use common::{assert_position, feature, MockNominatim, Project};
+use manifester::{simplify, Position, TrackPoint};
+
+fn track(positions: &[Position]) -> Vec<TrackPoint> {
+    positions
+        .iter()
+        .map(|&position| TrackPoint {
+            position,
+            elevation: None,
+        })
+        .collect()
+}
+
+#[test]
+fn simplifying_keeps_the_points_further_than_the_tolerance() {
+    // Along the equator a thousandth of a degree is about 111 m. The peak is 56 m off the line
+    // between the ends, and the points either side of it stray about a metre from the lines to it.
+    let points = track(&[
+        (0.0, 0.0),
+        (0.001, 0.00026),
+        (0.002, 0.0005),
+        (0.003, 0.00024),
+        (0.004, 0.0),
+    ]);
+
+    let positions = points.iter().map(|p| p.position).collect::<Vec<_>>();
+    assert_eq!(simplify(&points, 0.5), positions);
+    assert_eq!(
+        simplify(&points, 10.0),
+        [(0.0, 0.0), (0.002, 0.0005), (0.004, 0.0)]
+    );
+    assert_eq!(simplify(&points, 100.0), [(0.0, 0.0), (0.004, 0.0)]);
+}
+
+#[test]
+fn short_tracks_are_left_as_they_are() {
+    let points = track(&[(126.9, 37.5), (126.9, 37.5)]);
+    assert_eq!(simplify(&points, 1000.0), [(126.9, 37.5), (126.9, 37.5)]);
+    assert!(simplify(&[], 1000.0).is_empty());
+}
+
+#[test]
+fn tracks_are_found_relative_to_the_repository() {
+    let nominatim = MockNominatim::start();
+    let project = Project::new("manifest.yaml", &nominatim);
+    fs::create_dir(project.path("tracks")).unwrap();
+    fs::write(
+        project.path("tracks/seoul.gpx"),
+        r#"<gpx><trk><trkseg><trkpt lat="37.50" lon="126.90"/><trkpt lat="37.55" lon="126.95"/></trkseg></trk></gpx>"#,
+    )
+    .unwrap();
+    project.replace_in_manifest(
+        "        start: 2021-08-01",
+        "        track: tracks/seoul.gpx\n        start: 2021-08-01",
+    );
+
+    let output = project.run(&[]);
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+
+    let trips = project.read_json("manifester/trips.json");
+    let geometry = &feature(&trips, "seoul-jeju-busan-korea-2021")["geometry"];
+    assert_position(&geometry["coordinates"][0], [126.90, 37.50]);
+    assert_position(&geometry["coordinates"][1], [126.95, 37.55]);
+}
+
+#[test]
+fn track_segments_are_drawn_apart() {
+    let nominatim = MockNominatim::start();
+    let project = Project::new("manifest.yaml", &nominatim);
+    let track = project.path("seoul.gpx");
+    fs::write(
+        &track,
+        r#"<gpx><trk>
+<trkseg><trkpt lat="37.50" lon="126.90"/><trkpt lat="37.55" lon="126.95"/></trkseg>
+<trkseg><trkpt lat="37.60" lon="127.05"/><trkpt lat="37.65" lon="127.10"/></trkseg>
+</trk></gpx>"#,
+    )
+    .unwrap();
+    project.replace_in_manifest(
+        "        start: 2021-08-01",
+        &format!(
+            "        track: {}\n        start: 2021-08-01",
+            track.display()
+        ),
+    );
+
+    let output = project.run(&[]);
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+
+    let trips = project.read_json("manifester/trips.json");
+    let geometry = &feature(&trips, "seoul-jeju-busan-korea-2021")["geometry"];
+    assert_eq!(geometry["type"], "MultiLineString");
+    let parts = geometry["coordinates"].as_array().unwrap();
+    let first = parts[0].as_array().unwrap();
+    assert_eq!(first.len(), 2, "{}", geometry);
+    assert_position(&first[1], [126.95, 37.55]);
+    assert_position(&parts[1][0], [127.05, 37.60]);
+}
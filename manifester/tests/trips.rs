@@ -0,0 +1,207 @@
+//! Trips and the places they visit, as manifest.yaml describes them and as they are built.
+
+mod common;
+
+use std::fs;
+
+use common::{assert_position, feature, MockNominatim, Project};
+use serde_json::json;
+
+#[test]
+fn trip_ids_taking_names_manifest_elm_uses_are_rejected() {
+    let nominatim = MockNominatim::start();
+    for (id, constructor) in [("may", "May"), ("just", "Just"), ("leg", "Leg")] {
+        let project = Project::new("manifest.yaml", &nominatim);
+        project.replace_in_manifest(
+            "    description: Seoul Jeju Busan Korea 2021",
+            &format!(
+                "    id: {}\n    description: Seoul Jeju Busan Korea 2021",
+                id
+            ),
+        );
+
+        let output = project.run(&["geocode"]);
+        assert!(!output.status.success());
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        assert!(
+            stderr.contains(&format!(
+                "becomes the Elm constructor {} which is already used by Manifest.elm itself",
+                constructor
+            )),
+            "{}",
+            stderr
+        );
+    }
+    assert!(nominatim.requests().is_empty());
+}
+
+#[test]
+fn region_names_taking_names_manifest_elm_uses_are_rejected() {
+    let nominatim = MockNominatim::start();
+    let project = Project::new("manifest.yaml", &nominatim);
+    project.replace_in_manifest("    Jeju: 제주", "    Image:\n      Jeju: 제주");
+
+    let output = project.run(&["geocode"]);
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("Region \"Image\" becomes the Elm constructor Image"),
+        "{}",
+        stderr
+    );
+}
+
+#[test]
+fn trips_follow_their_legs_and_count_their_photos() {
+    let nominatim = MockNominatim::start();
+    let project = Project::new("manifest.yaml", &nominatim);
+    project.add_photo("2021/08/Korea/Jeju/beach.png");
+    project.add_photo("2021/08/Korea/Busan/market.png");
+    // Named like the Jeju photo, but a photo of its own.
+    project.add_photo("2021/08/Korea/Busan/beach.png");
+    project.add_photo("2022/05/Korea/Jeju/later.png");
+
+    let output = project.run(&[]);
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+
+    let trips = project.read_json("manifester/trips.json");
+    let trip = feature(&trips, "seoul-jeju-busan-korea-2021");
+    let properties = &trip["properties"];
+    assert_eq!(properties["name"], "K01");
+    assert_eq!(properties["start"], "2021-08-01");
+    assert_eq!(properties["end"], "2021-08-07");
+    assert_eq!(properties["cities"], 3);
+    assert_eq!(properties["countries"], 1);
+    assert_eq!(properties["days"], 7);
+    assert_eq!(properties["photos"], 3);
+    assert_eq!(properties["colour"], "#42d4f4");
+
+    // Flights follow great circles, so the path runs through more than the three cities.
+    let geometry = &trip["geometry"];
+    assert_eq!(geometry["type"], "LineString");
+    let path = geometry["coordinates"].as_array().unwrap();
+    assert!(path.len() > 3);
+    assert_position(path.first().unwrap(), [126.97829, 37.56668]);
+    assert_position(path.last().unwrap(), [129.07524, 35.179955]);
+
+    let cities = project.read_json("manifester/cities.json");
+    let jeju = &feature(&cities, "kor-jeju")["properties"];
+    assert_eq!(jeju["photos"], 2);
+    assert_eq!(jeju["trips"], json!(["seoul-jeju-busan-korea-2021"]));
+
+    let elm = fs::read_to_string(project.path("src/Manifest.elm")).unwrap();
+    // The photo taken after the trip counts in the lifetime total too.
+    let lifetime = lifetime_statistics(&elm);
+    assert!(lifetime.ends_with(" 7 4"), "{}", lifetime);
+
+    let images = elm
+        .lines()
+        .skip_while(|line| !line.starts_with("tripImages trip ="))
+        .skip_while(|line| line.trim() != "SeoulJejuBusanKorea2021 ->")
+        .nth(1)
+        .unwrap();
+    assert!(
+        images.contains("\"2021/08/Korea/Jeju/beach.png\""),
+        "{}",
+        images
+    );
+    assert!(
+        images.contains("\"2021/08/Korea/Busan/beach.png\""),
+        "{}",
+        images
+    );
+}
+
+/// The `Statistics trips distance countries cities days photos` of every trip in Manifest.elm.
+fn lifetime_statistics(elm: &str) -> String {
+    elm.lines()
+        .skip_while(|line| !line.starts_with("lifetimeStatistics ="))
+        .nth(1)
+        .unwrap()
+        .trim()
+        .to_string()
+}
+
+#[test]
+fn overlapping_trips_count_their_shared_days_once() {
+    let nominatim = MockNominatim::start();
+    let project = Project::new("manifest.yaml", &nominatim);
+    project.replace_in_manifest(
+        "        end: 2021-08-07",
+        "        end: 2021-08-07\n  - name: K02\n    description: Busan Seoul Korea 2021\n    legs:\n      - cities:\n          - Busan\n        start: 2021-08-06\n        end: 2021-08-08\n      - cities:\n          - Seoul\n        start: 2021-08-08\n        end: 2021-08-09\n  - name: K03\n    description: Seoul Korea 2021\n    legs:\n      - cities:\n          - Seoul\n        start: 2021-08-20\n        end: 2021-08-21",
+    );
+
+    let output = project.run(&[]);
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+
+    // 1 to 9 August and 20 to 21 August.
+    let elm = fs::read_to_string(project.path("src/Manifest.elm")).unwrap();
+    let lifetime = lifetime_statistics(&elm);
+    assert!(lifetime.starts_with("Statistics 3 "), "{}", lifetime);
+    assert!(lifetime.ends_with(" 1 3 11 0"), "{}", lifetime);
+}
+
+#[test]
+fn unreadable_trip_sidecars_fail_the_build() {
+    let nominatim = MockNominatim::start();
+    let project = Project::new("manifest.yaml", &nominatim);
+    project.add_photo("2021/08/Korea/Jeju/beach.png");
+    fs::create_dir(project.path("dist/gallery/2021/08/Korea/Jeju/beach.trip")).unwrap();
+
+    let output = project.run(&[]);
+    assert!(!output.status.success());
+    assert!(
+        String::from_utf8_lossy(&output.stderr).contains("beach.trip"),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+}
+
+#[test]
+fn cities_without_a_longitude_and_latitude_fail_the_build() {
+    let nominatim = MockNominatim::start();
+    let project = Project::new("manifest.yaml", &nominatim);
+    assert!(project.run(&[]).status.success());
+
+    let mut cities = project.read_json("manifester/cities.json");
+    for city in cities["features"].as_array_mut().unwrap() {
+        if city["properties"]["id"] == "kor-jeju" {
+            city["geometry"]["coordinates"] = json!([126.5]);
+        }
+    }
+    project.write_json("manifester/cities.json", &cities);
+
+    let output = project.run(&[]);
+    assert!(!output.status.success());
+    assert!(
+        String::from_utf8_lossy(&output.stderr).contains("Jeju has a Point with 1 coordinates"),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+}
+
+#[test]
+fn pinned_region_names_fail_the_build() {
+    let nominatim = MockNominatim::start();
+    let project = Project::new("manifest.yaml", &nominatim);
+    project.replace_in_manifest(
+        "    Jeju: 제주",
+        "    Jeju-do:\n      Local:\n        local: 제주도\n        coordinates: [126.5, 33.4]\n      Jeju: 제주",
+    );
+
+    let output = project.run(&[]);
+    assert!(!output.status.success());
+    assert!(
+        String::from_utf8_lossy(&output.stderr).contains("Local of region Jeju-do"),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+}
@@ -0,0 +1,160 @@
+//! Rebuilding when the gallery or the manifest change, and only then.
+
+mod common;
+
+use std::fs;
+use std::io::{BufRead, BufReader};
+use std::process::Child;
+use std::sync::mpsc::{self, Receiver};
+use std::thread;
+use std::time::Duration;
+
+use common::{MockNominatim, Project};
+
+/// Long enough for a rebuild of the few photos a test files to finish.
+const TIMEOUT: Duration = Duration::from_secs(20);
+
+/// A `watch` run and the lines it printed that haven't been looked at yet.
+struct Watch {
+    child: Child,
+    lines: Receiver<String>,
+}
+
+impl Watch {
+    fn start(project: &Project) -> Self {
+        let mut child = project.spawn(&["watch", "--debounce", "100"]);
+        let stdout = BufReader::new(child.stdout.take().unwrap());
+        let (tx, lines) = mpsc::channel();
+        thread::spawn(move || {
+            for line in stdout.lines() {
+                if tx.send(line.unwrap()).is_err() {
+                    break;
+                }
+            }
+        });
+
+        let mut watch = Watch { child, lines };
+        watch.build();
+        // Built and watching.
+        watch.line();
+        watch
+    }
+
+    fn line(&mut self) -> String {
+        match self.lines.recv_timeout(TIMEOUT) {
+            Ok(line) => line,
+            Err(_) => {
+                let _ = self.child.kill();
+                let mut stderr = String::new();
+                let _ =
+                    std::io::Read::read_to_string(self.child.stderr.as_mut().unwrap(), &mut stderr);
+                panic!("watch printed nothing more: {}", stderr)
+            }
+        }
+    }
+
+    /// The changes the next build listed, added `+`, removed `-`, modified `~` and sidecars `*`.
+    fn build(&mut self) -> Vec<String> {
+        let mut changes = Vec::new();
+        loop {
+            let line = self.line();
+            if line.starts_with("Built in") || line.starts_with("Rebuilt in") {
+                return changes;
+            }
+            if ["+ ", "- ", "~ ", "* "]
+                .iter()
+                .any(|mark| line.starts_with(mark))
+            {
+                changes.push(line);
+            }
+        }
+    }
+
+    /// Checks nothing else was rebuilt, such as for what the last build wrote itself.
+    fn settled(&mut self) {
+        if let Ok(line) = self.lines.recv_timeout(Duration::from_secs(1)) {
+            panic!("watch went on with {:?}", line);
+        }
+    }
+}
+
+impl Drop for Watch {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+#[test]
+fn photos_and_their_descriptions_are_rebuilt_but_not_what_the_build_writes() {
+    let nominatim = MockNominatim::start();
+    let project = Project::new("manifest.yaml", &nominatim);
+    project.add_photo("2021/08/Korea/Jeju/beach.png");
+    let mut watch = Watch::start(&project);
+    watch.settled();
+
+    project.add_photo("2021/08/Korea/Busan/market.png");
+    assert_eq!(watch.build(), ["+ 2021/08/Korea/Busan/market.png"]);
+    // The description, thumbnail and blur the rebuild made are its own.
+    assert!(project
+        .path("dist/gallery/2021/08/Korea/Busan/market.desc")
+        .exists());
+    assert!(project
+        .path("dist/gallery/2021/08/Korea/Busan/market_small.png")
+        .exists());
+    watch.settled();
+
+    fs::write(
+        project.path("dist/gallery/2021/08/Korea/Busan/market.desc"),
+        "Fish for sale",
+    )
+    .unwrap();
+    assert_eq!(watch.build(), ["* 2021/08/Korea/Busan/market.desc"]);
+    watch.settled();
+}
+
+#[test]
+fn removed_directories_take_their_photos_with_them() {
+    let nominatim = MockNominatim::start();
+    let project = Project::new("manifest.yaml", &nominatim);
+    project.add_photo("2021/08/Korea/Jeju/beach.png");
+    project.add_photo("2021/08/Korea/Jeju/harbour.png");
+    project.add_photo("2021/08/Korea/Busan/market.png");
+    let mut watch = Watch::start(&project);
+
+    fs::remove_dir_all(project.path("dist/gallery/2021/08/Korea/Jeju")).unwrap();
+    assert_eq!(
+        watch.build(),
+        [
+            "- 2021/08/Korea/Jeju/beach.png",
+            "- 2021/08/Korea/Jeju/harbour.png"
+        ]
+    );
+
+    let cities = project.read_json("manifester/cities.json");
+    let jeju = &common::feature(&cities, "kor-jeju")["properties"];
+    assert_eq!(jeju["photos"], 0);
+    let busan = &common::feature(&cities, "kor-busan")["properties"];
+    assert_eq!(busan["photos"], 1);
+}
+
+#[test]
+fn manifest_changes_are_read_again() {
+    let nominatim = MockNominatim::start();
+    let project = Project::new("manifest.yaml", &nominatim);
+    project.add_photo("2021/08/Korea/Jeju/beach.png");
+    let mut watch = Watch::start(&project);
+
+    project.replace_in_manifest("    Seoul: 서울", "    Seoul: 서울특별시");
+    let lines = watch.build();
+    assert_eq!(lines.len(), 1, "{:?}", lines);
+    assert!(
+        lines[0].starts_with("~ ") && lines[0].ends_with("manifest.yaml"),
+        "{:?}",
+        lines
+    );
+
+    let cities = project.read_json("manifester/cities.json");
+    let seoul = &common::feature(&cities, "kor-seoul")["properties"];
+    assert_eq!(seoul["localname"], "서울특별시");
+}
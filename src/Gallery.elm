@@ -1,4 +1,4 @@
-module Gallery exposing (Filter(..), blurURL, countryNames, displayURL, filterImages, imageURL, locale, locationCoordinates, locationNames, sortImages, thumbURL, tripId, tripNames)
+module Gallery exposing (Filter(..), blurURL, countryNames, displayURL, filterImages, idToLocation, imageURL, locale, locationCoordinates, locationNames, sortImages, thumbURL, tripNames)
 
 import List.Extra exposing (unconsLast)
 import Manifest exposing (Country(..), Date, Image, Location(..), Month(..), Trip(..), Year)
@@ -15,14 +15,20 @@ imagePath image =
     let
         info =
             Manifest.locationInformation image.location
+
+        region =
+            Manifest.locationRegion image.location
+                |> Maybe.map (\r -> [ Manifest.regionName r |> String.replace " " "_" ])
+                |> Maybe.withDefault []
     in
-    String.join "/"
+    String.join "/" <|
         [ "/gallery"
         , String.fromInt image.date.year
         , monthToDirectory image.date.month
         , Manifest.countryName info.country |> String.replace " " "_"
-        , String.replace " " "_" info.name
         ]
+            ++ region
+            ++ [ String.replace " " "_" info.name ]
 
 
 imageURL : Image -> String
@@ -82,9 +88,29 @@ countryNames =
     List.map Manifest.countryName Manifest.countryList
 
 
-locationNames : List String
+{-| Locations as they are offered in the filter menu, by id with their name. Cities that share a name
+are told apart by their country.
+-}
+locationNames : List ( String, String )
 locationNames =
-    List.map (\location -> Manifest.locationInformation location |> .name) Manifest.locationList
+    let
+        name location =
+            Manifest.locationInformation location |> .name
+
+        label location =
+            if List.length (List.filter (\other -> name other == name location) Manifest.locationList) > 1 then
+                name location ++ " (" ++ (Manifest.locationInformation location |> .country |> Manifest.countryName) ++ ")"
+
+            else
+                name location
+    in
+    List.map (\location -> ( Manifest.locationId location, label location )) Manifest.locationList
+
+
+idToLocation : String -> Maybe Location
+idToLocation id =
+    List.filter (\location -> Manifest.locationId location == id) Manifest.locationList
+        |> List.head
 
 
 locationCoordinates : Location -> ( Float, Float )
@@ -111,18 +137,18 @@ locale image =
         Just localCountry ->
             case Manifest.locationLocalName image.location of
                 Just localLocation ->
-                    ( localLocation ++ ", " ++ localCountry ++ " (" ++ info.name ++ ", " ++ countryString ++ "); " ++ date ++ ".", info.name, url )
+                    ( localLocation ++ ", " ++ localCountry ++ " (" ++ info.name ++ ", " ++ countryString ++ "); " ++ date ++ ".", Manifest.locationId image.location, url )
 
                 Nothing ->
-                    ( info.name ++ ", " ++ localCountry ++ " (" ++ countryString ++ "); " ++ date ++ ".", info.name, url )
+                    ( info.name ++ ", " ++ localCountry ++ " (" ++ countryString ++ "); " ++ date ++ ".", Manifest.locationId image.location, url )
 
         Nothing ->
             case Manifest.locationLocalName image.location of
                 Just localLocation ->
-                    ( localLocation ++ " (" ++ info.name ++ "), " ++ countryString ++ "; " ++ date ++ ".", info.name, url )
+                    ( localLocation ++ " (" ++ info.name ++ "), " ++ countryString ++ "; " ++ date ++ ".", Manifest.locationId image.location, url )
 
                 Nothing ->
-                    ( info.name ++ ", " ++ countryString ++ "; " ++ date ++ ".", info.name, url )
+                    ( info.name ++ ", " ++ countryString ++ "; " ++ date ++ ".", Manifest.locationId image.location, url )
 
 
 dateString : Date -> String
@@ -169,17 +195,14 @@ dateString date =
     monthString ++ String.fromInt date.year
 
 
-tripNames : List String
+{-| Trips as they are offered in the filter menu, by id with their description, newest first.
+-}
+tripNames : List ( String, String )
 tripNames =
-    List.map (\trip -> Manifest.tripInformation trip |> .description) Manifest.tripList
+    List.map (\trip -> ( Manifest.tripId trip, Manifest.tripInformation trip |> .description )) Manifest.tripList
         |> List.reverse
 
 
-tripId : Trip -> String
-tripId trip =
-    Manifest.tripInformation trip |> .name
-
-
 {-| Generate a directory string for our month, padding the int to a width of 2.
 -}
 monthToDirectory : Month -> String
@@ -298,10 +321,5 @@ byLocation location image =
 
 byTrip : Trip -> Image -> Bool
 byTrip trip image =
-    let
-        tripInfo =
-            Manifest.tripInformation trip
-    in
-    -- locations are not unique, but these lists are small, so it's better to traverse and double count rather than
-    -- find unique values and then test.
-    List.any (\d -> d == image.date) tripInfo.dates && List.any (\l -> l == image.location) tripInfo.locations
+    -- Trip membership is worked out by manifester from trip legs and .trip sidecars.
+    List.member trip image.trips
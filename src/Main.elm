@@ -176,9 +176,9 @@ routeParser =
     Parser.oneOf
         [ mapRoute Parser.top RouteAll
         , mapRoute (Parser.s "license") RouteLicense
-        , mapRoute (Parser.s "trip" </> Parser.string) (\trip -> RouteTrip (sanitise trip |> Manifest.stringToTrip))
+        , mapRoute (Parser.s "trip" </> Parser.string) (\trip -> RouteTrip (Manifest.stringToTrip trip))
         , mapRoute Parser.string (\country -> RouteCountry (sanitise country |> Manifest.stringToCountry))
-        , mapRoute (Parser.string </> Parser.string) (\_ location -> RouteLocation (sanitise location |> Manifest.stringToLocation))
+        , mapRoute (Parser.string </> Parser.string) (\country location -> RouteLocation (sanitise country |> Manifest.stringToCountry |> Maybe.andThen (\c -> Manifest.countryLocation c (sanitise location))))
         ]
 
 
@@ -231,7 +231,7 @@ routeModel route model =
                         info =
                             Manifest.locationInformation location
                     in
-                    ( { newModel | filter = ByLocation location, filterSelected = ( RadioLocation, info.name ) }, [ Ports.initMap ( 3, info.name |> String.replace " " "_", [ negate <| Tuple.first info.coordinates, negate <| Tuple.second info.coordinates ] ), clearQuery ] )
+                    ( { newModel | filter = ByLocation location, filterSelected = ( RadioLocation, Manifest.locationId location ) }, [ Ports.initMap ( 3, info.name |> String.replace " " "_", [ negate <| Tuple.first info.coordinates, negate <| Tuple.second info.coordinates ] ), clearQuery ] )
 
                 Nothing ->
                     resetRoute model
@@ -239,11 +239,7 @@ routeModel route model =
         RouteTrip maybeTrip ->
             case maybeTrip of
                 Just trip ->
-                    let
-                        info =
-                            Manifest.tripInformation trip
-                    in
-                    ( { newModel | filter = ByTrip trip, filterSelected = ( RadioTrip, info.description ) }, [ Ports.initMap ( 4, info.name |> String.replace " " "_", [] ), clearQuery ] )
+                    ( { newModel | filter = ByTrip trip, filterSelected = ( RadioTrip, Manifest.tripId trip ) }, [ Ports.initMap ( 4, Manifest.tripId trip, [] ), clearQuery ] )
 
                 Nothing ->
                     resetRoute model
@@ -578,19 +574,19 @@ update msg model =
                 path =
                     case radio of
                         RadioLocation ->
-                            let
-                                country =
-                                    case Manifest.stringToLocation selection of
-                                        Just location ->
-                                            "/" ++ (Manifest.locationInformation location |> (\info -> Manifest.countryName info.country)) ++ "/"
+                            case Gallery.idToLocation selection of
+                                Just location ->
+                                    let
+                                        info =
+                                            Manifest.locationInformation location
+                                    in
+                                    "/" ++ Manifest.countryName info.country ++ "/" ++ info.name |> String.replace " " "_"
 
-                                        Nothing ->
-                                            "/"
-                            in
-                            country ++ selection |> String.replace " " "_"
+                                Nothing ->
+                                    "/"
 
                         RadioTrip ->
-                            "/trip/" ++ (String.replace " " "_" selection |> String.replace "/" "-")
+                            "/trip/" ++ selection
 
                         _ ->
                             "/" ++ String.replace " " "_" selection
@@ -753,10 +749,10 @@ update msg model =
                                                         ( { model | url = url }, Cmd.none )
 
                                                     else
-                                                        doUpdate (ByLocation newLocation) RadioLocation (Manifest.locationInformation newLocation |> .name) model
+                                                        doUpdate (ByLocation newLocation) RadioLocation (Manifest.locationId newLocation) model
 
                                                 _ ->
-                                                    doUpdate (ByLocation newLocation) RadioLocation (Manifest.locationInformation newLocation |> .name) model
+                                                    doUpdate (ByLocation newLocation) RadioLocation (Manifest.locationId newLocation) model
 
                                         Nothing ->
                                             ( { model | url = url }, Cmd.none )
@@ -770,10 +766,10 @@ update msg model =
                                                         ( { model | url = url }, Cmd.none )
 
                                                     else
-                                                        doUpdate (ByTrip newTrip) RadioTrip (Manifest.tripInformation newTrip |> .description) model
+                                                        doUpdate (ByTrip newTrip) RadioTrip (Manifest.tripId newTrip) model
 
                                                 _ ->
-                                                    doUpdate (ByTrip newTrip) RadioTrip (Manifest.tripInformation newTrip |> .description) model
+                                                    doUpdate (ByTrip newTrip) RadioTrip (Manifest.tripId newTrip) model
 
                                         Nothing ->
                                             ( { model | url = url }, Cmd.none )
@@ -1276,7 +1272,7 @@ filterMenu ( radio, selected ) =
                     ( "visible", Gallery.locationNames, "Location" )
 
                 RadioCountry ->
-                    ( "visible", Gallery.countryNames, "Country" )
+                    ( "visible", List.map (\country -> ( country, country )) Gallery.countryNames, "Country" )
 
                 RadioTrip ->
                     ( "visible", Gallery.tripNames, "Trip" )
@@ -1284,8 +1280,8 @@ filterMenu ( radio, selected ) =
     Html.select [ Html.Events.onInput SetSelection, Html.Attributes.class visible ] <|
         Html.option [ Html.Attributes.hidden True, Html.Attributes.selected (selected == "") ] [ Html.text ("— Select a " ++ name ++ " —") ]
             :: List.map
-                (\label ->
-                    Html.option [ Html.Attributes.selected (label == selected) ]
+                (\( value, label ) ->
+                    Html.option [ Html.Attributes.value value, Html.Attributes.selected (value == selected) ]
                         [ Html.text label ]
                 )
                 list
@@ -1306,7 +1302,7 @@ newFilter ( radio, selected ) current =
                     current
 
         RadioLocation ->
-            case Manifest.stringToLocation selected of
+            case Gallery.idToLocation selected of
                 Just location ->
                     ByLocation location
 
@@ -1394,7 +1390,7 @@ updateMap radio selected clearPrevious =
         RadioTrip ->
             case Manifest.stringToTrip selected of
                 Just trip ->
-                    Ports.viewTrip (Gallery.tripId trip)
+                    Ports.viewTrip (Manifest.tripId trip)
 
                 _ ->
                     Cmd.none
@@ -1408,13 +1404,13 @@ updateMap radio selected clearPrevious =
                     else
                         Ports.showLocation
             in
-            case Manifest.stringToLocation selected of
+            case Gallery.idToLocation selected of
                 Just location ->
                     let
                         coordinates =
                             Gallery.locationCoordinates location
                     in
-                    port_ ( String.replace " " "_" selected, [ negate <| Tuple.first coordinates, negate <| Tuple.second coordinates ] )
+                    port_ ( Manifest.locationInformation location |> .name |> String.replace " " "_", [ negate <| Tuple.first coordinates, negate <| Tuple.second coordinates ] )
 
                 Nothing ->
                     port_ ( "", [] )